@@ -0,0 +1,334 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::future::{LocalBoxFuture, Shared};
+use futures::FutureExt;
+
+use crate::asset_source::{AssetError, AssetSource, AssetSources};
+use crate::hash::content_hash;
+use crate::image_loader::LoadProgress;
+
+// Layout, all numbers little endian:
+//   magic "MRPK", version u32, entry count u32, index size u32
+//   index entries: path length u16, path, compression u8, offset u64, stored size u64, size u64, hash u64
+//   entry data, back to back in index order
+// The index comes first and offsets are absolute, so a reader can fetch the header, the index,
+// then any entry by a byte range. Entries are sorted by path, files of a directory are contiguous
+const MAGIC: [u8; 4] = *b"MRPK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+
+// A pack served next to the web build with the assets of one demo, so they arrive in one download
+pub struct WebPack {
+   pub path: &'static str,
+   // relative to the web root, a request under them downloads the pack
+   pub directories: &'static [&'static str],
+}
+
+// built by `asset_pack --web`, files missing from them are fetched one by one
+pub const WEB_PACKS: &[WebPack] = &[
+   WebPack { path: "assets/packs/mesh.pack", directories: &["assets/materials/leather", "assets/environment"] },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+   Stored = 0,
+   Deflate = 1,
+}
+
+pub struct PackEntry {
+   pub compression: Compression,
+   pub offset: u64,
+   pub stored_size: u64,
+   pub size: u64,
+   pub hash: u64,
+}
+
+pub struct AssetPack {
+   bytes: Vec<u8>,
+   entries: HashMap<String, PackEntry>,
+}
+
+impl AssetPack {
+   pub fn parse(bytes: Vec<u8>) -> Result<Self, String> {
+      let entries = Self::parse_index(&bytes)?;
+      for (path, entry) in &entries {
+         if entry.range().end > bytes.len() {
+            return Err(format!("entry {path} is out of the pack"));
+         }
+      }
+      Ok(Self { bytes, entries })
+   }
+
+   // `bytes` has to contain the header and the index, entry data isn't needed
+   pub fn parse_index(bytes: &[u8]) -> Result<HashMap<String, PackEntry>, String> {
+      if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+         return Err("not an asset pack".to_owned());
+      }
+      let mut reader = Reader { bytes, offset: 4 };
+      let version = reader.u32()?;
+      if version != VERSION {
+         return Err(format!("unsupported asset pack version {version}"));
+      }
+      let entry_count = reader.u32()?;
+      let index_size = reader.u32()? as usize;
+      if bytes.len() < HEADER_SIZE + index_size {
+         return Err("asset pack index is truncated".to_owned());
+      }
+      let mut entries = HashMap::with_capacity(entry_count as usize);
+      for _ in 0..entry_count {
+         let path_length = reader.u16()? as usize;
+         let path = String::from_utf8(reader.take(path_length)?.to_vec()).map_err(|e| e.to_string())?;
+         let compression = match reader.take(1)?[0] {
+            0 => Compression::Stored,
+            1 => Compression::Deflate,
+            compression => return Err(format!("entry {path} has unknown compression {compression}")),
+         };
+         let entry = PackEntry {
+            compression,
+            offset: reader.u64()?,
+            stored_size: reader.u64()?,
+            size: reader.u64()?,
+            hash: reader.u64()?,
+         };
+         // so `range` can't overflow, even on 32 bit targets
+         let end = entry.offset.checked_add(entry.stored_size).and_then(|end| usize::try_from(end).ok());
+         if end.is_none() {
+            return Err(format!("entry {path} is out of the addressable range"));
+         }
+         entries.insert(path, entry);
+      }
+      Ok(entries)
+   }
+
+   pub fn entries(&self) -> &HashMap<String, PackEntry> {
+      &self.entries
+   }
+
+   // decompressed entry, checked against its content hash
+   pub fn read(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      let entry = self.entries.get(path)?;
+      let stored = &self.bytes[entry.range()];
+      let data = match entry.compression {
+         Compression::Stored => Ok(stored.to_vec()),
+         Compression::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(stored, entry.size as usize)
+            .map_err(|e| format!("failed to inflate: {e}")),
+      };
+      let data = data.and_then(|data| match content_hash(&data) == entry.hash {
+         true => Ok(data),
+         false => Err("content hash mismatch".to_owned()),
+      });
+      Some(data.map_err(|reason| AssetError::DecodeFailed { path: path.to_owned(), reason }))
+   }
+}
+
+impl PackEntry {
+   // of the stored data in the pack, `parse_index` checked that it fits
+   pub fn range(&self) -> std::ops::Range<usize> {
+      self.offset as usize..(self.offset + self.stored_size) as usize
+   }
+}
+
+struct Reader<'a> {
+   bytes: &'a [u8],
+   offset: usize,
+}
+
+impl<'a> Reader<'a> {
+   fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+      let bytes = self.bytes.get(self.offset..self.offset + length)
+         .ok_or("asset pack index is truncated")?;
+      self.offset += length;
+      Ok(bytes)
+   }
+
+   fn u16(&mut self) -> Result<u16, String> {
+      Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+   }
+
+   fn u32(&mut self) -> Result<u32, String> {
+      Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+   }
+
+   fn u64(&mut self) -> Result<u64, String> {
+      Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+   }
+}
+
+// With `compress` entries are deflated, unless that doesn't make them smaller
+pub fn write_pack(mut files: Vec<(String, Vec<u8>)>, compress: bool) -> Vec<u8> {
+   files.sort_by(|(a, _), (b, _)| a.cmp(b));
+   let stored: Vec<(Compression, Vec<u8>)> = files.iter()
+      .map(|(_, data)| {
+         let deflated = compress.then(|| miniz_oxide::deflate::compress_to_vec(data, 9))
+            .filter(|deflated| deflated.len() < data.len());
+         match deflated {
+            Some(deflated) => (Compression::Deflate, deflated),
+            None => (Compression::Stored, data.clone()),
+         }
+      })
+      .collect();
+
+   let index_size: usize = files.iter().map(|(path, _)| 2 + path.len() + 1 + 4 * 8).sum();
+   let mut offset = (HEADER_SIZE + index_size) as u64;
+   let mut pack = Vec::with_capacity(offset as usize + stored.iter().map(|(_, data)| data.len()).sum::<usize>());
+   pack.extend_from_slice(&MAGIC);
+   pack.extend_from_slice(&VERSION.to_le_bytes());
+   pack.extend_from_slice(&(files.len() as u32).to_le_bytes());
+   pack.extend_from_slice(&(index_size as u32).to_le_bytes());
+   for ((path, data), (compression, stored_data)) in files.iter().zip(&stored) {
+      pack.extend_from_slice(&(path.len() as u16).to_le_bytes());
+      pack.extend_from_slice(path.as_bytes());
+      pack.push(*compression as u8);
+      for value in [offset, stored_data.len() as u64, data.len() as u64, content_hash(data)] {
+         pack.extend_from_slice(&value.to_le_bytes());
+      }
+      offset += stored_data.len() as u64;
+   }
+   for (_, stored_data) in &stored {
+      pack.extend_from_slice(stored_data);
+   }
+   pack
+}
+
+type PackLoading = Shared<LocalBoxFuture<'static, Option<Rc<AssetPack>>>>;
+
+// Entries of a pack which is read once through other sources, on the first request.
+// Without the pack every path is passed on to the next source
+pub struct PackSource {
+   pack_path: String,
+   // paths outside them are passed on without downloading the pack, every path when empty
+   directories: Vec<String>,
+   from: Rc<AssetSources>,
+   progress: Rc<LoadProgress>,
+   pack: RefCell<Option<PackLoading>>,
+}
+
+impl PackSource {
+   pub fn new(pack_path: &str, from: Rc<AssetSources>) -> Self {
+      Self {
+         pack_path: pack_path.to_owned(),
+         directories: vec![],
+         from,
+         progress: Default::default(),
+         pack: Default::default(),
+      }
+   }
+
+   pub fn with_directories(mut self, directories: &[&str]) -> Self {
+      self.directories = directories.iter().map(|directory| directory.trim_end_matches('/').to_owned()).collect();
+      self
+   }
+
+   fn covers(&self, path: &str) -> bool {
+      self.directories.is_empty() || self.directories.iter()
+         .any(|directory| path.strip_prefix(directory.as_str()).is_some_and(|rest| rest.starts_with('/')))
+   }
+
+   // every reader waits for the same download, and sees its progress
+   async fn pack(&self, progress: &LoadProgress) -> Option<Rc<AssetPack>> {
+      let mut pack = self.pack.borrow_mut()
+         .get_or_insert_with(|| {
+            let (pack_path, from, pack_progress) = (self.pack_path.clone(), self.from.clone(), self.progress.clone());
+            async move {
+               let pack = from.read(&pack_path, &pack_progress).await.map_err(|e| e.to_string());
+               match pack.and_then(AssetPack::parse) {
+                  Ok(pack) => Some(Rc::new(pack)),
+                  Err(e) => {
+                     log::warn!("Asset pack {pack_path} isn't used: {e}");
+                     None
+                  },
+               }
+            }.boxed_local().shared()
+         })
+         .clone();
+      futures::future::poll_fn(|cx| {
+         let poll = pack.poll_unpin(cx);
+         if poll.is_pending() {
+            progress.set_total_bytes(self.progress.total_bytes());
+            progress.set_loaded_bytes(self.progress.loaded_bytes());
+         }
+         poll
+      }).await
+   }
+}
+
+impl AssetSource for PackSource {
+   fn name(&self) -> String {
+      format!("pack '{}'", self.pack_path)
+   }
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      Box::pin(async move {
+         if !self.covers(path) {
+            return None;
+         }
+         let bytes = self.pack(progress).await?.read(path)?;
+         if let Ok(bytes) = &bytes {
+            progress.set_total_bytes(Some(bytes.len() as u64));
+            progress.set_loaded_bytes(bytes.len() as u64);
+         }
+         Some(bytes)
+      })
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use crate::asset_source::EmbeddedSource;
+
+   use super::*;
+
+   #[test]
+   fn pack_round_trip() {
+      let files = vec![
+         ("textures/b.png".to_owned(), vec![7; 1000]),
+         ("textures/a.png".to_owned(), b"incompressible".to_vec()),
+      ];
+      let pack = write_pack(files.clone(), true);
+      let index = AssetPack::parse_index(&pack).unwrap();
+      assert_eq!(index["textures/a.png"].compression, Compression::Stored);
+      assert_eq!(index["textures/b.png"].compression, Compression::Deflate);
+      // sorted by path, so the data of "a" comes first
+      assert!(index["textures/a.png"].offset < index["textures/b.png"].offset);
+
+      let sources = AssetSources::new()
+         .with_source(PackSource::new("assets.pack", Rc::new(AssetSources::new()
+            .with_source(EmbeddedSource::new().with_file("assets.pack", pack.clone())))))
+         .with_source(EmbeddedSource::new().with_file("textures/c.png", b"loose"));
+      let progress = LoadProgress::default();
+      let read = |path| futures::executor::block_on(sources.read(path, &progress));
+      for (path, data) in &files {
+         assert_eq!(&read(path).unwrap(), data);
+      }
+      assert_eq!(read("textures/c.png").unwrap(), b"loose");
+
+      let mut corrupted = pack.clone();
+      *corrupted.last_mut().unwrap() ^= 1;
+      let corrupted = AssetPack::parse(corrupted).unwrap();
+      assert!(matches!(corrupted.read("textures/b.png"), Some(Err(AssetError::DecodeFailed { .. }))));
+      assert!(AssetPack::parse(pack[..HEADER_SIZE + 4].to_vec()).is_err());
+      // an offset near u64::MAX, its end would wrap past the bounds check
+      let mut hostile = pack.clone();
+      let offset_at = HEADER_SIZE + 2 + "textures/a.png".len() + 1;
+      hostile[offset_at..offset_at + 8].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+      assert!(AssetPack::parse_index(&hostile).is_err());
+      assert!(AssetPack::parse(hostile).is_err());
+   }
+
+   #[test]
+   fn packs_are_downloaded_for_their_directories_only() {
+      let pack = write_pack(vec![("mesh/albedo.png".to_owned(), b"albedo".to_vec())], false);
+      let source = PackSource::new("mesh.pack", Rc::new(AssetSources::new()
+         .with_source(EmbeddedSource::new().with_file("mesh.pack", pack))))
+         .with_directories(&["mesh/"]);
+      let progress = LoadProgress::default();
+      // another demo's request, and a path which only starts like the directory
+      for path in ["fractal/albedo.png", "mesh_other/albedo.png"] {
+         assert!(futures::executor::block_on(source.read(path, &progress)).is_none());
+      }
+      assert!(source.pack.borrow().is_none());
+      assert_eq!(futures::executor::block_on(source.read("mesh/albedo.png", &progress)), Some(Ok(b"albedo".to_vec())));
+   }
+}
@@ -0,0 +1,72 @@
+// Bundles files under a root directory into one asset pack:
+//   asset_pack [--compress] <root> <output> [paths relative to root...]
+// Paths inside the pack are relative to the root, the whole root is packed when none are given.
+//   asset_pack [--compress] --web <root>
+// writes every pack of `WEB_PACKS` under the web root, one per demo
+
+use std::path::{Path, PathBuf};
+
+use my_renderer::asset_pack::{write_pack, WEB_PACKS};
+
+const USAGE: &str = "Usage: asset_pack [--compress] <root> <output> [paths relative to root...]\n       asset_pack [--compress] --web <root>";
+
+fn main() {
+   let mut args: Vec<String> = std::env::args().skip(1).collect();
+   let compress = args.iter().any(|arg| arg == "--compress");
+   let web = args.iter().any(|arg| arg == "--web");
+   args.retain(|arg| arg != "--compress" && arg != "--web");
+   if args.is_empty() || (!web && args.len() < 2) {
+      eprintln!("{USAGE}");
+      std::process::exit(1);
+   }
+   let root = PathBuf::from(&args[0]);
+   if web {
+      for pack in WEB_PACKS {
+         let output = root.join(pack.path);
+         if let Some(directory) = output.parent() {
+            std::fs::create_dir_all(directory)
+               .unwrap_or_else(|e| panic!("Failed to create {}: {e}", directory.display()));
+         }
+         let inputs = pack.directories.iter().map(|directory| root.join(directory)).collect();
+         pack_files(&root, &output, inputs, compress);
+      }
+      return;
+   }
+   let output = PathBuf::from(&args[1]);
+   let inputs: Vec<PathBuf> = match &args[2..] {
+      [] => vec![root.clone()],
+      paths => paths.iter().map(|path| root.join(path)).collect(),
+   };
+   pack_files(&root, &output, inputs, compress);
+}
+
+fn pack_files(root: &Path, output: &Path, inputs: Vec<PathBuf>, compress: bool) {
+   let mut files = vec![];
+   for input in inputs {
+      collect_files(root, &input, output, &mut files)
+         .unwrap_or_else(|e| panic!("Failed to read {}: {e}", input.display()));
+   }
+   let size: usize = files.iter().map(|(_, data)| data.len()).sum();
+   let count = files.len();
+   let pack = write_pack(files, compress);
+   std::fs::write(output, &pack)
+      .unwrap_or_else(|e| panic!("Failed to write {}: {e}", output.display()));
+   println!("Packed {count} files, {size} bytes into {} bytes: {}", pack.len(), output.display());
+}
+
+fn collect_files(root: &Path, path: &Path, output: &Path, files: &mut Vec<(String, Vec<u8>)>) -> std::io::Result<()> {
+   if path.is_dir() {
+      for entry in std::fs::read_dir(path)? {
+         collect_files(root, &entry?.path(), output, files)?;
+      }
+   } else if path != output {
+      let relative = path.strip_prefix(root).expect("Packed files have to be under the root");
+      // pack paths use forward slashes on every platform, same as URLs
+      let name = relative.components()
+         .map(|component| component.as_os_str().to_string_lossy())
+         .collect::<Vec<_>>()
+         .join("/");
+      files.push((name, std::fs::read(path)?));
+   }
+   Ok(())
+}
@@ -0,0 +1,592 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::future::LocalBoxFuture;
+
+use crate::image_loader::LoadProgress;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetError {
+   // none of the sources has the path
+   NotFound { path: String, sources: Vec<String> },
+   // a source has the path but fetching it failed
+   Network { path: String, reason: String },
+   // the bytes can't be read or decoded, e.g. an I/O error or a broken archive entry
+   DecodeFailed { path: String, reason: String },
+   UnsupportedFormat { path: String, reason: String },
+}
+
+impl AssetError {
+   pub fn path(&self) -> &str {
+      match self {
+         AssetError::NotFound { path, .. }
+         | AssetError::Network { path, .. }
+         | AssetError::DecodeFailed { path, .. }
+         | AssetError::UnsupportedFormat { path, .. } => path,
+      }
+   }
+}
+
+impl std::fmt::Display for AssetError {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self {
+         AssetError::NotFound { path, sources } => write!(f, "{path} not found in [{}]", sources.join(", ")),
+         AssetError::Network { path, reason } => write!(f, "failed to read {path}: {reason}"),
+         AssetError::DecodeFailed { path, reason } => write!(f, "failed to decode {path}: {reason}"),
+         AssetError::UnsupportedFormat { path, reason } => write!(f, "unsupported format of {path}: {reason}"),
+      }
+   }
+}
+
+// Somewhere asset bytes can be read from by a relative path like "textures/albedo.png".
+// `None` means the source doesn't have the path, so the next source is asked
+pub trait AssetSource {
+   fn name(&self) -> String;
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>>;
+
+   // for sources which don't need to wait, e.g. embedded data or files on native
+   fn read_now(&self, _path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      None
+   }
+
+   // file the data of `path` is read from, for sources backed by the local file system
+   fn local_path(&self, _path: &str) -> Option<std::path::PathBuf> {
+      None
+   }
+}
+
+// Sources composed in priority order, the first one having the path wins
+#[derive(Default)]
+pub struct AssetSources {
+   sources: Vec<Box<dyn AssetSource>>,
+}
+
+impl AssetSources {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   // lower priority than the sources added before
+   pub fn with_source(mut self, source: impl AssetSource + 'static) -> Self {
+      self.sources.push(Box::new(source));
+      self
+   }
+
+   // higher priority than every source added so far
+   pub fn with_first_source(mut self, source: impl AssetSource + 'static) -> Self {
+      self.sources.insert(0, Box::new(source));
+      self
+   }
+
+   // textures and other demo data, `www` is the web root
+   pub fn assets() -> Self {
+      cfg_if::cfg_if!{ if #[cfg(feature="web")] {
+         use crate::asset_pack::{PackSource, WEB_PACKS};
+         let fetch = std::rc::Rc::new(Self::new().with_source(FetchSource::new("")));
+         WEB_PACKS.iter()
+            .fold(Self::new(), |sources, pack| sources
+               .with_source(PackSource::new(pack.path, fetch.clone()).with_directories(pack.directories)))
+            .with_source(FetchSource::new(""))
+      } else {
+         Self::new()
+            .with_source(FsSource::new(FsSource::cwd().join("www")))
+            .with_source(FsSource::new(FsSource::exe_dir().join("www")))
+            .with_source(FsSource::new(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("www")))
+      }}
+   }
+
+   // shader sources, files on disk are preferred on native so edits are picked up
+   pub fn shaders(embedded: EmbeddedSource) -> Self {
+      cfg_if::cfg_if!{ if #[cfg(feature="web")] {
+         Self::new().with_source(embedded)
+      } else {
+         Self::new()
+            .with_source(FsSource::new(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join("renderer")))
+            .with_source(FsSource::new(FsSource::cwd().join("src").join("renderer")))
+            .with_source(embedded)
+      }}
+   }
+
+   pub async fn read(&self, path: &str, progress: &LoadProgress) -> Result<Vec<u8>, AssetError> {
+      for source in &self.sources {
+         if let Some(bytes) = source.read(path, progress).await {
+            return bytes.map_err(|e| Self::read_failed(source.as_ref(), e));
+         }
+      }
+      Err(self.not_found(path))
+   }
+
+   // only asks the sources which answer without waiting
+   pub fn read_now(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+      for source in &self.sources {
+         if let Some(bytes) = source.read_now(path) {
+            return bytes.map_err(|e| Self::read_failed(source.as_ref(), e));
+         }
+      }
+      Err(self.not_found(path))
+   }
+
+   // file of `path` in the first file system source which has it
+   pub fn local_path(&self, path: &str) -> Option<std::path::PathBuf> {
+      self.sources.iter().find_map(|source| source.local_path(path))
+   }
+
+   fn not_found(&self, path: &str) -> AssetError {
+      AssetError::NotFound {
+         path: path.to_owned(),
+         sources: self.sources.iter().map(|source| source.name()).collect(),
+      }
+   }
+
+   // keeps the kind of the error, the reason tells which source failed
+   fn read_failed(source: &dyn AssetSource, mut error: AssetError) -> AssetError {
+      if let AssetError::Network { reason, .. }
+         | AssetError::DecodeFailed { reason, .. }
+         | AssetError::UnsupportedFormat { reason, .. } = &mut error {
+         *reason = format!("{}: {reason}", source.name());
+      }
+      error
+   }
+}
+
+// Files compiled into the binary, or generated at startup
+#[derive(Default)]
+pub struct EmbeddedSource {
+   files: HashMap<String, Cow<'static, [u8]>>,
+}
+
+impl EmbeddedSource {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   // `include_bytes!` data is borrowed, generated files are owned
+   pub fn with_file(mut self, path: &str, bytes: impl Into<Cow<'static, [u8]>>) -> Self {
+      self.files.insert(path.to_owned(), bytes.into());
+      self
+   }
+
+   pub fn with_text(mut self, path: &str, text: String) -> Self {
+      self.files.insert(path.to_owned(), Cow::Owned(text.into_bytes()));
+      self
+   }
+}
+
+impl AssetSource for EmbeddedSource {
+   fn name(&self) -> String {
+      "embedded".to_owned()
+   }
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      let bytes = self.read_now(path);
+      if let Some(Ok(bytes)) = &bytes {
+         progress.set_total_bytes(Some(bytes.len() as u64));
+         progress.add_loaded_bytes(bytes.len() as u64);
+      }
+      Box::pin(futures::future::ready(bytes))
+   }
+
+   fn read_now(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      self.files.get(path).map(|bytes| Ok(bytes.to_vec()))
+   }
+}
+
+// Files put in at runtime, e.g. read ahead through async sources for synchronous readers.
+// Clones share the files
+#[derive(Clone, Default)]
+pub struct MemorySource {
+   files: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemorySource {
+   // false if the file already had these bytes
+   pub fn insert(&self, path: &str, bytes: Vec<u8>) -> bool {
+      let previous = self.files.borrow_mut().insert(path.to_owned(), bytes.clone());
+      previous != Some(bytes)
+   }
+
+   // false if the file wasn't there
+   pub fn remove(&self, path: &str) -> bool {
+      self.files.borrow_mut().remove(path).is_some()
+   }
+
+   pub fn contains(&self, path: &str) -> bool {
+      self.files.borrow().contains_key(path)
+   }
+}
+
+impl AssetSource for MemorySource {
+   fn name(&self) -> String {
+      "memory".to_owned()
+   }
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      let bytes = self.read_now(path);
+      if let Some(Ok(bytes)) = &bytes {
+         progress.set_total_bytes(Some(bytes.len() as u64));
+         progress.add_loaded_bytes(bytes.len() as u64);
+      }
+      Box::pin(futures::future::ready(bytes))
+   }
+
+   fn read_now(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      self.files.borrow().get(path).map(|bytes| Ok(bytes.clone()))
+   }
+}
+
+// Files under a root directory
+#[cfg(not(feature = "web"))]
+pub struct FsSource {
+   root: std::path::PathBuf,
+}
+
+#[cfg(not(feature = "web"))]
+impl FsSource {
+   const READ_CHUNK_SIZE: usize = 1 << 22;
+
+   pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+      Self { root: root.into() }
+   }
+
+   pub fn cwd() -> std::path::PathBuf {
+      std::env::current_dir().unwrap_or_default()
+   }
+
+   // directory of the running binary
+   pub fn exe_dir() -> std::path::PathBuf {
+      std::env::current_exe().ok()
+         .and_then(|exe| exe.parent().map(|dir| dir.to_owned()))
+         .unwrap_or_default()
+   }
+
+   fn open(&self, path: &str) -> Option<Result<std::fs::File, AssetError>> {
+      match std::fs::File::open(self.root.join(path)) {
+         Ok(file) => Some(Ok(file)),
+         Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+         Err(e) => Some(Err(Self::io_error(path, e))),
+      }
+   }
+
+   fn io_error(path: &str, e: std::io::Error) -> AssetError {
+      AssetError::DecodeFailed { path: path.to_owned(), reason: e.to_string() }
+   }
+}
+
+#[cfg(not(feature = "web"))]
+impl AssetSource for FsSource {
+   fn name(&self) -> String {
+      self.root.display().to_string()
+   }
+
+   // reads the file in chunks, reporting them to `progress`
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      Box::pin(async move {
+         use std::io::Read;
+         let mut file = match self.open(path)? {
+            Ok(file) => file,
+            Err(e) => return Some(Err(e)),
+         };
+         let total_bytes = file.metadata().map(|metadata| metadata.len()).ok();
+         progress.set_total_bytes(total_bytes);
+         let mut data = Vec::with_capacity(total_bytes.unwrap_or(0) as usize);
+         let mut chunk = vec![0; Self::READ_CHUNK_SIZE];
+         loop {
+            let read = match file.read(&mut chunk) {
+               Ok(read) => read,
+               Err(e) => return Some(Err(Self::io_error(path, e))),
+            };
+            if read == 0 {
+               break;
+            }
+            data.extend_from_slice(&chunk[..read]);
+            progress.add_loaded_bytes(read as u64);
+            // lets the progress bar and other loads advance between chunks
+            YieldNow::default().await;
+         }
+         Some(Ok(data))
+      })
+   }
+
+   fn read_now(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      use std::io::Read;
+      let mut data = vec![];
+      Some(self.open(path)?
+         .and_then(|mut file| file.read_to_end(&mut data).map_err(|e| Self::io_error(path, e)))
+         .map(|_| data))
+   }
+
+   fn local_path(&self, path: &str) -> Option<std::path::PathBuf> {
+      Some(self.root.join(path)).filter(|path| path.is_file())
+   }
+}
+
+// Pending once, so the caller gets back control until the next poll
+#[cfg(not(feature = "web"))]
+#[derive(Default)]
+struct YieldNow(bool);
+
+#[cfg(not(feature = "web"))]
+impl std::future::Future for YieldNow {
+   type Output = ();
+
+   fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<()> {
+      if self.0 {
+         return std::task::Poll::Ready(());
+      }
+      self.0 = true;
+      cx.waker().wake_by_ref();
+      std::task::Poll::Pending
+   }
+}
+
+// HTTP GET relative to `base_url`, the page URL when it's empty
+#[cfg(feature = "web")]
+pub struct FetchSource {
+   base_url: String,
+}
+
+#[cfg(feature = "web")]
+impl FetchSource {
+   pub fn new(base_url: &str) -> Self {
+      Self { base_url: base_url.to_owned() }
+   }
+
+   async fn fetch(&self, path: &str, progress: &LoadProgress) -> Option<Result<Vec<u8>, String>> {
+      use wasm_bindgen::JsCast;
+      use wasm_bindgen_futures::JsFuture;
+      let js_error = |e: wasm_bindgen::JsValue| format!("{e:?}");
+      let url = format!("{}{}", self.base_url, path);
+      let response = async {
+         let window = web_sys::window().ok_or("No window to fetch from".to_owned())?;
+         JsFuture::from(window.fetch_with_str(&url))
+            .await
+            .map_err(js_error)?
+            .dyn_into::<web_sys::Response>()
+            .map_err(js_error)
+      }.await;
+      let response = match response {
+         Ok(response) if response.status() == 404 => return None,
+         Ok(response) if !response.ok() => return Some(Err(format!("HTTP {} for {}", response.status(), url))),
+         Ok(response) => response,
+         Err(e) => return Some(Err(e)),
+      };
+      let total_bytes = response.headers().get("Content-Length").ok().flatten()
+         .and_then(|length| length.parse::<u64>().ok());
+      progress.set_total_bytes(total_bytes);
+      let Some(body) = response.body() else {
+         return Some(Ok(vec![]));
+      };
+      let reader: web_sys::ReadableStreamDefaultReader = body.get_reader().unchecked_into();
+      let mut data = Vec::with_capacity(total_bytes.unwrap_or(0) as usize);
+      let read_chunks = async {
+         loop {
+            let chunk = JsFuture::from(reader.read()).await.map_err(js_error)?;
+            let done = js_sys::Reflect::get(&chunk, &"done".into()).map_err(js_error)?;
+            if done.as_bool().unwrap_or(true) {
+               return Ok::<(), String>(());
+            }
+            let value = js_sys::Reflect::get(&chunk, &"value".into()).map_err(js_error)?;
+            let bytes = js_sys::Uint8Array::new(&value);
+            let offset = data.len();
+            data.resize(offset + bytes.length() as usize, 0);
+            bytes.copy_to(&mut data[offset..]);
+            progress.add_loaded_bytes(bytes.length() as u64);
+         }
+      };
+      let read = read_chunks.await;
+      Some(read.map(|_| data))
+   }
+}
+
+#[cfg(feature = "web")]
+impl AssetSource for FetchSource {
+   fn name(&self) -> String {
+      format!("fetch '{}'", self.base_url)
+   }
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      Box::pin(async move {
+         let bytes = self.fetch(path, progress).await?;
+         Some(bytes.map_err(|reason| AssetError::Network { path: path.to_owned(), reason }))
+      })
+   }
+}
+
+// Entries of a zip archive, stored or deflated
+pub struct ZipSource {
+   name: String,
+   archive: Vec<u8>,
+   entries: HashMap<String, ZipEntry>,
+}
+
+struct ZipEntry {
+   method: u16,
+   compressed_size: usize,
+   size: usize,
+   local_header_offset: usize,
+}
+
+impl ZipSource {
+   const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+   const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+   const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+   const STORED: u16 = 0;
+   const DEFLATED: u16 = 8;
+
+   pub fn new(name: &str, archive: Vec<u8>) -> Result<Self, String> {
+      let read_u16 = |offset: usize| archive.get(offset..offset + 2)
+         .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()));
+      let read_u32 = |offset: usize| archive.get(offset..offset + 4)
+         .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+      let truncated = || format!("{name}: truncated zip archive");
+
+      // the end record is followed by a comment of up to 64K
+      let end = (0..=archive.len().saturating_sub(22)).rev()
+         .take(22 + u16::MAX as usize)
+         .find(|offset| read_u32(*offset) == Some(Self::END_OF_CENTRAL_DIRECTORY))
+         .ok_or_else(|| format!("{name}: not a zip archive"))?;
+      let entry_count = read_u16(end + 10).ok_or_else(truncated)? as usize;
+      let mut offset = read_u32(end + 16).ok_or_else(truncated)? as usize;
+
+      let mut entries = HashMap::with_capacity(entry_count);
+      for _ in 0..entry_count {
+         if read_u32(offset) != Some(Self::CENTRAL_DIRECTORY_HEADER) {
+            return Err(format!("{name}: broken zip central directory"));
+         }
+         let method = read_u16(offset + 10).ok_or_else(truncated)?;
+         let compressed_size = read_u32(offset + 20).ok_or_else(truncated)? as usize;
+         let size = read_u32(offset + 24).ok_or_else(truncated)? as usize;
+         let name_length = read_u16(offset + 28).ok_or_else(truncated)? as usize;
+         let extra_length = read_u16(offset + 30).ok_or_else(truncated)? as usize;
+         let comment_length = read_u16(offset + 32).ok_or_else(truncated)? as usize;
+         let local_header_offset = read_u32(offset + 42).ok_or_else(truncated)? as usize;
+         let path = archive.get(offset + 46..offset + 46 + name_length).ok_or_else(truncated)?;
+         let path = String::from_utf8_lossy(path).into_owned();
+         if !path.ends_with('/') {
+            entries.insert(path, ZipEntry { method, compressed_size, size, local_header_offset });
+         }
+         offset += 46 + name_length + extra_length + comment_length;
+      }
+      Ok(Self { name: name.to_owned(), archive, entries })
+   }
+
+   fn extract(&self, path: &str, entry: &ZipEntry) -> Result<Vec<u8>, AssetError> {
+      let decode_failed = |reason: &str| AssetError::DecodeFailed { path: path.to_owned(), reason: reason.to_owned() };
+      let offset = entry.local_header_offset;
+      let header = self.archive.get(offset..offset + 30)
+         .filter(|header| header[..4] == Self::LOCAL_FILE_HEADER.to_le_bytes())
+         .ok_or_else(|| decode_failed("broken zip local header"))?;
+      // the local extra field may differ from the one in the central directory
+      let name_length = u16::from_le_bytes([header[26], header[27]]) as usize;
+      let extra_length = u16::from_le_bytes([header[28], header[29]]) as usize;
+      let data_offset = offset + 30 + name_length + extra_length;
+      let data = self.archive.get(data_offset..data_offset + entry.compressed_size)
+         .ok_or_else(|| decode_failed("truncated zip entry"))?;
+      match entry.method {
+         Self::STORED => Ok(data.to_vec()),
+         Self::DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(data, entry.size)
+            .map_err(|e| decode_failed(&format!("failed to inflate: {e}"))),
+         method => Err(AssetError::UnsupportedFormat {
+            path: path.to_owned(),
+            reason: format!("zip compression method {method}"),
+         }),
+      }
+   }
+}
+
+impl AssetSource for ZipSource {
+   fn name(&self) -> String {
+      format!("zip '{}'", self.name)
+   }
+
+   fn read<'a>(&'a self, path: &'a str, progress: &'a LoadProgress) -> LocalBoxFuture<'a, Option<Result<Vec<u8>, AssetError>>> {
+      let bytes = self.read_now(path);
+      if let Some(Ok(bytes)) = &bytes {
+         progress.set_total_bytes(Some(bytes.len() as u64));
+         progress.add_loaded_bytes(bytes.len() as u64);
+      }
+      Box::pin(futures::future::ready(bytes))
+   }
+
+   fn read_now(&self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+      self.entries.get(path).map(|entry| self.extract(path, entry))
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // a single entry archive, as written by `zip -0`
+   fn stored_zip(path: &str, data: &[u8]) -> Vec<u8> {
+      let mut zip = vec![];
+      zip.extend_from_slice(&ZipSource::LOCAL_FILE_HEADER.to_le_bytes());
+      zip.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+      zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+      zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+      zip.extend_from_slice(&(path.len() as u16).to_le_bytes());
+      zip.extend_from_slice(&0_u16.to_le_bytes());
+      zip.extend_from_slice(path.as_bytes());
+      zip.extend_from_slice(data);
+      let directory_offset = zip.len();
+      zip.extend_from_slice(&ZipSource::CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+      zip.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+      zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+      zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+      zip.extend_from_slice(&(path.len() as u16).to_le_bytes());
+      zip.extend_from_slice(&[0; 12]);
+      zip.extend_from_slice(&0_u32.to_le_bytes());
+      zip.extend_from_slice(path.as_bytes());
+      let directory_size = zip.len() - directory_offset;
+      zip.extend_from_slice(&ZipSource::END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+      zip.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+      zip.extend_from_slice(&(directory_size as u32).to_le_bytes());
+      zip.extend_from_slice(&(directory_offset as u32).to_le_bytes());
+      zip.extend_from_slice(&0_u16.to_le_bytes());
+      zip
+   }
+
+   #[test]
+   fn sources_are_asked_in_priority_order() {
+      let zip = ZipSource::new("test.zip", stored_zip("shaders/a.wgsl", b"zipped")).unwrap();
+      let sources = AssetSources::new()
+         .with_source(EmbeddedSource::new().with_file("shaders/b.wgsl", b"embedded"))
+         .with_source(zip)
+         .with_source(EmbeddedSource::new().with_file("shaders/a.wgsl", b"shadowed"));
+      let progress = LoadProgress::default();
+      let read = |path| futures::executor::block_on(sources.read(path, &progress));
+      assert_eq!(read("shaders/a.wgsl").unwrap(), b"zipped");
+      assert_eq!(read("shaders/b.wgsl").unwrap(), b"embedded");
+      assert_eq!(sources.read_now("shaders/a.wgsl").unwrap(), b"zipped");
+      let missing = read("shaders/c.wgsl").unwrap_err();
+      assert!(matches!(missing, AssetError::NotFound { .. }));
+      assert!(missing.to_string().contains("zip 'test.zip'"));
+      assert!(ZipSource::new("broken.zip", b"PK".to_vec()).is_err());
+   }
+
+   #[test]
+   fn zip_errors_keep_their_kind() {
+      let mut zip = stored_zip("shaders/a.wgsl", b"zipped");
+      // compression method of the central directory entry
+      let method_at = zip.len() - 22 - 46 - "shaders/a.wgsl".len() + 10;
+      zip[method_at] = 99;
+      let sources = AssetSources::new().with_source(ZipSource::new("test.zip", zip).unwrap());
+      let error = sources.read_now("shaders/a.wgsl").unwrap_err();
+      assert!(matches!(error, AssetError::UnsupportedFormat { .. }));
+      assert!(error.to_string().contains("zip 'test.zip'"));
+   }
+
+   #[cfg(not(feature = "web"))]
+   #[test]
+   fn file_read_errors_are_not_network_errors() {
+      let directory = std::env::temp_dir().join(format!("asset_source_test_{}", std::process::id()));
+      std::fs::create_dir_all(directory.join("shaders/b.wgsl")).unwrap();
+      // a directory can be opened but not read
+      let error = AssetSources::new().with_source(FsSource::new(&directory)).read_now("shaders/b.wgsl").unwrap_err();
+      std::fs::remove_dir_all(&directory).unwrap();
+      assert!(matches!(error, AssetError::DecodeFailed { .. }));
+   }
+}
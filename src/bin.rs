@@ -23,6 +23,8 @@ use imgui_wgpu::{Renderer, RendererConfig};
 use imgui_winit_support::winit::{event_loop::EventLoop, window::WindowBuilder};
 
 use my_renderer::renderer::asset_loader::AssetLoader;
+use my_renderer::renderer::asset_watcher::{AssetWatcher, ShaderWatcher};
+use my_renderer::renderer::imgui_web::shader_editor::ShaderEditor;
 use my_renderer::renderer::{demo_mesh, GlobalUniform, LoadingArgs, RenderArgs};
 use my_renderer::renderer::{handle_keyboard, demo_uv, imgui_web, FrameStateRef, webgpu::Webgpu, demo_stub, demo_fractal, DemoHistoryPlayback, DemoStateHistory, ExternalState, IDemo, Premade};
 use my_renderer::{DemoId, GraphicsLevel};
@@ -73,6 +75,10 @@ struct State<'window> {
    demo_idx: i32,
    premade: Rc<RefCell<Premade>>,
    asset_loader: Rc<RefCell<AssetLoader>>,
+   asset_watcher: AssetWatcher,
+   asset_reload_count: u64,
+   shader_watcher: ShaderWatcher,
+   shader_editor: ShaderEditor,
    waker: std::task::Waker,
 }
 
@@ -92,7 +98,17 @@ const GRAPHICS_LEVELS: [GraphicsLevel; 5] = [
    GraphicsLevel::High, GraphicsLevel::Ultra];
 
 impl<'window> State<'window> {
+   fn loading_args(&self) -> LoadingArgs {
+      LoadingArgs {
+         webgpu: self.webgpu.clone(),
+         color_texture_format: self.webgpu_config.format,
+         premade: self.premade.clone(),
+         asset_loader: self.asset_loader.clone(),
+      }
+   }
+
    async fn load_demo(&mut self, id: DemoId) -> Box<dyn IDemo> {
+      self.premade.borrow().shader_loader.borrow_mut().forget_used_shaders();
       let loading_args = LoadingArgs {
          webgpu: self.webgpu.clone(),
          color_texture_format: self.webgpu_config.format,
@@ -178,6 +194,10 @@ impl<'window> State<'window> {
          imgui_exports,
          premade,
          asset_loader,
+         asset_watcher: AssetWatcher::default(),
+         asset_reload_count: 0,
+         shader_watcher: ShaderWatcher::default(),
+         shader_editor: ShaderEditor::default(),
          waker,
      }
    }
@@ -197,7 +217,19 @@ impl<'window> State<'window> {
          handle_keyboard(keyboard, frame_state);
       }
       let mut async_cx = std::task::Context::from_waker(&self.waker);
+      self.asset_watcher.poll(&mut self.asset_loader.borrow_mut());
       self.asset_loader.borrow_mut().tick_loading(&mut async_cx);
+      let reload_count = self.asset_loader.borrow().reload_count();
+      if reload_count != self.asset_reload_count {
+         self.asset_reload_count = reload_count;
+         self.demo.assets_reloaded(self.loading_args());
+      }
+      let shaders_changed = self.shader_watcher.poll(&mut self.premade.borrow().shader_loader.borrow_mut());
+      if shaders_changed {
+         // shaders which fail to compile keep their last good module, and so their pipelines
+         self.demo.rebuild_pipelines(self.loading_args());
+      }
+      self.premade.borrow().shader_loader.borrow_mut().precompile_next(&self.webgpu.device);
       self.tick_imgui(now_timestamp_ms);
       let tick_timestamp_ms = self.demo_history_playback.playback_timestamp_ms().unwrap_or(now_timestamp_ms);
       self.demo_state.tick(tick_timestamp_ms);
@@ -245,6 +277,7 @@ impl<'window> State<'window> {
    }
    
    fn switch_graphics_level(&mut self, level: GraphicsLevel) {
+      self.premade.borrow().shader_loader.borrow_mut().forget_used_shaders();
       self.demo_state.set_graphics_level(level);
       let loading_args = LoadingArgs {
          webgpu: self.webgpu.clone(),
@@ -298,6 +331,7 @@ impl<'window> State<'window> {
          .always_auto_resize(true)
          .build(|| {
          if ui.button("Recompile shaders") {
+            self.premade.borrow().shader_loader.borrow_mut().clear_cache();
             let loading_args = LoadingArgs {
                webgpu: self.webgpu.clone(),
                color_texture_format: self.webgpu_config.format,
@@ -306,6 +340,10 @@ impl<'window> State<'window> {
             };
             self.demo.rebuild_pipelines(loading_args);
          }
+         let cache_stats = self.premade.borrow().shader_loader.borrow().cache_stats();
+         ui.same_line();
+         ui.text_disabled(format!("{} modules, {} hits, {} misses", cache_stats.modules, cache_stats.hits, cache_stats.misses));
+         ui.checkbox("Shader editor", &mut self.shader_editor.open);
          if ui.collapsing_header("Settings", TreeNodeFlags::SPAN_FULL_WIDTH) {
             if ui.list_box("Demo",&mut self.demo_idx,
          &self.demos_ids, self.demos_ids.len() as i32) {
@@ -377,6 +415,35 @@ impl<'window> State<'window> {
             }
          }
       });
+
+      let shader_editor_args = imgui_web::ImguiRenderArgs {
+         size: [560.0, 480.0],
+         ..imgui_web::ImguiRenderArgs::new_down_from(&imgui_common_args, [0.0, 10.0])
+      };
+      let shaders_edited = self.shader_editor.render(&ui, shader_editor_args, &mut self.premade.borrow().shader_loader.borrow_mut());
+      if shaders_edited {
+         self.demo.rebuild_pipelines(self.loading_args());
+      }
+
+      // stays until the shaders compile again
+      let premade = self.premade.borrow();
+      let shader_errors = premade.shader_loader.borrow().errors().clone();
+      if !shader_errors.is_empty() {
+         let mut shader_errors: Vec<_> = shader_errors.into_iter().collect();
+         shader_errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+         let display_size = ui.io().display_size;
+         ui.window("Shader errors")
+            .position([10.0, display_size[1] - 10.0], Condition::Always)
+            .position_pivot([0.0, 1.0])
+            .always_auto_resize(true)
+            .collapsible(false)
+            .build(|| {
+               for (_, error) in &shader_errors {
+                  ui.text_colored([1.0, 0.3, 0.3, 1.0], error.to_string());
+               }
+            });
+      }
+      drop(premade);
       self.imgui = Some(imgui_local);
    }
 
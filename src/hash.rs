@@ -0,0 +1,5 @@
+// 64 bit FNV-1a, stable across builds and platforms unlike std's hashers.
+// Keys asset pack entries and compiled shader modules by their contents
+pub fn content_hash(bytes: &[u8]) -> u64 {
+   bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
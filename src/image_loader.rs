@@ -1,64 +1,305 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::asset_source::{AssetError, AssetSources};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+   // 8 bits per channel, sRGB or linear is decided on upload
+   Rgba8,
+   Rgba16Float,
+   Rgba32Float,
+   // block compressed data uploaded as is
+   Compressed(wgpu::TextureFormat),
+}
+
+impl PixelFormat {
+   // bytes per pixel, or per block of compressed formats
+   pub fn stride(self) -> u32 {
+      match self {
+         PixelFormat::Rgba8 => 4,
+         PixelFormat::Rgba16Float => 8,
+         PixelFormat::Rgba32Float => 16,
+         PixelFormat::Compressed(format) => format.block_copy_size(None).unwrap_or(0),
+      }
+   }
+
+   // GPU format of the data, `rgba8_format` is used for 8 bit data
+   pub fn texture_format(self, rgba8_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+      match self {
+         PixelFormat::Rgba8 => rgba8_format,
+         PixelFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+         PixelFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+         PixelFormat::Compressed(format) => format,
+      }
+   }
+}
+
 pub struct TextureInfo {
    pub data: Vec<u8>,
    pub width: u32,
    pub height: u32,
    pub depth: u32,
-   pub pixel_stride: u8,
+   pub format: PixelFormat,
+   // levels are stored one after another in `data`
+   pub mip_level_count: u32,
 }
 
-pub async fn load_image_rgba8(image_path: String) -> TextureInfo {
+impl TextureInfo {
+   pub fn pixel_stride(&self) -> u32 {
+      self.format.stride()
+   }
+
+   // magenta and black checkerboard, stands in for textures which failed to load
+   pub fn error_texture() -> Self {
+      const SIZE: u32 = 64;
+      const SQUARE: u32 = 8;
+      let data = (0..SIZE * SIZE)
+         .flat_map(|i| match (i % SIZE / SQUARE + i / SIZE / SQUARE) % 2 {
+            0 => [255, 0, 255, 255],
+            _ => [0, 0, 0, 255],
+         })
+         .collect();
+      Self {
+         data,
+         width: SIZE, height: SIZE, depth: 1,
+         format: PixelFormat::Rgba8,
+         mip_level_count: 1,
+      }
+   }
+}
+
+// Shared between a loading future and whoever shows its progress
+#[derive(Default)]
+pub struct LoadProgress {
+   loaded_bytes: Cell<u64>,
+   total_bytes: Cell<Option<u64>>,
+   decoded: Cell<bool>,
+}
+
+impl LoadProgress {
+   // share of fetching in the normalized progress, decoding takes the rest
+   const FETCH_WEIGHT: f32 = 0.8;
+
+   // normalized progress 0.0 - 1.0
+   pub fn fraction(&self) -> f32 {
+      if self.decoded.get() {
+         return 1.0;
+      }
+      match self.total_bytes.get() {
+         Some(total_bytes) if total_bytes > 0 => {
+            let fetched = (self.loaded_bytes.get() as f64 / total_bytes as f64).min(1.0) as f32;
+            fetched * Self::FETCH_WEIGHT
+         },
+         _ => 0.0,
+      }
+   }
+
+   pub fn loaded_bytes(&self) -> u64 {
+      self.loaded_bytes.get()
+   }
+
+   // None until the size is known, or if the server didn't send it
+   pub fn total_bytes(&self) -> Option<u64> {
+      self.total_bytes.get()
+   }
+
+   pub(crate) fn set_total_bytes(&self, total_bytes: Option<u64>) {
+      self.total_bytes.set(total_bytes);
+   }
+
+   pub(crate) fn add_loaded_bytes(&self, bytes: u64) {
+      self.loaded_bytes.set(self.loaded_bytes.get() + bytes);
+   }
+
+   pub(crate) fn set_loaded_bytes(&self, bytes: u64) {
+      self.loaded_bytes.set(bytes);
+   }
+
+   pub(crate) fn set_decoded(&self) {
+      self.decoded.set(true);
+   }
+
+   pub(crate) fn reset(&self) {
+      self.loaded_bytes.set(0);
+      self.total_bytes.set(None);
+      self.decoded.set(false);
+   }
+}
+
+pub async fn load_image_rgba8(sources: Rc<AssetSources>, image_path: String, progress: Rc<LoadProgress>) -> Result<TextureInfo, AssetError> {
    let _t = crate::timer::ScopedTimer::new("load_image");
+   let bytes = sources.read(&image_path, &progress).await?;
    cfg_if::cfg_if!{ if #[cfg(feature="web")] {
       // TODO: don't create new canvas for each load
       // store in an object?
       use wasm_bindgen::JsCast;
       use std::ops::Deref;
-      let image = web_sys::HtmlImageElement::new().unwrap();
-      image.set_src(&image_path);
+      let js_error = |e: wasm_bindgen::JsValue| AssetError::DecodeFailed {
+         path: image_path.clone(),
+         reason: format!("{e:?}"),
+      };
+      // fetched bytes are decoded by the browser through an object URL
+      let blob = web_sys::Blob::new_with_u8_array_sequence(
+         &js_sys::Array::of1(&js_sys::Uint8Array::from(bytes.as_slice()))).map_err(js_error)?;
+      let blob_url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+      let image = web_sys::HtmlImageElement::new().map_err(js_error)?;
+      image.set_src(&blob_url);
       let image_load_promise = image.decode();
-      wasm_bindgen_futures::JsFuture::from(image_load_promise)
-         .await
-         .unwrap();
+      let decoded = wasm_bindgen_futures::JsFuture::from(image_load_promise).await;
+      let _ = web_sys::Url::revoke_object_url(&blob_url);
+      // the browser rejects data it can't decode, as well as formats it doesn't know
+      decoded.map_err(js_error)?;
       let (width, height) = (image.width(), image.height());
-      let canvas = web_sys::OffscreenCanvas::new(width, height).unwrap();
+      let canvas = web_sys::OffscreenCanvas::new(width, height).map_err(js_error)?;
       let context = canvas
          .get_context("2d")
-         .unwrap()
-         .unwrap()
+         .map_err(js_error)?
+         .ok_or_else(|| js_error("OffscreenCanvas has no 2d context".into()))?
          .dyn_into::<web_sys::OffscreenCanvasRenderingContext2d>()
-         .unwrap();
+         .map_err(|e| js_error(e.into()))?;
       context.draw_image_with_html_image_element(&image, 0.0, 0.0)
-         .expect("Failed to draw_image_with_html_image_element");
+         .map_err(js_error)?;
       let image_data = context
          .get_image_data(0.0, 0.0, width as f64, height as f64)
-         .unwrap()
+         .map_err(js_error)?
          .data()
          .deref()
          .clone();
       // canvas.remove();
       image.remove();
-      TextureInfo {
+      progress.set_decoded();
+      Ok(TextureInfo {
          data: image_data,
          width, height, depth: 1,
-         pixel_stride: 4,
-      }
+         format: PixelFormat::Rgba8,
+         mip_level_count: 1,
+      })
    } else { // cfg_if::cfg_if!
-      let cwd = std::env::current_dir().expect("Failed to get current working dir");
-      let abs_filepath = cwd.join("www").join(image_path);
-      let img = image::io::Reader::open(abs_filepath)
-         .unwrap()
-         .decode()
-         .unwrap();
+      let img = image::load_from_memory(&bytes)
+         .map_err(|e| image_error(&image_path, e))?;
       let decoded_bytes = img.to_rgba8()
          .into_vec();
       use image::GenericImageView;
       let dimensions = img.dimensions();
-      TextureInfo {
+      progress.set_decoded();
+      Ok(TextureInfo {
          data: decoded_bytes,
          width: dimensions.0,
          height: dimensions.1,
          depth: 1,
-         pixel_stride: 4,
-      }
+         format: PixelFormat::Rgba8,
+         mip_level_count: 1,
+      })
    }} // cfg_if::cfg_if!
-}
\ No newline at end of file
+}
+
+// Radiance .hdr and OpenEXR images, `format` is either Rgba16Float or Rgba32Float
+pub async fn load_image_float(sources: Rc<AssetSources>, image_path: String, format: PixelFormat, progress: Rc<LoadProgress>) -> Result<TextureInfo, AssetError> {
+   let _t = crate::timer::ScopedTimer::new("load_image_float");
+   let bytes = sources.read(&image_path, &progress).await?;
+   let info = decode_image_float(&image_path, &bytes, format)?;
+   progress.set_decoded();
+   Ok(info)
+}
+
+pub fn decode_image_float(path: &str, bytes: &[u8], format: PixelFormat) -> Result<TextureInfo, AssetError> {
+   let img = image::load_from_memory(bytes)
+      .map_err(|e| image_error(path, e))?
+      .into_rgba32f();
+   let (width, height) = img.dimensions();
+   let data = match format {
+      PixelFormat::Rgba32Float => bytemuck::cast_slice(img.as_raw()).to_vec(),
+      PixelFormat::Rgba16Float => img.as_raw().iter()
+         .flat_map(|&value| f32_to_f16(value).to_le_bytes())
+         .collect(),
+      _ => return Err(AssetError::UnsupportedFormat {
+         path: path.to_owned(),
+         reason: format!("{format:?} is not a float format"),
+      }),
+   };
+   Ok(TextureInfo {
+      data,
+      width, height, depth: 1,
+      format,
+      mip_level_count: 1,
+   })
+}
+
+fn image_error(path: &str, error: image::ImageError) -> AssetError {
+   match error {
+      image::ImageError::Unsupported(e) => AssetError::UnsupportedFormat { path: path.to_owned(), reason: e.to_string() },
+      e => AssetError::DecodeFailed { path: path.to_owned(), reason: e.to_string() },
+   }
+}
+
+// Rgba32Float isn't filterable without FLOAT32_FILTERABLE
+pub fn convert_to_rgba16f(info: &TextureInfo) -> TextureInfo {
+   assert_eq!(info.format, PixelFormat::Rgba32Float, "Only Rgba32Float data is converted");
+   TextureInfo {
+      data: info.data.chunks_exact(4)
+         .flat_map(|value| f32_to_f16(f32::from_le_bytes(value.try_into().unwrap())).to_le_bytes())
+         .collect(),
+      format: PixelFormat::Rgba16Float,
+      ..*info
+   }
+}
+
+// round to nearest, out of range values become infinity
+fn f32_to_f16(value: f32) -> u16 {
+   let bits = value.to_bits();
+   let sign = ((bits >> 16) & 0x8000) as u16;
+   let exponent = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
+   let mantissa = bits & 0x7F_FFFF;
+   if bits & 0x7FFF_FFFF > 0x7F80_0000 {
+      return sign | 0x7E00; // NaN
+   }
+   if exponent >= 0x1F {
+      return sign | 0x7C00;
+   }
+   if exponent <= 0 {
+      // subnormal half, or zero
+      if exponent < -10 {
+         return sign;
+      }
+      let mantissa = mantissa | 0x80_0000;
+      let shift = (14 - exponent) as u32;
+      let round = (mantissa >> (shift - 1)) & 1;
+      return sign | ((mantissa >> shift) + round) as u16;
+   }
+   // a rounding carry moves into the exponent, up to infinity
+   let half = ((exponent as u32) << 10) | (mantissa >> 13);
+   sign | (half + ((mantissa >> 12) & 1)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn f32_to_f16_bits() {
+      assert_eq!(f32_to_f16(1.0), 0x3C00);
+      assert_eq!(f32_to_f16(-2.0), 0xC000);
+      assert_eq!(f32_to_f16(0.5), 0x3800);
+      assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+      assert_eq!(f32_to_f16(1e6), 0x7C00);
+      assert_eq!(f32_to_f16(2_f32.powi(-24)), 0x0001);
+      assert_eq!(f32_to_f16(1e-10), 0);
+   }
+
+   #[test]
+   fn decode_radiance_hdr() {
+      // 2x1 flat RGBE, short scanlines aren't run length encoded
+      let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+      bytes.extend_from_slice(&[128, 64, 32, 129, 128, 128, 128, 136]);
+      let info = decode_image_float("test.hdr", &bytes, PixelFormat::Rgba32Float).unwrap();
+      assert_eq!((info.width, info.height, info.pixel_stride()), (2, 1, 16));
+      let pixels: Vec<f32> = info.data.chunks_exact(4)
+         .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+         .collect();
+      assert_eq!(pixels, [1.0, 0.5, 0.25, 1.0, 128.0, 128.0, 128.0, 1.0]);
+      let half = convert_to_rgba16f(&info);
+      assert_eq!(half.pixel_stride(), 8);
+      assert_eq!(&half.data[..8], &[0x00, 0x3C, 0x00, 0x38, 0x00, 0x34, 0x00, 0x3C]);
+   }
+}
@@ -0,0 +1,248 @@
+use crate::asset_source::{AssetError, AssetSources};
+use crate::image_loader::{LoadProgress, PixelFormat, TextureInfo};
+
+const IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+// supercompressionScheme values
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+   Io(AssetError),
+   InvalidIdentifier,
+   Truncated,
+   // ETC1S/UASTC payloads, they need a transcoder which isn't built in
+   BasisUniversal,
+   Supercompressed(u32),
+   UnsupportedVkFormat(u32),
+   // only single 2D images with their mips are supported
+   UnsupportedLayout { depth: u32, layers: u32, faces: u32 },
+   // no pixels, wgpu can't create the texture
+   ZeroWidth,
+   NotBlockAligned { width: u32, height: u32, format: wgpu::TextureFormat },
+   // more mips than the base level halves into
+   InvalidLevelCount { levels: u32, max: u32 },
+   // a level whose size doesn't match its dimensions, uploading it would fail
+   InvalidLevelLength { level: u32, length: u64, expected: u64 },
+   UnsupportedByDevice(wgpu::TextureFormat),
+}
+
+impl std::fmt::Display for Ktx2Error {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self {
+         Ktx2Error::Io(e) => write!(f, "{e}"),
+         Ktx2Error::InvalidIdentifier => write!(f, "not a KTX2 file"),
+         Ktx2Error::Truncated => write!(f, "file is truncated"),
+         Ktx2Error::BasisUniversal => write!(f, "Basis Universal payload, transcoding is not available"),
+         Ktx2Error::Supercompressed(scheme) => write!(f, "unsupported supercompression scheme {scheme}"),
+         Ktx2Error::UnsupportedVkFormat(format) => write!(f, "unsupported vkFormat {format}"),
+         Ktx2Error::UnsupportedLayout { depth, layers, faces } =>
+            write!(f, "unsupported layout depth:{depth} layers:{layers} faces:{faces}"),
+         Ktx2Error::ZeroWidth => write!(f, "image width is 0"),
+         Ktx2Error::NotBlockAligned { width, height, format } =>
+            write!(f, "{width}x{height} is not a multiple of the {format:?} block size"),
+         Ktx2Error::InvalidLevelCount { levels, max } => write!(f, "{levels} mip levels, the image has at most {max}"),
+         Ktx2Error::InvalidLevelLength { level, length, expected } =>
+            write!(f, "mip level {level} has {length} bytes, {expected} expected"),
+         Ktx2Error::UnsupportedByDevice(format) => write!(f, "{format:?} is not supported by the device"),
+      }
+   }
+}
+
+// Reads a KTX2 file with a BC/ETC2/ASTC/RGBA8 payload,
+// fails if the device doesn't have the features for its format
+pub async fn load_ktx2(sources: &AssetSources, path: String, features: wgpu::Features, progress: &LoadProgress) -> Result<TextureInfo, Ktx2Error> {
+   let _t = crate::timer::ScopedTimer::new("load_ktx2");
+   let bytes = sources.read(&path, progress).await.map_err(Ktx2Error::Io)?;
+   let info = parse_ktx2(&bytes)?;
+   let format = info.format.texture_format(wgpu::TextureFormat::Rgba8Unorm);
+   if !features.contains(format.required_features()) {
+      return Err(Ktx2Error::UnsupportedByDevice(format));
+   }
+   progress.set_decoded();
+   Ok(info)
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> Result<TextureInfo, Ktx2Error> {
+   if bytes.len() < IDENTIFIER.len() || bytes[..IDENTIFIER.len()] != IDENTIFIER {
+      return Err(Ktx2Error::InvalidIdentifier);
+   }
+   if bytes.len() < HEADER_SIZE {
+      return Err(Ktx2Error::Truncated);
+   }
+   let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+   let read_u64 = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+   let vk_format = read_u32(12);
+   let width = read_u32(20);
+   let height = read_u32(24).max(1);
+   let depth = read_u32(28);
+   let layers = read_u32(32);
+   let faces = read_u32(36);
+   let level_count = read_u32(40).max(1);
+   let supercompression = read_u32(44);
+
+   if vk_format == 0 || supercompression == SUPERCOMPRESSION_BASIS_LZ {
+      return Err(Ktx2Error::BasisUniversal);
+   }
+   if supercompression != SUPERCOMPRESSION_NONE {
+      return Err(Ktx2Error::Supercompressed(supercompression));
+   }
+   let format = vk_format_to_wgpu(vk_format).ok_or(Ktx2Error::UnsupportedVkFormat(vk_format))?;
+   if depth > 1 || layers > 1 || faces != 1 {
+      return Err(Ktx2Error::UnsupportedLayout { depth, layers, faces });
+   }
+   if width == 0 {
+      return Err(Ktx2Error::ZeroWidth);
+   }
+   let (block_width, block_height) = format.block_dimensions();
+   if width % block_width != 0 || height % block_height != 0 {
+      return Err(Ktx2Error::NotBlockAligned { width, height, format });
+   }
+
+   let max_level_count = u32::BITS - width.max(height).leading_zeros();
+   if level_count > max_level_count {
+      return Err(Ktx2Error::InvalidLevelCount { levels: level_count, max: max_level_count });
+   }
+
+   // the level index starts from the base level, level data in the file is usually stored
+   // from the smallest mip, it's copied in the upload order
+   let level_index_end = (level_count as usize).checked_mul(LEVEL_INDEX_ENTRY_SIZE)
+      .and_then(|size| size.checked_add(HEADER_SIZE))
+      .ok_or(Ktx2Error::Truncated)?;
+   if bytes.len() < level_index_end {
+      return Err(Ktx2Error::Truncated);
+   }
+   let mut data = Vec::new();
+   for level in 0..level_count {
+      let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+      let (offset, length) = (read_u64(entry), read_u64(entry + 8));
+      let expected = level_size(format, width, height, level);
+      if length != expected {
+         return Err(Ktx2Error::InvalidLevelLength { level, length, expected });
+      }
+      let level_data = offset.checked_add(length)
+         .and_then(|end| Some(usize::try_from(offset).ok()?..usize::try_from(end).ok()?))
+         .and_then(|range| bytes.get(range))
+         .ok_or(Ktx2Error::Truncated)?;
+      data.extend_from_slice(level_data);
+   }
+
+   Ok(TextureInfo {
+      data,
+      width,
+      height,
+      depth: 1,
+      format: PixelFormat::Compressed(format),
+      mip_level_count: level_count,
+   })
+}
+
+// bytes of mip `level`, blocks at its edges are stored whole even when the mip is smaller than a block
+fn level_size(format: wgpu::TextureFormat, width: u32, height: u32, level: u32) -> u64 {
+   let (block_width, block_height) = format.block_dimensions();
+   let blocks = |size: u32, block_size: u32| (size >> level).max(1).div_ceil(block_size) as u64;
+   let block_bytes = format.block_copy_size(None).expect("KTX2 formats are color formats") as u64;
+   blocks(width, block_width) * blocks(height, block_height) * block_bytes
+}
+
+// Vulkan format enum values as stored in the KTX2 header
+fn vk_format_to_wgpu(vk_format: u32) -> Option<wgpu::TextureFormat> {
+   use wgpu::TextureFormat::*;
+   use wgpu::{AstcBlock, AstcChannel};
+   let format = match vk_format {
+      37 => Rgba8Unorm,
+      43 => Rgba8UnormSrgb,
+      // BC1 RGB is read as RGBA, alpha of opaque blocks is 1
+      131 | 133 => Bc1RgbaUnorm,
+      132 | 134 => Bc1RgbaUnormSrgb,
+      135 => Bc2RgbaUnorm,
+      136 => Bc2RgbaUnormSrgb,
+      137 => Bc3RgbaUnorm,
+      138 => Bc3RgbaUnormSrgb,
+      139 => Bc4RUnorm,
+      140 => Bc4RSnorm,
+      141 => Bc5RgUnorm,
+      142 => Bc5RgSnorm,
+      143 => Bc6hRgbUfloat,
+      144 => Bc6hRgbFloat,
+      145 => Bc7RgbaUnorm,
+      146 => Bc7RgbaUnormSrgb,
+      147 => Etc2Rgb8Unorm,
+      148 => Etc2Rgb8UnormSrgb,
+      149 => Etc2Rgb8A1Unorm,
+      150 => Etc2Rgb8A1UnormSrgb,
+      151 => Etc2Rgba8Unorm,
+      152 => Etc2Rgba8UnormSrgb,
+      153 => EacR11Unorm,
+      154 => EacR11Snorm,
+      155 => EacRg11Unorm,
+      156 => EacRg11Snorm,
+      // ASTC LDR blocks go in pairs of UNORM and SRGB, in the same order as `AstcBlock`
+      157..=184 => {
+         let blocks = [
+            AstcBlock::B4x4, AstcBlock::B5x4, AstcBlock::B5x5, AstcBlock::B6x5,
+            AstcBlock::B6x6, AstcBlock::B8x5, AstcBlock::B8x6, AstcBlock::B8x8,
+            AstcBlock::B10x5, AstcBlock::B10x6, AstcBlock::B10x8, AstcBlock::B10x10,
+            AstcBlock::B12x10, AstcBlock::B12x12,
+         ];
+         let index = (vk_format - 157) as usize;
+         let channels = [AstcChannel::Unorm, AstcChannel::UnormSrgb];
+         Astc { block: blocks[index / 2], channel: channels[index % 2] }
+      },
+      _ => return None,
+   };
+   Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn ktx2_bytes(vk_format: u32, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+      let mut header = IDENTIFIER.to_vec();
+      for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, SUPERCOMPRESSION_NONE] {
+         header.extend_from_slice(&value.to_le_bytes());
+      }
+      header.resize(HEADER_SIZE, 0);
+      // levels are written smallest first, as KTX2 recommends
+      let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+      let mut offsets = vec![0; levels.len()];
+      for (level, data) in levels.iter().enumerate().rev() {
+         offsets[level] = offset;
+         offset += data.len();
+      }
+      for (level, data) in levels.iter().enumerate() {
+         for value in [offsets[level] as u64, data.len() as u64, data.len() as u64] {
+            header.extend_from_slice(&value.to_le_bytes());
+         }
+      }
+      for data in levels.iter().rev() {
+         header.extend_from_slice(data);
+      }
+      header
+   }
+
+   #[test]
+   fn parse_ktx2_levels() {
+      // 8x4 BC7, two 4x4 blocks in the base level and one block in each of the next two levels
+      let (level0, level1, level2) = ([0_u8; 32], [1_u8; 16], [2_u8; 16]);
+      let bytes = ktx2_bytes(146, 8, 4, &[&level0, &level1, &level2]);
+      let info = parse_ktx2(&bytes).unwrap();
+      assert_eq!(info.format, PixelFormat::Compressed(wgpu::TextureFormat::Bc7RgbaUnormSrgb));
+      assert_eq!((info.width, info.height, info.mip_level_count), (8, 4, 3));
+      assert_eq!(info.data, [&level0[..], &level1, &level2].concat());
+
+      assert!(matches!(parse_ktx2(&ktx2_bytes(0, 8, 4, &[&level0])), Err(Ktx2Error::BasisUniversal)));
+      assert!(matches!(parse_ktx2(&ktx2_bytes(146, 6, 4, &[&level0])), Err(Ktx2Error::NotBlockAligned { .. })));
+      assert!(matches!(parse_ktx2(&ktx2_bytes(146, 0, 4, &[&level0])), Err(Ktx2Error::ZeroWidth)));
+      assert!(matches!(parse_ktx2(&bytes[..bytes.len() - 1]), Err(Ktx2Error::Truncated)));
+      // 8x4 halves into 4 levels at most
+      assert!(matches!(parse_ktx2(&ktx2_bytes(146, 8, 4, &[&level0, &level1, &level2, &level2, &level2])),
+         Err(Ktx2Error::InvalidLevelCount { levels: 5, max: 4 })));
+      assert!(matches!(parse_ktx2(&ktx2_bytes(146, 8, 4, &[&level0, &level0])),
+         Err(Ktx2Error::InvalidLevelLength { level: 1, length: 32, expected: 16 })));
+      assert!(matches!(parse_ktx2(b"\x89PNG"), Err(Ktx2Error::InvalidIdentifier)));
+   }
+}
@@ -2,7 +2,11 @@ pub mod js_interop;
 pub mod renderer;
 pub mod timer;
 pub mod env;
+pub mod asset_source;
+pub mod asset_pack;
 pub mod image_loader;
+pub mod ktx2_loader;
+pub mod hash;
 
 use std::sync::Mutex;
 
@@ -73,6 +77,7 @@ impl std::task::Wake for SimpleWaker {
 #[cfg(feature = "web")]
 mod wasm {
 
+use crate::asset_source::{AssetSources, FetchSource};
 use crate::env::log_init;
 use crate::renderer::{self, Premade, LoadingArgs, RenderArgs};
 use crate::renderer::{handle_keyboard, FrameStateRef};
@@ -143,8 +148,10 @@ impl From<u32> for GraphicsLevel {
 #[wasm_bindgen]
 impl WasmInterface {
 
+    // With `shader_base_url` shader files are fetched from "<shader_base_url>shaders/..." instead of
+    // using the ones built in, so they can be edited and reloaded with `reloadShaders`
     #[wasm_bindgen(constructor)]
-    pub async fn new(canvas_dom_id: &str, canvas_parent_element: JsValue, level: GraphicsLevel) -> Result<WasmInterface, JsValue> {
+    pub async fn new(canvas_dom_id: &str, canvas_parent_element: JsValue, level: GraphicsLevel, shader_base_url: Option<String>) -> Result<WasmInterface, JsValue> {
         log_init();
         log::warn!("WasmInterface::new");
 
@@ -177,10 +184,17 @@ impl WasmInterface {
         }
 
         demo_loading_apply_progress(0.6);
+        let premade = Premade::new(&webgpu.device);
+        if let Some(shader_base_url) = shader_base_url {
+            premade.shader_loader.borrow_mut().use_remote_sources(
+                AssetSources::new().with_source(FetchSource::new(&shader_base_url)));
+            let fetch = premade.shader_loader.borrow().fetch_remote_shaders();
+            let fetched = fetch.await;
+            log::info!("Fetched {} shader files from '{shader_base_url}'", fetched.len());
+        }
         demo_loading_apply_progress(0.7);
         demo_loading_finish();
 
-        let premade = Premade::new(&webgpu.device);
         Ok(Self {
             canvas,
             webgpu: Rc::new(webgpu),
@@ -291,6 +305,34 @@ impl WasmInterface {
         js_interop::request_animation_frame(&js_interop::window(), switcher_callback.borrow().as_ref().unwrap());
     }
 
+    // Fetches the shader files again and rebuilds the pipelines of the current demo if any changed.
+    // Resolves to the number of changed files, shaders which don't compile keep their last version
+    #[wasm_bindgen(js_name = reloadShaders)]
+    pub fn wasm_reload_shaders(&self) -> js_sys::Promise {
+        let fetch = self.premade.borrow().shader_loader.borrow().fetch_remote_shaders();
+        let premade = self.premade.clone();
+        let demo = self.demo.clone();
+        let loading_args = LoadingArgs {
+            webgpu: self.webgpu.clone(),
+            color_texture_format: self.webgpu_config.borrow().format,
+            premade: self.premade.clone(),
+            asset_loader: self.asset_loader.clone(),
+        };
+        wasm_bindgen_futures::future_to_promise(async move {
+            let changed = fetch.await;
+            {
+                let premade = premade.borrow();
+                let mut shader_loader = premade.shader_loader.borrow_mut();
+                changed.iter().for_each(|path| shader_loader.invalidate(path));
+            }
+            if !changed.is_empty() {
+                log::info!("Reloading shaders, changed: {}", changed.join(", "));
+                demo.borrow_mut().rebuild_pipelines(loading_args);
+            }
+            Ok(JsValue::from(changed.len() as u32))
+        })
+    }
+
     #[wasm_bindgen(js_name = startLoadingDemo)]
     pub fn wasm_start_loading_demo(&mut self, demo_id: DemoId) {
         let loader_callback = Rc::new(RefCell::new(None));
@@ -487,6 +529,8 @@ impl WasmInterface {
                 // swap buffers
                 surface_texture.present();
                 demo_state.dismiss_events();
+                // after the frame, so compiling doesn't delay it
+                premade.shader_loader.borrow_mut().precompile_next(&webgpu.device);
             }
             {
                 // setTimeout may overshoot the requested timeout, so compensate it by requesting less 
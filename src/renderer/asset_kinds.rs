@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use super::webgpu::buffer::VertexPosUvNormal;
+
+// A kind of asset `AssetLoader::load` can read, decoded from the bytes of its file
+pub trait Asset: Sized + 'static {
+   // runs inside the loading future, after all bytes are read
+   fn decode(path: &str, bytes: Vec<u8>) -> Result<Self, String>;
+
+   // bytes kept in memory, counted against the loader memory budget
+   fn memory_size(&self) -> usize;
+}
+
+// Raw bytes, e.g. vertex data prepared offline
+pub struct BinaryAsset(pub Vec<u8>);
+
+impl Asset for BinaryAsset {
+   fn decode(_path: &str, bytes: Vec<u8>) -> Result<Self, String> {
+      Ok(Self(bytes))
+   }
+
+   fn memory_size(&self) -> usize {
+      self.0.len()
+   }
+}
+
+// UTF-8 text of a WGSL file
+pub struct ShaderSourceAsset(pub String);
+
+impl Asset for ShaderSourceAsset {
+   fn decode(_path: &str, bytes: Vec<u8>) -> Result<Self, String> {
+      String::from_utf8(bytes).map(Self).map_err(|e| e.to_string())
+   }
+
+   fn memory_size(&self) -> usize {
+      self.0.len()
+   }
+}
+
+// Parameter files, e.g. material or light settings
+pub struct JsonAsset(pub serde_json::Value);
+
+impl JsonAsset {
+   pub fn get_f32(&self, pointer: &str) -> Option<f32> {
+      self.0.pointer(pointer)?.as_f64().map(|value| value as f32)
+   }
+}
+
+impl Asset for JsonAsset {
+   fn decode(_path: &str, bytes: Vec<u8>) -> Result<Self, String> {
+      serde_json::from_slice(&bytes).map(Self).map_err(|e| e.to_string())
+   }
+
+   fn memory_size(&self) -> usize {
+      // rough, the parsed value is about as large as its text
+      self.0.to_string().len()
+   }
+}
+
+// TrueType/OpenType or WOFF data for a text renderer
+pub struct FontAsset(pub Vec<u8>);
+
+impl Asset for FontAsset {
+   fn decode(path: &str, bytes: Vec<u8>) -> Result<Self, String> {
+      const SIGNATURES: [&[u8; 4]; 6] = [b"\x00\x01\x00\x00", b"OTTO", b"true", b"ttcf", b"wOFF", b"wOF2"];
+      match SIGNATURES.iter().any(|signature| bytes.starts_with(*signature)) {
+         true => Ok(Self(bytes)),
+         false => Err(format!("{path} is not a font file")),
+      }
+   }
+
+   fn memory_size(&self) -> usize {
+      self.0.len()
+   }
+}
+
+// Triangle list read from a Wavefront OBJ file, vertices are deduplicated
+pub struct MeshAsset {
+   pub vertices: Vec<VertexPosUvNormal>,
+   pub indices: Vec<u32>,
+}
+
+impl Asset for MeshAsset {
+   fn decode(path: &str, bytes: Vec<u8>) -> Result<Self, String> {
+      let text = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+      let mut positions: Vec<[f32; 3]> = vec![];
+      let mut uvs: Vec<[f32; 2]> = vec![];
+      let mut normals: Vec<[f32; 3]> = vec![];
+      let mut mesh = MeshAsset { vertices: vec![], indices: vec![] };
+      let mut vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+      for (line_number, line) in text.lines().enumerate() {
+         let error = |message: &str| format!("{path}:{}: {message}", line_number + 1);
+         let mut tokens = line.split_whitespace();
+         let keyword = tokens.next();
+         let mut floats = |count: usize| -> Result<Vec<f32>, String> {
+            let values: Vec<f32> = tokens.by_ref()
+               .take(count)
+               .map(|token| token.parse::<f32>().map_err(|e| error(&e.to_string())))
+               .collect::<Result<_, _>>()?;
+            match values.len() == count {
+               true => Ok(values),
+               false => Err(error("not enough components")),
+            }
+         };
+         match keyword {
+            Some("v") => {
+               let v = floats(3)?;
+               positions.push([v[0], v[1], v[2]]);
+            },
+            Some("vt") => {
+               let vt = floats(2)?;
+               uvs.push([vt[0], vt[1]]);
+            },
+            Some("vn") => {
+               let vn = floats(3)?;
+               normals.push([vn[0], vn[1], vn[2]]);
+            },
+            Some("f") => {
+               // "v", "v/vt", "v//vn" or "v/vt/vn", 1-based, negative values count from the end
+               let corners = tokens
+                  .map(|corner| {
+                     let mut parts = corner.split('/');
+                     let mut index = |count: usize| -> Result<Option<usize>, String> {
+                        match parts.next().filter(|part| !part.is_empty()) {
+                           None => Ok(None),
+                           Some(part) => {
+                              let index: i64 = part.parse().map_err(|_| error("bad face index"))?;
+                              let index = if index < 0 { count as i64 + index } else { index - 1 };
+                              match (0..count as i64).contains(&index) {
+                                 true => Ok(Some(index as usize)),
+                                 false => Err(error("face index out of range")),
+                              }
+                           },
+                        }
+                     };
+                     let position = index(positions.len())?.ok_or_else(|| error("face without a position"))?;
+                     Ok((position, index(uvs.len())?, index(normals.len())?))
+                  })
+                  .collect::<Result<Vec<_>, String>>()?;
+               if corners.len() < 3 {
+                  return Err(error("face with less than 3 vertices"));
+               }
+               let corner_indices: Vec<u32> = corners.into_iter()
+                  .map(|key| *vertex_indices.entry(key).or_insert_with(|| {
+                     let (position, uv, normal) = key;
+                     mesh.vertices.push(VertexPosUvNormal {
+                        position: positions[position],
+                        uv: uv.map_or([0.0; 2], |uv| uvs[uv]),
+                        normal: normal.map_or([0.0, 0.0, 1.0], |normal| normals[normal]),
+                     });
+                     mesh.vertices.len() as u32 - 1
+                  }))
+                  .collect();
+               // polygons are split into a fan
+               for i in 1..corner_indices.len() - 1 {
+                  mesh.indices.extend([corner_indices[0], corner_indices[i], corner_indices[i + 1]]);
+               }
+            },
+            // groups, materials and smoothing aren't used
+            _ => {},
+         }
+      }
+      Ok(mesh)
+   }
+
+   fn memory_size(&self) -> usize {
+      std::mem::size_of_val(self.vertices.as_slice()) + std::mem::size_of_val(self.indices.as_slice())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn obj_faces_are_triangulated() {
+      let obj = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\n\
+         f 1/1/1 2/1/1 3/2/1 4/2/1\nf -4//1 -2//1 -1//1\n";
+      let mesh = MeshAsset::decode("quad.obj", obj.to_vec()).unwrap();
+      assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+      assert_eq!(mesh.vertices.len(), 7);
+      assert_eq!(mesh.vertices[2].uv, [1.0, 1.0]);
+      let error = MeshAsset::decode("bad.obj", b"v 0 0 0\nf 1 2 3\n".to_vec()).err().unwrap();
+      assert_eq!(error, "bad.obj:2: face index out of range");
+   }
+}
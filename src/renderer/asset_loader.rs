@@ -1,81 +1,774 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::task::Poll;
+use std::marker::PhantomData;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Wake, Waker};
 use std::pin::Pin;
 
-use futures::Future;
+use futures::future::LocalBoxFuture;
+use futures::{Future, FutureExt};
 
-use crate::image_loader::{self, TextureInfo};
+use crate::asset_source::{AssetError, AssetSources};
+use crate::image_loader::{self, LoadProgress, PixelFormat, TextureInfo};
+use crate::ktx2_loader;
+use crate::timer;
+
+use super::asset_kinds::Asset;
+use super::mipmap;
+use super::webgpu::texture::TextureBuilder;
+use super::{LoadingArgs, Progress};
 
 pub struct AssetLoader {
+   sources: Rc<AssetSources>,
    textures: HashMap<AssetGUID, TextureAsset>,
    textures_guids: HashMap<String, AssetGUID>,
-   textures_loading: HashSet<AssetGUID>,
+   // assets of other kinds, deduplicated by kind and path
+   assets: HashMap<AssetGUID, ErasedAsset>,
+   assets_guids: HashMap<(TypeId, String), AssetGUID>,
+   // GUIDs of both textures and other assets
+   loading: HashSet<AssetGUID>,
+   load_progress: HashMap<AssetGUID, Rc<LoadProgress>>,
+   // how to read each asset again, and the reloads in flight
+   loaders: HashMap<AssetGUID, Loader>,
+   reloading: HashMap<AssetGUID, Reloading>,
+   generations: HashMap<AssetGUID, u64>,
+   reload_count: u64,
+   // why a texture shows the error texture instead of its data
+   errors: HashMap<AssetGUID, AssetError>,
+   priorities: HashMap<AssetGUID, LoadPriority>,
+   // the loader keeps one reference, the rest are held by `Handle`s
+   handles: HashMap<AssetGUID, Rc<AssetGUID>>,
+   last_used: HashMap<AssetGUID, u64>,
+   use_clock: u64,
+   memory_budget_bytes: usize,
+   // loading futures which were woken since their last poll
+   woken: Arc<Mutex<HashSet<AssetGUID>>>,
    free_guid: AssetGUID,
+   generate_mipmaps: bool,
+   frame_budget_ms: f64,
+   // GPU uploads since the last tick, they take from the next polling budget
+   upload_spent_ms: f64,
+}
+
+// Order in which woken loads are polled when the frame budget doesn't fit all of them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoadPriority {
+   Background,
+   #[default] Normal,
+   Urgent,
+}
+
+// Wakes a single loading future, and the task ticking the loader
+struct AssetWaker {
+   guid: AssetGUID,
+   woken: Arc<Mutex<HashSet<AssetGUID>>>,
+   parent: Waker,
+}
+
+impl Wake for AssetWaker {
+   fn wake(self: Arc<Self>) {
+      self.wake_by_ref();
+   }
+
+   fn wake_by_ref(self: &Arc<Self>) {
+      self.woken.lock().unwrap().insert(self.guid);
+      self.parent.wake_by_ref();
+   }
 }
 
 impl AssetLoader {
    pub fn new() -> Self {
       Self {
+         sources: Rc::new(AssetSources::assets()),
          textures: HashMap::new(),
          textures_guids: HashMap::new(),
-         textures_loading: HashSet::new(),
+         assets: HashMap::new(),
+         assets_guids: HashMap::new(),
+         loading: HashSet::new(),
+         load_progress: HashMap::new(),
+         loaders: HashMap::new(),
+         reloading: HashMap::new(),
+         generations: HashMap::new(),
+         reload_count: 0,
+         errors: HashMap::new(),
+         priorities: HashMap::new(),
+         handles: HashMap::new(),
+         last_used: HashMap::new(),
+         use_clock: 0,
+         memory_budget_bytes: 256 << 20,
+         woken: Default::default(),
          free_guid: AssetGUID(1),
+         generate_mipmaps: true,
+         frame_budget_ms: 8.0,
+         upload_spent_ms: 0.0,
+      }
+   }
+
+   // where the following loads read their files from
+   pub fn set_sources(&mut self, sources: AssetSources) {
+      self.sources = Rc::new(sources);
+   }
+
+   // time a tick may spend on loading, at least one future is polled per tick
+   pub fn set_frame_budget_ms(&mut self, frame_budget_ms: f64) {
+      self.frame_budget_ms = frame_budget_ms;
+   }
 
+   // unreferenced textures are evicted, least recently used first, while their data exceeds the budget
+   pub fn set_memory_budget_bytes(&mut self, memory_budget_bytes: usize) {
+      self.memory_budget_bytes = memory_budget_bytes;
+   }
+
+   pub fn set_priority(&mut self, guid: AssetGUID, priority: LoadPriority) {
+      if self.loading.contains(&guid) {
+         self.priorities.insert(guid, priority);
       }
    }
 
+   // textures created by `create_gpu_texture` get a full mip chain, unless disabled
+   pub fn set_generate_mipmaps(&mut self, generate_mipmaps: bool) {
+      self.generate_mipmaps = generate_mipmaps;
+   }
+
+   // Polls the woken loads by priority, then by request order, until the frame budget runs out
    pub fn tick_loading(&mut self, cx: &mut std::task::Context<'_>) {
-      if self.textures_loading.is_empty() {
+      let budget_ms = self.frame_budget_ms - std::mem::take(&mut self.upload_spent_ms);
+      self.collect_garbage();
+      self.poll_reloads(cx);
+      if self.loading.is_empty() {
          return;
       }
-      let guid = self.textures_loading.iter().take(1).next().unwrap().to_owned();
-      // TODO: self.textures_get_many !
-      let asset = self.textures.get_mut(&guid)
-         .expect("BUG in AssetLoader - self.textures_loading contains GUID of non-existing texture");
-      if let TextureAsset::Loading(future) = asset {
-         if let Poll::Ready(texture) = future.as_mut().poll(cx) {
-            *asset = TextureAsset::Texture(texture);
-            self.textures_loading.remove(&guid);
-            log::warn!("AssetLoader texture loaded GUID:{} res:{:?}", guid.0, asset.dimensions());
-         } else {
-            log::warn!("AssetLoader texture in progress GUID:{}", guid.0);
+      let mut woken: Vec<AssetGUID> = self.woken.lock().unwrap()
+         .drain()
+         .filter(|guid| self.loading.contains(guid))
+         .collect();
+      woken.sort_by_key(|guid| (std::cmp::Reverse(self.priorities.get(guid).copied().unwrap_or_default()), *guid));
+
+      let start_ms = timer::now_ms();
+      for (i, guid) in woken.iter().copied().enumerate() {
+         if i > 0 && timer::now_ms() - start_ms >= budget_ms {
+            // the rest waits for the next tick
+            self.woken.lock().unwrap().extend(&woken[i..]);
+            break;
+         }
+         let waker = Waker::from(Arc::new(AssetWaker {
+            guid,
+            woken: self.woken.clone(),
+            parent: cx.waker().clone(),
+         }));
+         let mut cx = std::task::Context::from_waker(&waker);
+         let ready = match self.textures.get_mut(&guid) {
+            Some(TextureAsset::Loading(future)) => match future.as_mut().poll(&mut cx) {
+               Poll::Ready(Ok(texture)) => {
+                  log::warn!("AssetLoader texture loaded GUID:{} res:{:?}", guid.0, (texture.width, texture.height, texture.depth));
+                  self.textures.insert(guid, TextureAsset::Texture(texture));
+                  true
+               },
+               Poll::Ready(Err(e)) => {
+                  log::error!("AssetLoader failed to load texture GUID:{}, using the error texture: {}", guid.0, e);
+                  self.textures.insert(guid, TextureAsset::Texture(TextureInfo::error_texture()));
+                  self.errors.insert(guid, e);
+                  true
+               },
+               Poll::Pending => false,
+            },
+            _ => match self.assets.get_mut(&guid) {
+               Some(ErasedAsset::Loading(future)) => match future.poll_unpin(&mut cx) {
+                  Poll::Ready(loaded) => {
+                     let asset = match loaded {
+                        Ok((asset, memory_size)) => ErasedAsset::Loaded { asset, memory_size },
+                        Err(e) => {
+                           log::error!("AssetLoader failed to load GUID:{}: {}", guid.0, e);
+                           ErasedAsset::Failed(e)
+                        },
+                     };
+                     self.assets.insert(guid, asset);
+                     true
+                  },
+                  Poll::Pending => false,
+               },
+               _ => panic!("BUG in AssetLoader - self.loading contains GUID of non-existing asset"),
+            },
+         };
+         if ready {
+            self.loading.remove(&guid);
+            self.load_progress.remove(&guid);
+            self.priorities.remove(&guid);
+            self.touch(guid);
+         }
+      }
+   }
+
+   // Reads the asset again, its current data stays available until the new one is decoded.
+   // Dependents compare `generation` to notice the swap
+   pub fn reload(&mut self, guid: AssetGUID) {
+      if self.loading.contains(&guid) {
+         return;
+      }
+      let Some(loader) = self.loaders.get(&guid) else {
+         return;
+      };
+      log::info!("AssetLoader reloading GUID:{}", guid.0);
+      let progress = Rc::new(LoadProgress::default());
+      let reloading = match loader {
+         Loader::Texture(load) => Reloading::Texture(load(self.sources.clone(), progress)),
+         Loader::Erased(load) => Reloading::Erased(load(self.sources.clone(), progress)),
+      };
+      // a newer change of the file replaces the reload in flight
+      self.reloading.insert(guid, reloading);
+   }
+
+   // bumped every time a reload swaps the asset data
+   pub fn generation(&self, guid: AssetGUID) -> u64 {
+      self.generations.get(&guid).copied().unwrap_or_default()
+   }
+
+   fn poll_reloads(&mut self, cx: &mut std::task::Context<'_>) {
+      let mut finished = vec![];
+      for (guid, reloading) in self.reloading.iter_mut() {
+         let reloaded = match reloading {
+            Reloading::Texture(future) => future.as_mut().poll(cx).map(Reloaded::Texture),
+            Reloading::Erased(future) => future.poll_unpin(cx).map(Reloaded::Erased),
+         };
+         if let Poll::Ready(reloaded) = reloaded {
+            finished.push((*guid, reloaded));
+         }
+      }
+      for (guid, reloaded) in finished {
+         self.reloading.remove(&guid);
+         match reloaded {
+            Reloaded::Texture(Ok(texture)) => {
+               self.textures.insert(guid, TextureAsset::Texture(texture));
+            },
+            Reloaded::Erased(Ok((asset, memory_size))) => {
+               self.assets.insert(guid, ErasedAsset::Loaded { asset, memory_size });
+            },
+            Reloaded::Texture(Err(e)) | Reloaded::Erased(Err(e)) => {
+               log::error!("AssetLoader failed to reload GUID:{}, keeping the previous data: {}", guid.0, e);
+               continue;
+            },
+         }
+         log::info!("AssetLoader reloaded GUID:{}", guid.0);
+         self.errors.remove(&guid);
+         *self.generations.entry(guid).or_default() += 1;
+         self.reload_count += 1;
+      }
+   }
+
+   // why the asset failed to load, failed textures are replaced by a checkerboard
+   pub fn error(&self, guid: AssetGUID) -> Option<&AssetError> {
+      match self.assets.get(&guid) {
+         Some(ErasedAsset::Failed(e)) => Some(e),
+         _ => self.errors.get(&guid),
+      }
+   }
+
+   // reloads finished so far, a change means some asset data was swapped
+   pub fn reload_count(&self) -> u64 {
+      self.reload_count
+   }
+
+   // paths assets were requested by, e.g. to watch their files
+   pub fn asset_paths(&self) -> impl Iterator<Item=(AssetGUID, &str)> {
+      self.textures_guids.iter()
+         .map(|(path, guid)| (*guid, path.as_str()))
+         .chain(self.assets_guids.iter().map(|((_, path), guid)| (*guid, path.as_str())))
+   }
+
+   pub fn sources(&self) -> Rc<AssetSources> {
+      self.sources.clone()
+   }
+
+   // Drops a load in progress, its path can be requested again later
+   pub fn cancel(&mut self, guid: AssetGUID) {
+      if self.loading.contains(&guid) {
+         log::info!("AssetLoader cancelled GUID:{}", guid.0);
+         self.forget(guid);
+      }
+   }
+
+   // Loads nobody waits for are cancelled, then unreferenced textures are evicted
+   // in LRU order until their data fits into the memory budget
+   fn collect_garbage(&mut self) {
+      let abandoned: Vec<AssetGUID> = self.loading.iter()
+         .copied()
+         .filter(|guid| !self.is_referenced(*guid))
+         .collect();
+      for guid in abandoned {
+         self.cancel(guid);
+      }
+      let mut memory_usage = self.memory_usage();
+      if memory_usage <= self.memory_budget_bytes {
+         return;
+      }
+      let mut evictable: Vec<AssetGUID> = self.textures.keys()
+         .chain(self.assets.keys())
+         .copied()
+         .filter(|guid| !self.is_referenced(*guid) && !self.loading.contains(guid))
+         .collect();
+      evictable.sort_by_key(|guid| self.last_used.get(guid).copied().unwrap_or_default());
+      for guid in evictable {
+         if memory_usage <= self.memory_budget_bytes {
+            break;
          }
+         let size = self.memory_size(guid);
+         log::info!("AssetLoader evicted GUID:{} {} bytes", guid.0, size);
+         self.forget(guid);
+         memory_usage -= size;
+      }
+   }
+
+   // bytes of decoded asset data kept on CPU
+   pub fn memory_usage(&self) -> usize {
+      self.textures.keys()
+         .chain(self.assets.keys())
+         .map(|guid| self.memory_size(*guid))
+         .sum()
+   }
+
+   fn memory_size(&self, guid: AssetGUID) -> usize {
+      match (self.textures.get(&guid), self.assets.get(&guid)) {
+         (Some(texture), _) if !texture.is_loading() => texture.data().len(),
+         (_, Some(ErasedAsset::Loaded { memory_size, .. })) => *memory_size,
+         _ => 0,
+      }
+   }
+
+   pub fn contains(&self, guid: AssetGUID) -> bool {
+      self.textures.contains_key(&guid) || self.assets.contains_key(&guid)
+   }
+
+   pub fn is_loading(&self, guid: AssetGUID) -> bool {
+      self.loading.contains(&guid)
+   }
+
+   // whether any `Handle` to the asset is alive
+   pub fn is_referenced(&self, guid: AssetGUID) -> bool {
+      self.handles.get(&guid).is_some_and(|handle| Rc::strong_count(handle) > 1)
+   }
+
+   fn handle<T>(&mut self, guid: AssetGUID) -> Handle<T> {
+      self.touch(guid);
+      let guid = self.handles.entry(guid).or_insert_with(|| Rc::new(guid));
+      Handle { guid: guid.clone(), _asset: PhantomData }
+   }
+
+   fn touch(&mut self, guid: AssetGUID) {
+      self.use_clock += 1;
+      self.last_used.insert(guid, self.use_clock);
+   }
+
+   // removes every trace of the asset, outstanding handles won't resolve anymore
+   fn forget(&mut self, guid: AssetGUID) {
+      self.textures.remove(&guid);
+      self.assets.remove(&guid);
+      self.loading.remove(&guid);
+      self.load_progress.remove(&guid);
+      self.loaders.remove(&guid);
+      self.reloading.remove(&guid);
+      self.generations.remove(&guid);
+      self.errors.remove(&guid);
+      self.priorities.remove(&guid);
+      self.handles.remove(&guid);
+      self.last_used.remove(&guid);
+      self.textures_guids.retain(|_, path_guid| *path_guid != guid);
+      self.assets_guids.retain(|_, path_guid| *path_guid != guid);
+   }
+
+   // Reads and decodes any `Asset` kind, the same path of the same kind shares one GUID
+   pub fn load<T: Asset>(&mut self, path: String) -> Handle<T> {
+      let key = (TypeId::of::<T>(), path);
+      if let Some(guid) = self.assets_guids.get(&key) {
+         return self.handle(*guid);
+      }
+      let guid = self.free_guid;
+      self.free_guid.0 += 1;
+      let path = key.1.clone();
+      log::info!("Loading {} asset: {}, GUID={}", std::any::type_name::<T>(), path, guid.0);
+      self.assets_guids.insert(key, guid);
+      let load: Rc<dyn Fn(Rc<AssetSources>, Rc<LoadProgress>) -> ErasedLoading> = Rc::new(move |sources, progress| {
+         let path = path.clone();
+         async move {
+            let bytes = sources.read(&path, &progress).await?;
+            let asset = T::decode(&path, bytes)
+               .map_err(|reason| AssetError::DecodeFailed { path: path.clone(), reason })?;
+            progress.set_decoded();
+            let memory_size = asset.memory_size();
+            Ok((Box::new(asset) as Box<dyn Any>, memory_size))
+         }.boxed_local()
+      });
+      let progress = self.track_progress(guid);
+      self.assets.insert(guid, ErasedAsset::Loading(load(self.sources.clone(), progress)));
+      self.loaders.insert(guid, Loader::Erased(load));
+      self.loading.insert(guid);
+      self.woken.lock().unwrap().insert(guid);
+      self.handle(guid)
+   }
+
+   // None while the asset is loading, or after it was unloaded
+   pub fn get<T: Asset>(&mut self, handle: &Handle<T>) -> Option<Result<&T, &AssetError>> {
+      let guid = handle.guid();
+      if self.assets.contains_key(&guid) {
+         self.touch(guid);
+      }
+      match self.assets.get(&guid)? {
+         ErasedAsset::Loading(_) => None,
+         ErasedAsset::Loaded { asset, .. } => asset.downcast_ref::<T>().map(Ok),
+         ErasedAsset::Failed(e) => Some(Err(e)),
       }
    }
 
-   pub fn load_texture(&mut self, image_path: String) -> AssetGUID {
+   // keeps `load` for reloads
+   fn start_texture(&mut self, guid: AssetGUID, load: TextureLoader) {
+      let progress = self.track_progress(guid);
+      self.start_loading(guid, load(self.sources.clone(), progress));
+      self.loaders.insert(guid, Loader::Texture(load));
+   }
+
+   fn start_loading(&mut self, guid: AssetGUID, loading: TextureLoading) {
+      self.textures.insert(guid, TextureAsset::Loading(loading));
+      self.loading.insert(guid);
+      // the first poll starts the load
+      self.woken.lock().unwrap().insert(guid);
+   }
+
+   pub fn load_texture(&mut self, image_path: String) -> Handle<TextureAsset> {
       if !self.textures_guids.contains_key(&image_path) {
          let guid = self.free_guid;
          log::info!("Loading texture asset: {}, GUID={}", image_path, guid.0);
          self.textures_guids.insert(image_path.clone(), guid);
-         let loading = Box::pin(image_loader::load_image_rgba8(image_path));
-         self.textures.insert(guid, TextureAsset::Loading(loading));
-         self.textures_loading.insert(guid);
+         self.start_texture(guid, Rc::new(move |sources, progress|
+            Box::pin(image_loader::load_image_rgba8(sources, image_path.clone(), progress))));
+         self.free_guid.0 += 1;
+         self.handle(guid)
+      } else {
+         self.handle(self.textures_guids[&image_path])
+      }
+   }
+
+   // .hdr/.exr images, `format` is PixelFormat::Rgba16Float or PixelFormat::Rgba32Float
+   pub fn load_texture_float(&mut self, image_path: String, format: PixelFormat) -> Handle<TextureAsset> {
+      if !self.textures_guids.contains_key(&image_path) {
+         let guid = self.free_guid;
+         log::info!("Loading texture asset: {}, GUID={}, {:?}", image_path, guid.0, format);
+         self.textures_guids.insert(image_path.clone(), guid);
+         self.start_texture(guid, Rc::new(move |sources, progress|
+            Box::pin(image_loader::load_image_float(sources, image_path.clone(), format, progress))));
          self.free_guid.0 += 1;
-         guid
+         self.handle(guid)
       } else {
-         self.textures_guids[&image_path]
+         self.handle(self.textures_guids[&image_path])
+      }
+   }
+
+   // Picks the KTX2 variant the device can sample, or decodes the fallback image to RGBA8
+   // when there is none or it fails to load
+   pub fn load_texture_compressed(&mut self, sources: CompressedTextureSources, features: wgpu::Features) -> Handle<TextureAsset> {
+      if let Some(guid) = self.textures_guids.get(&sources.fallback) {
+         return self.handle(*guid);
       }
+      let guid = self.free_guid;
+      let chosen = sources.choose(features);
+      match &chosen {
+         Some((family, path)) => log::info!("Loading texture asset: {}, GUID={}, {} KTX2 {}", sources.fallback, guid.0, family, path),
+         None => log::info!("Loading texture asset: {}, GUID={}, no supported compressed format, decoded RGBA8", sources.fallback, guid.0),
+      }
+      self.textures_guids.insert(sources.fallback.clone(), guid);
+      let chosen = chosen.map(|(_, path)| path.to_owned());
+      let fallback = sources.fallback;
+      self.start_texture(guid, Rc::new(move |asset_sources, progress| {
+         let (chosen, fallback) = (chosen.clone(), fallback.clone());
+         Box::pin(async move {
+            if let Some(path) = chosen {
+               match ktx2_loader::load_ktx2(&asset_sources, path.clone(), features, &progress).await {
+                  Ok(info) => return Ok(info),
+                  Err(e) => log::warn!("AssetLoader failed to load {}: {}, falling back to decoded RGBA8 {}", path, e, fallback),
+               }
+               progress.reset();
+            }
+            image_loader::load_image_rgba8(asset_sources, fallback, progress).await
+         })
+      }));
+      self.free_guid.0 += 1;
+      self.handle(guid)
+   }
+
+   fn track_progress(&mut self, guid: AssetGUID) -> Rc<LoadProgress> {
+      let progress = Rc::new(LoadProgress::default());
+      self.load_progress.insert(guid, progress.clone());
+      progress
+   }
+
+   // normalized 0.0 - 1.0, fetched bytes weigh the most
+   pub fn progress(&self, guid: AssetGUID) -> f32 {
+      if self.loading.contains(&guid) {
+         self.load_progress.get(&guid).map_or(0.0, |progress| progress.fraction())
+      } else if self.contains(guid) {
+         1.0
+      } else {
+         0.0
+      }
+   }
+
+   // fetched and total bytes of an asset still loading, the total is None while unknown
+   pub fn bytes_progress(&self, guid: AssetGUID) -> Option<(u64, Option<u64>)> {
+      self.load_progress.get(&guid)
+         .map(|progress| (progress.loaded_bytes(), progress.total_bytes()))
    }
 
+   // Frees the texture right away, even if it's still referenced
    pub fn unload_texture(&mut self, guid: AssetGUID) {
-      self.textures.remove(&guid);
+      self.forget(guid);
    }
 
    pub fn get_texture(&mut self, guid: AssetGUID) -> Option<&TextureAsset> {
+      if self.textures.contains_key(&guid) {
+         self.touch(guid);
+      }
       self.textures.get(&guid)
    }
+
+   // Uploads a loaded texture, returns None while it's still loading.
+   // `format` is used for 8 bit data, float and compressed data keeps its own format.
+   // Mips are rendered on GPU when the format allows it, otherwise they're computed on CPU
+   // and kept in the asset as `TextureAsset::TextureLod` for later uploads
+   pub fn create_gpu_texture(&mut self, guid: AssetGUID, loading_args: &LoadingArgs, format: wgpu::TextureFormat, label: Option<&str>) -> Option<wgpu::Texture> {
+      let webgpu = loading_args.webgpu.as_ref();
+      let start_ms = timer::now_ms();
+      if self.textures.contains_key(&guid) {
+         self.touch(guid);
+      }
+      let asset = self.textures.get_mut(&guid)?;
+      let info = match asset {
+         TextureAsset::Loading(_) => return None,
+         TextureAsset::Texture(info) | TextureAsset::TextureLod(info) => info,
+      };
+      let requested_format = format;
+      let format = info.format.texture_format(requested_format);
+      if format != requested_format {
+         log::warn!("AssetLoader GUID:{} is {:?}, requested {:?}", guid.0, format, requested_format);
+      }
+      // compressed textures and files with mips are uploaded as is
+      let generate_mipmaps = self.generate_mipmaps && info.mip_level_count == 1
+         && !matches!(info.format, PixelFormat::Compressed(_));
+      let gpu_mipmaps = generate_mipmaps && mipmap::supports_gpu_mipmaps(&webgpu.device, format);
+      if generate_mipmaps && !gpu_mipmaps {
+         if info.format == PixelFormat::Rgba8 && mipmap::supports_cpu_mipmaps(format) && info.depth == 1 {
+            log::info!("AssetLoader CPU mipmaps GUID:{} format:{:?}", guid.0, format);
+            *asset = TextureAsset::TextureLod(mipmap::generate_mipmaps_cpu(info, format.is_srgb()));
+         } else {
+            log::warn!("AssetLoader can't generate mipmaps GUID:{} format:{:?}", guid.0, format);
+         }
+      }
+      let texture = match asset {
+         TextureAsset::Loading(_) => return None,
+         TextureAsset::Texture(info) if gpu_mipmaps => {
+            let size = wgpu::Extent3d {
+               width: info.width, height: info.height, depth_or_array_layers: info.depth,
+            };
+            let texture = TextureBuilder::new_2d(size, format)
+               .with_mip_level_count(mipmap::full_mip_level_count(info.width, info.height))
+               .add_usage(wgpu::TextureUsages::TEXTURE_BINDING
+                  | wgpu::TextureUsages::RENDER_ATTACHMENT
+                  | wgpu::TextureUsages::COPY_DST)
+               .with_label(label)
+               .build(&webgpu.device);
+            webgpu.queue.write_texture(
+               texture.as_image_copy(),
+               &info.data,
+               wgpu::ImageDataLayout {
+                  offset: 0,
+                  bytes_per_row: Some(info.width * info.pixel_stride()),
+                  rows_per_image: Some(info.height),
+               },
+               size);
+            let mut encoder = webgpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+               label: Some("Mipmap Encoder"),
+            });
+            mipmap::generate_mipmaps_gpu(loading_args, &mut encoder, &texture);
+            webgpu.queue.submit(std::iter::once(encoder.finish()));
+            texture
+         },
+         TextureAsset::Texture(info) | TextureAsset::TextureLod(info) => {
+            TextureBuilder::new_2d(wgpu::Extent3d {
+                  width: info.width, height: info.height, depth_or_array_layers: info.depth,
+               }, format)
+               .with_mip_level_count(info.mip_level_count)
+               .add_usage(wgpu::TextureUsages::TEXTURE_BINDING)
+               .with_label(label)
+               .build_with_data(&webgpu.device, &webgpu.queue, &info.data)
+         },
+      };
+      self.upload_spent_ms += timer::now_ms() - start_ms;
+      Some(texture)
+   }
+}
+
+// Resolves once all the assets are loaded. Polling it ticks the loader,
+// so a loading stage can wait on its dependencies without relying on the render loop
+pub struct AssetsReady {
+   asset_loader: Rc<RefCell<AssetLoader>>,
+   handles: Vec<UntypedHandle>,
+}
+
+impl AssetsReady {
+   pub fn new(asset_loader: Rc<RefCell<AssetLoader>>, handles: impl IntoIterator<Item=impl Into<UntypedHandle>>) -> Self {
+      Self {
+         asset_loader,
+         handles: handles.into_iter().map(Into::into).collect(),
+      }
+   }
+}
+
+impl Progress for AssetsReady {
+   fn progress(&self) -> f32 {
+      if self.handles.is_empty() {
+         return 1.0;
+      }
+      let asset_loader = self.asset_loader.borrow();
+      let sum: f32 = self.handles.iter()
+         .map(|handle| asset_loader.progress(handle.guid()))
+         .sum();
+      sum / self.handles.len() as f32
+   }
+}
+
+impl Future for AssetsReady {
+   type Output = ();
+
+   fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+      let mut asset_loader = self.asset_loader.borrow_mut();
+      asset_loader.tick_loading(cx);
+      // an unloaded asset won't ever be ready, it's reported by whoever uses it
+      let pending = self.handles.iter()
+         .any(|handle| asset_loader.is_loading(handle.guid()));
+      if pending { Poll::Pending } else { Poll::Ready(()) }
+   }
+}
+
+// The same texture encoded for each GPU compression family, paths of KTX2 files
+pub struct CompressedTextureSources {
+   pub bc: Option<String>,
+   pub etc2: Option<String>,
+   pub astc: Option<String>,
+   // decodable image, used when the device supports none of the above
+   pub fallback: String,
+}
+
+impl CompressedTextureSources {
+   // BC is preferred on desktop, ETC2 and ASTC are mostly found on mobile GPUs
+   pub fn choose(&self, features: wgpu::Features) -> Option<(&'static str, &str)> {
+      [
+         ("BC", wgpu::Features::TEXTURE_COMPRESSION_BC, &self.bc),
+         ("ETC2", wgpu::Features::TEXTURE_COMPRESSION_ETC2, &self.etc2),
+         ("ASTC", wgpu::Features::TEXTURE_COMPRESSION_ASTC, &self.astc),
+      ].into_iter()
+         .filter(|(_, feature, _)| features.contains(*feature))
+         .find_map(|(family, _, path)| path.as_deref().map(|path| (family, path)))
+   }
 }
 
 #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct AssetGUID(usize);
+
+// Keeps the asset from being evicted, cloning is cheap
+pub struct Handle<T> {
+   guid: Rc<AssetGUID>,
+   _asset: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+   pub fn guid(&self) -> AssetGUID {
+      *self.guid
+   }
+
+   pub fn downgrade(&self) -> WeakHandle<T> {
+      WeakHandle { guid: Rc::downgrade(&self.guid), _asset: PhantomData }
+   }
+}
+
+// Handle of any asset kind
+#[derive(Clone)]
+pub struct UntypedHandle {
+   guid: Rc<AssetGUID>,
+}
+
+impl UntypedHandle {
+   pub fn guid(&self) -> AssetGUID {
+      *self.guid
+   }
+}
+
+impl<T> From<Handle<T>> for UntypedHandle {
+   fn from(handle: Handle<T>) -> Self {
+      Self { guid: handle.guid }
+   }
+}
+
+impl<T> Clone for Handle<T> {
+   fn clone(&self) -> Self {
+      Self { guid: self.guid.clone(), _asset: PhantomData }
+   }
+}
+
+// Doesn't keep the asset alive, upgrades while the asset is still in the loader
+pub struct WeakHandle<T> {
+   guid: Weak<AssetGUID>,
+   _asset: PhantomData<fn() -> T>,
+}
+
+impl<T> WeakHandle<T> {
+   pub fn upgrade(&self) -> Option<Handle<T>> {
+      self.guid.upgrade().map(|guid| Handle { guid, _asset: PhantomData })
+   }
+}
+
+impl<T> Clone for WeakHandle<T> {
+   fn clone(&self) -> Self {
+      Self { guid: self.guid.clone(), _asset: PhantomData }
+   }
+}
+
+// resolves to the decoded asset and its memory size
+type ErasedLoading = LocalBoxFuture<'static, Result<(Box<dyn Any>, usize), AssetError>>;
+type TextureLoading = Pin<Box<dyn Future<Output=Result<TextureInfo, AssetError>>>>;
+type TextureLoader = Rc<dyn Fn(Rc<AssetSources>, Rc<LoadProgress>) -> TextureLoading>;
+
+// creates the loading future of an asset, once more for every reload
+enum Loader {
+   Texture(TextureLoader),
+   Erased(Rc<dyn Fn(Rc<AssetSources>, Rc<LoadProgress>) -> ErasedLoading>),
+}
+
+enum Reloading {
+   Texture(TextureLoading),
+   Erased(ErasedLoading),
+}
+
+enum Reloaded {
+   Texture(Result<TextureInfo, AssetError>),
+   Erased(Result<(Box<dyn Any>, usize), AssetError>),
+}
+
+enum ErasedAsset {
+   Loading(ErasedLoading),
+   Loaded { asset: Box<dyn Any>, memory_size: usize },
+   Failed(AssetError),
+}
+
 pub enum TextureAsset {
    Texture(TextureInfo),
    TextureLod(TextureInfo),
-   Loading(Pin<Box<dyn Future<Output=TextureInfo>>>),
+   Loading(TextureLoading),
 }
 
 impl TextureAsset {
+   pub fn is_loading(&self) -> bool {
+      matches!(self, TextureAsset::Loading(_))
+   }
+
    pub fn data(&self) -> &[u8] {
       match self {
          TextureAsset::Texture(info) => &info.data,
@@ -92,11 +785,203 @@ impl TextureAsset {
       }
    }
 
-   pub fn pixel_stride(&self) -> u8 {
+   pub fn mip_level_count(&self) -> u32 {
       match self {
-         TextureAsset::Texture(info) => info.pixel_stride,
-         TextureAsset::TextureLod(info) => info.pixel_stride,
+         TextureAsset::Texture(info) => info.mip_level_count,
+         TextureAsset::TextureLod(info) => info.mip_level_count,
          TextureAsset::Loading(_) => 1,
       }
    }
-}
\ No newline at end of file
+
+   pub fn pixel_format(&self) -> PixelFormat {
+      match self {
+         TextureAsset::Texture(info) => info.format,
+         TextureAsset::TextureLod(info) => info.format,
+         TextureAsset::Loading(_) => PixelFormat::Rgba8,
+      }
+   }
+
+   pub fn pixel_stride(&self) -> u32 {
+      match self {
+         TextureAsset::Texture(info) => info.pixel_stride(),
+         TextureAsset::TextureLod(info) => info.pixel_stride(),
+         TextureAsset::Loading(_) => 1,
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use crate::asset_source::{EmbeddedSource, FsSource};
+   use crate::renderer::asset_kinds::{BinaryAsset, JsonAsset};
+
+   use super::*;
+
+   fn pixel() -> TextureInfo {
+      TextureInfo {
+         data: vec![255; 4], width: 1, height: 1, depth: 1, format: PixelFormat::Rgba8, mip_level_count: 1,
+      }
+   }
+
+   fn start_test_load(loader: &mut AssetLoader, loading: impl Future<Output=TextureInfo> + 'static) -> Handle<TextureAsset> {
+      let guid = loader.free_guid;
+      loader.free_guid.0 += 1;
+      loader.start_loading(guid, Box::pin(loading.map(Ok)));
+      loader.handle(guid)
+   }
+
+   #[test]
+   fn assets_ready_waits_for_textures() {
+      let asset_loader = Rc::new(RefCell::new(AssetLoader::new()));
+      let (sender, receiver) = futures::channel::oneshot::channel::<TextureInfo>();
+      let handle = {
+         let mut loader = asset_loader.borrow_mut();
+         let handle = start_test_load(&mut loader, async move { receiver.await.unwrap() });
+         let progress = loader.track_progress(handle.guid());
+         progress.set_total_bytes(Some(4));
+         progress.add_loaded_bytes(2);
+         handle
+      };
+      let guid = handle.guid();
+      let mut assets_ready = AssetsReady::new(asset_loader.clone(), [handle]);
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      assert!(Pin::new(&mut assets_ready).poll(&mut cx).is_pending());
+      assert_eq!(assets_ready.progress(), 0.4);
+
+      sender.send(pixel()).ok().unwrap();
+      assert!(Pin::new(&mut assets_ready).poll(&mut cx).is_ready());
+      assert_eq!(assets_ready.progress(), 1.0);
+      assert_eq!(asset_loader.borrow().bytes_progress(guid), None);
+   }
+
+   #[test]
+   fn loads_are_polled_by_priority_within_budget() {
+      let mut loader = AssetLoader::new();
+      // nothing fits into the budget, only the first load is polled each tick
+      loader.set_frame_budget_ms(0.0);
+      let polled = Rc::new(RefCell::new(vec![]));
+      let handles: Vec<Handle<TextureAsset>> = (0..3).map(|i| {
+         let polled = polled.clone();
+         start_test_load(&mut loader, async move {
+            polled.borrow_mut().push(i);
+            pixel()
+         })
+      }).collect();
+      let guids: Vec<AssetGUID> = handles.iter().map(Handle::guid).collect();
+      loader.set_priority(guids[2], LoadPriority::Urgent);
+      loader.set_priority(guids[0], LoadPriority::Background);
+      loader.cancel(guids[1]);
+      assert!(loader.get_texture(guids[1]).is_none());
+
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      loader.tick_loading(&mut cx);
+      assert_eq!(*polled.borrow(), [2]);
+      loader.tick_loading(&mut cx);
+      assert_eq!(*polled.borrow(), [2, 0]);
+      assert!(loader.loading.is_empty());
+   }
+
+   #[test]
+   fn unreferenced_textures_are_evicted_over_budget() {
+      let mut loader = AssetLoader::new();
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      let kept = start_test_load(&mut loader, async { pixel() });
+      let evicted = start_test_load(&mut loader, async { pixel() });
+      let abandoned = start_test_load(&mut loader, futures::future::pending()).guid();
+      loader.tick_loading(&mut cx);
+      // the pending load lost its handle and is cancelled right away
+      assert!(!loader.contains(abandoned));
+      assert_eq!(loader.memory_usage(), 8);
+      let evicted_guid = evicted.guid();
+      drop(evicted);
+
+      loader.set_memory_budget_bytes(4);
+      loader.tick_loading(&mut cx);
+      assert!(!loader.contains(evicted_guid));
+      assert!(loader.contains(kept.guid()));
+      let weak = kept.downgrade();
+      assert!(weak.upgrade().is_some());
+
+      // paths of evicted textures are loaded again under a new GUID
+      loader.textures_guids.insert("kept.png".to_owned(), kept.guid());
+      drop(kept);
+      loader.set_memory_budget_bytes(0);
+      loader.tick_loading(&mut cx);
+      assert!(weak.upgrade().is_none());
+      assert_eq!(loader.memory_usage(), 0);
+      assert!(loader.textures_guids.is_empty());
+   }
+
+   #[test]
+   fn typed_assets_load_through_sources() {
+      let mut loader = AssetLoader::new();
+      loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new()
+         .with_file("params.json", br#"{"roughness": 0.25}"#)
+         .with_file("broken.json", b"{")));
+      let params = loader.load::<JsonAsset>("params.json".to_owned());
+      let same = loader.load::<JsonAsset>("params.json".to_owned());
+      let bytes = loader.load::<BinaryAsset>("params.json".to_owned());
+      let broken = loader.load::<JsonAsset>("broken.json".to_owned());
+      let missing = loader.load::<BinaryAsset>("missing.bin".to_owned());
+      assert!(params.guid() == same.guid() && params.guid() != bytes.guid());
+      assert!(loader.get(&params).is_none());
+
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      loader.tick_loading(&mut cx);
+      assert_eq!(loader.get(&params).unwrap().unwrap().get_f32("/roughness"), Some(0.25));
+      assert_eq!(loader.get(&bytes).unwrap().unwrap().0.len(), 19);
+      assert!(loader.get(&broken).unwrap().is_err());
+      assert!(matches!(loader.get(&missing).unwrap(), Err(AssetError::NotFound { .. })));
+      assert_eq!(loader.progress(params.guid()), 1.0);
+      assert!(loader.memory_usage() > 19);
+   }
+
+   #[test]
+   fn failed_textures_are_replaced_by_the_error_texture() {
+      let mut loader = AssetLoader::new();
+      loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new()
+         .with_file("corrupt.png", b"\x89PNG\r\n\x1a\n")));
+      let missing = loader.load_texture("missing.png".to_owned());
+      let corrupt = loader.load_texture("corrupt.png".to_owned());
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      loader.tick_loading(&mut cx);
+      assert!(matches!(loader.error(missing.guid()), Some(AssetError::NotFound { .. })));
+      assert!(matches!(loader.error(corrupt.guid()), Some(AssetError::DecodeFailed { .. })));
+      let Some(TextureAsset::Texture(info)) = loader.get_texture(missing.guid()) else {
+         panic!("no error texture");
+      };
+      assert_eq!((info.width, &info.data[..8]), (64, &[255, 0, 255, 255, 255, 0, 255, 255][..]));
+      assert_eq!(loader.progress(missing.guid()), 1.0);
+   }
+
+   #[test]
+   fn reload_swaps_data_of_changed_files() {
+      let root = std::env::temp_dir().join(format!("asset_reload_{}", std::process::id()));
+      std::fs::create_dir_all(&root).unwrap();
+      std::fs::write(root.join("params.json"), r#"{"roughness": 0.25}"#).unwrap();
+      let mut loader = AssetLoader::new();
+      loader.set_sources(AssetSources::new().with_source(FsSource::new(root.clone())));
+      let params = loader.load::<JsonAsset>("params.json".to_owned());
+      let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+      let mut tick = |loader: &mut AssetLoader| for _ in 0..10 {
+         loader.tick_loading(&mut cx);
+      };
+      tick(&mut loader);
+      assert_eq!(loader.generation(params.guid()), 0);
+
+      std::fs::write(root.join("params.json"), r#"{"roughness": 0.5}"#).unwrap();
+      loader.reload(params.guid());
+      // the previous data stays until the new one is decoded
+      assert_eq!(loader.get(&params).unwrap().unwrap().get_f32("/roughness"), Some(0.25));
+      tick(&mut loader);
+      assert_eq!(loader.get(&params).unwrap().unwrap().get_f32("/roughness"), Some(0.5));
+      assert_eq!(loader.generation(params.guid()), 1);
+
+      std::fs::write(root.join("params.json"), "{").unwrap();
+      loader.reload(params.guid());
+      tick(&mut loader);
+      assert_eq!(loader.get(&params).unwrap().unwrap().get_f32("/roughness"), Some(0.5));
+      assert_eq!((loader.generation(params.guid()), loader.reload_count()), (1, 1));
+      std::fs::remove_dir_all(root).unwrap();
+   }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::asset_loader::{AssetGUID, AssetLoader};
+use super::shader_loader::ShaderLoader;
+
+// Modification times of local files, compared at most once per interval
+pub struct FileWatcher<K> {
+   files: HashMap<K, (PathBuf, Option<SystemTime>)>,
+   interval: Duration,
+   last_check: Instant,
+}
+
+impl<K: Hash + Eq + Clone> FileWatcher<K> {
+   pub fn new(interval: Duration) -> Self {
+      Self {
+         files: HashMap::new(),
+         interval,
+         last_check: Instant::now(),
+      }
+   }
+
+   // true once per interval
+   pub fn is_due(&mut self) -> bool {
+      if self.last_check.elapsed() < self.interval {
+         return false;
+      }
+      self.last_check = Instant::now();
+      true
+   }
+
+   pub fn is_watched(&self, key: &K) -> bool {
+      self.files.contains_key(key)
+   }
+
+   pub fn watch(&mut self, key: K, file: PathBuf) {
+      let modified = modified_time(&file);
+      self.files.insert(key, (file, modified));
+   }
+
+   pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+      self.files.retain(|key, _| keep(key));
+   }
+
+   pub fn changed(&mut self) -> Vec<K> {
+      let mut changed = vec![];
+      for (key, (file, modified)) in self.files.iter_mut() {
+         let now_modified = modified_time(file);
+         // an editor saving by rename makes the file missing for a moment
+         if now_modified.is_some() && now_modified != *modified {
+            log::info!("FileWatcher: {} changed", file.display());
+            *modified = now_modified;
+            changed.push(key.clone());
+         }
+      }
+      changed
+   }
+}
+
+fn modified_time(file: &Path) -> Option<SystemTime> {
+   std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Reloads loaded assets whose local files changed. Only assets of file system sources are watched
+pub struct AssetWatcher {
+   files: FileWatcher<AssetGUID>,
+}
+
+impl Default for AssetWatcher {
+   fn default() -> Self {
+      Self::new(Duration::from_millis(500))
+   }
+}
+
+impl AssetWatcher {
+   pub fn new(interval: Duration) -> Self {
+      Self { files: FileWatcher::new(interval) }
+   }
+
+   pub fn poll(&mut self, asset_loader: &mut AssetLoader) {
+      if !self.files.is_due() {
+         return;
+      }
+      let sources = asset_loader.sources();
+      let paths: HashMap<AssetGUID, String> = asset_loader.asset_paths()
+         .map(|(guid, path)| (guid, path.to_owned()))
+         .collect();
+      self.files.retain(|guid| paths.contains_key(guid));
+
+      let mut changed = self.files.changed();
+      for (guid, path) in paths {
+         if self.files.is_watched(&guid) {
+            continue;
+         }
+         if let Some(file) = sources.local_path(&path) {
+            self.files.watch(guid, file);
+            // e.g. the file was missing when the asset was requested
+            if asset_loader.error(guid).is_some() {
+               changed.push(guid);
+            }
+         }
+      }
+      for guid in changed {
+         asset_loader.reload(guid);
+      }
+   }
+}
+
+// Invalidates shaders whose files changed, pipelines using them have to be rebuilt afterwards
+pub struct ShaderWatcher {
+   files: FileWatcher<String>,
+}
+
+impl Default for ShaderWatcher {
+   fn default() -> Self {
+      Self::new(Duration::from_millis(250))
+   }
+}
+
+impl ShaderWatcher {
+   pub fn new(interval: Duration) -> Self {
+      Self { files: FileWatcher::new(interval) }
+   }
+
+   // true if any loaded shader changed
+   pub fn poll(&mut self, shader_loader: &mut ShaderLoader) -> bool {
+      if !self.files.is_due() {
+         return false;
+      }
+      let paths: Vec<String> = shader_loader.loaded_paths()
+         .map(str::to_owned)
+         .filter(|path| !self.files.is_watched(path))
+         .collect();
+      for path in paths {
+         // embedded shaders have no file
+         if let Some(file) = shader_loader.sources().local_path(&path) {
+            self.files.watch(path, file);
+         }
+      }
+      let changed = self.files.changed();
+      for path in &changed {
+         shader_loader.invalidate(path);
+      }
+      !changed.is_empty()
+   }
+}
@@ -2,6 +2,7 @@ use std::ops::Rem;
 use std::rc::Rc;
 use futures::Future;
 use wgpu::ShaderStages;
+use wgsl_derive::WgslStruct;
 use bytemuck;
 
 use crate::renderer::webgpu::Utils;
@@ -9,16 +10,15 @@ use crate::timer::ScopedTimer;
 use crate::GraphicsLevel;
 
 use super::pipeline_loader::RenderPipelineFlatDescriptor;
-use super::preprocessor::Preprocessor;
-use super::shader_loader::{FragmentShaderVariant, VertexShaderVariant};
+use super::shader_loader::{FragmentShaderVariant, ShaderError, ShaderKey, VertexShaderVariant};
 use super::webgpu::buffer::{Buffer, UniformBuffer};
 use super::webgpu::utils::PipelineLayoutBuilder;
 use super::webgpu::uniform::BindGroupInfo;
 use super::{DemoLoadingFuture, DemoLoadingSimpleFuture, Dispose, ExternalState, IDemo, LoadingArgs, Progress, RenderArgs, SimpleFuture, Webgpu};
 
-const VERTEX_SHADER_VARIANT: VertexShaderVariant = VertexShaderVariant::TriangleFullscreen;
+pub(crate) const VERTEX_SHADER_VARIANT: VertexShaderVariant = VertexShaderVariant::TriangleFullscreen;
 // const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::Uv;
-const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::FractalMandelbrot;
+pub(crate) const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::FractalMandelbrot;
 
 #[derive(Default)]
 enum DemoLoadingStage {
@@ -36,9 +36,10 @@ struct DemoLoadingProcess {
    graphics_level: GraphicsLevel,
    loading_args: LoadingArgs,
    render_pipelines: Option<FractalRenderPipelines>,
-   vertex_shader: Option<Rc<wgpu::ShaderModule>>,
-   fragment_shader_default: Option<Rc<wgpu::ShaderModule>>,
-   fragment_shader_antialiasing: Option<Rc<wgpu::ShaderModule>>,
+   // compiled one per poll, the error shader replaces them once all are done
+   vertex_shader: Option<Result<Rc<wgpu::ShaderModule>, ShaderError>>,
+   fragment_shader_default: Option<Result<Rc<wgpu::ShaderModule>, ShaderError>>,
+   fragment_shader_antialiasing: Option<Result<Rc<wgpu::ShaderModule>, ShaderError>>,
    uniform_groups: Vec<BindGroupInfo>,
    fractal_uniform_buffer: Option<UniformBuffer>,
    loaded_demo: Option<Demo>,
@@ -190,22 +191,20 @@ impl DemoLoadingProcess {
 
    fn compile_shader_vert(&mut self) {
       let _t = ScopedTimer::new("compile_shader_vert");
-      let vertex_shader = self.loading_args.get_vertex_shader(VERTEX_SHADER_VARIANT, None);
+      let vertex_shader = self.loading_args.get_vertex_shader(VERTEX_SHADER_VARIANT);
       self.vertex_shader = Some(vertex_shader);
    }
 
    fn compile_shader_frag_default(&mut self) {
       let _t = ScopedTimer::new("compile_shader_frag_default");
-      let mut preprocessor = Preprocessor::new();
-      let fragment_shader_default = self.loading_args.get_fragment_shader(FRAGMENT_SHADER_VARIANT, Some(&mut preprocessor));
+      let fragment_shader_default = self.loading_args.get_fragment_shader(FRAGMENT_SHADER_VARIANT);
       self.fragment_shader_default = Some(fragment_shader_default);
    }
 
    fn compile_shader_frag_aa(&mut self) {
       let _t = ScopedTimer::new("compile_shader_frag_aa");
-      let mut preprocessor = Preprocessor::new();
-      preprocessor.define("USE_ANTIALIASING", "1");
-      let fragment_shader_antialiasing = self.loading_args.get_fragment_shader(FRAGMENT_SHADER_VARIANT, Some(&mut preprocessor));
+      let key = ShaderKey::new(FRAGMENT_SHADER_VARIANT).with("USE_ANTIALIASING", "1");
+      let fragment_shader_antialiasing = self.loading_args.get_fragment_shader(key);
       self.fragment_shader_antialiasing = Some(fragment_shader_antialiasing);
    }
 
@@ -218,6 +217,7 @@ impl DemoLoadingProcess {
          Some("Fractal Bind Buffer"));
       let fractal_uniform_group = BindGroupInfo::builder()
          .with_uniform_buffer(0, ShaderStages::FRAGMENT, &fractal_buffer.buffer)
+         .with_uniform_layout::<FractalUniformData>(0)
          .build(&self.loading_args.webgpu.device, Some("Fractal Bind Group"), None);
       self.fractal_uniform_buffer = Some(fractal_buffer);
 
@@ -233,9 +233,13 @@ impl DemoLoadingProcess {
          builder = builder.with(group);
       }
       let pipeline_layout_descr = builder.build_descriptor(Some("Render Pipeline Layout")).clone();
-      let vs = self.vertex_shader.take().unwrap();
-      let fs = self.fragment_shader_default.take().unwrap();
-      let fs_aa = self.fragment_shader_antialiasing.take().unwrap();
+      let (vs, [fs, fs_aa]) = self.loading_args.shaders_or_error(
+         self.vertex_shader.take().unwrap(),
+         [self.fragment_shader_default.take().unwrap(), self.fragment_shader_antialiasing.take().unwrap()]);
+      let groups: Vec<&BindGroupInfo> = std::iter::once(&premade.global_uniform.bind_group_info)
+         .chain(&self.uniform_groups)
+         .collect();
+      self.loading_args.check_bindings(&[&vs, &fs, &fs_aa], &groups);
       self.render_pipelines = Some(FractalRenderPipelines{
          default: self.build_render_pipeline("Render Pipeline - Default",
             &pipeline_layout_descr, &vs, &fs),
@@ -324,9 +328,12 @@ pub struct Demo {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct FractalUniformData {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "FractalSettings")]
+pub(crate) struct FractalUniformData {
+   #[wgsl(name = "center")]
    fractal_center: [f32; 2],
+   #[wgsl(name = "zoom")]
    fractal_zoom: f32,
    num_iterations: i32,
    color_bias: [f32; 3],
@@ -482,7 +489,7 @@ impl GraphicsSwitchingProcess {
 #[cfg(test)]
 mod tests {
    use std::cell::RefCell;
-   use crate::renderer::Premade;
+   use crate::renderer::{asset_loader::AssetLoader, Premade};
    use super::*;
 
     #[test]
@@ -493,6 +500,7 @@ mod tests {
          webgpu: Rc::new(webgpu),
          color_texture_format: wgpu::TextureFormat::Rgba8Unorm,
          premade: Rc::new(RefCell::new(premade)),
+         asset_loader: Rc::new(RefCell::new(AssetLoader::new())),
       };
       let mut demo_loader = DemoLoadingProcess::new(loading_args, GraphicsLevel::Medium);
       demo_loader.compile_shaders();
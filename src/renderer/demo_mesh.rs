@@ -1,23 +1,36 @@
 use std::rc::Rc;
 use futures::Future;
 use wgpu::BufferUsages;
+use wgsl_derive::WgslStruct;
 
+use crate::image_loader::PixelFormat;
 use crate::renderer::pipeline_loader::RenderPipelineFlatDescriptor;
 use crate::renderer::webgpu::Utils;
 
+use super::asset_loader::{AssetGUID, AssetLoader, AssetsReady, Handle, TextureAsset};
+use super::ibl::{self, EnvironmentMaps, Skybox};
+use super::lighting::{Lighting, PointLight, SpotLight};
 use super::shader_loader::{FragmentShaderVariant, VertexShaderVariant};
-use super::webgpu::buffer::{Buffer, IndexBuffer, VertexBuffer, VertexPosUv};
+use super::webgpu::buffer::{Buffer, IndexBuffer, UniformBuffer, VertexBuffer, VertexPosUv};
 use super::webgpu::uniform::BindGroupInfo;
 use super::webgpu::PipelineLayoutBuilder;
 use super::{DemoLoadingFuture, DemoLoadingSimpleFuture, Dispose, ExternalState, GraphicsLevel, IDemo, LoadingArgs, Progress, RenderArgs, SimpleFuture, Webgpu};
 
-const VERTEX_SHADER_VARIANT:   VertexShaderVariant   = VertexShaderVariant::Passthrough;
-const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::Uv;
+pub(crate) const VERTEX_SHADER_VARIANT:   VertexShaderVariant   = VertexShaderVariant::Mesh;
+pub(crate) const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::MeshParallax;
+
+const ALBEDO_TEXTURE_PATH: &str = "assets/materials/leather/Leather_Padded_001_basecolor.jpg";
+const NORMAL_TEXTURE_PATH: &str = "assets/materials/leather/Leather_Padded_001_normal.jpg";
+const HEIGHT_TEXTURE_PATH: &str = "assets/materials/leather/Leather_Padded_001_height.png";
+// equirectangular HDR panorama, loaded as Rgba16Float
+const ENVIRONMENT_TEXTURE_PATH: &str = "assets/environment/sky_1k.hdr";
 
 #[derive(Default)]
 enum DemoLoadingStage {
    Ready = 0,
    #[default] CompileShaders,
+   LoadAssets,
+   BakeEnvironment,
    BuildUniforms,
    BuildVertexData,
    BuildPipelines,
@@ -36,7 +49,14 @@ struct DemoLoadingProcess {
    vertex_buffer: Option<VertexBuffer>,
    render_pipeline: Option<Rc<wgpu::RenderPipeline>>,
    loaded_demo: Option<Demo>,
-   uniform_groups: Vec<BindGroupInfo>
+   material_textures: Vec<(Handle<TextureAsset>, wgpu::TextureFormat)>,
+   assets_ready: Option<AssetsReady>,
+   mesh_uniform_buffer: Option<UniformBuffer>,
+   uniform_groups: Vec<BindGroupInfo>,
+   lighting: Option<Lighting>,
+   environment_texture: Option<Handle<TextureAsset>>,
+   environment: Option<Rc<EnvironmentMaps>>,
+   skybox: Option<Skybox>,
 }
 
 impl Dispose for DemoLoadingProcess {
@@ -50,6 +70,10 @@ impl Dispose for DemoLoadingProcess {
             self.render_pipeline.take();
             self.vertex_shader.take();
             self.fragment_shader.take();
+            // unreferenced loads are cancelled by the asset loader
+            self.assets_ready.take();
+            self.material_textures.clear();
+            self.environment_texture.take();
             self.stage = DemoLoadingStage::Ready;
             self.loaded_demo.take();
             log::info!("Rust loading drop: {}", std::module_path!());
@@ -92,16 +116,34 @@ impl SimpleFuture for DemoLoadingProcess {
    type Output = Box<dyn IDemo>;
    //type Context = std::task::Context<'a>;
 
-   fn simple_poll(mut self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context) -> std::task::Poll<Self::Output> {
+   fn simple_poll(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context) -> std::task::Poll<Self::Output> {
       use DemoLoadingStage::*;
       match self.stage {
          CompileShaders => {
             self.compile_shaders();
-            self.stage_percent = 0.3;
+            self.start_loading_assets();
+            self.stage_percent = 0.2;
+            self.stage = LoadAssets;
+         },
+         LoadAssets => {
+            // waits for the textures, the progress bar follows their fetched bytes
+            let assets_ready = self.assets_ready.as_mut().unwrap();
+            let poll = std::pin::Pin::new(&mut *assets_ready).poll(cx);
+            let assets_progress = assets_ready.progress();
+            self.stage_percent = 0.2 + 0.1 * assets_progress;
+            if poll.is_ready() {
+               self.assets_ready = None;
+               self.stage_percent = 0.3;
+               self.stage = BakeEnvironment;
+            }
+         },
+         BakeEnvironment => {
+            self.bake_environment();
+            self.stage_percent = 0.35;
             self.stage = BuildUniforms;
          },
          BuildUniforms => {
-            self.load_assets();
+            self.make_bind_groups();
             self.stage_percent = 0.4;
             self.stage = BuildVertexData;
          },
@@ -111,7 +153,11 @@ impl SimpleFuture for DemoLoadingProcess {
             self.stage = BuildPipelines;
          },
          BuildPipelines => {
-            self.build_pipelines();
+            let mut lighting = self.lighting.take().unwrap();
+            let mut skybox = self.skybox.take().unwrap();
+            self.build_pipelines(&mut lighting, &mut skybox);
+            self.lighting = Some(lighting);
+            self.skybox = Some(skybox);
             self.stage_percent = 0.6;
             self.stage = StartSwitchingGraphicsLevel;
          },
@@ -157,67 +203,75 @@ impl DemoLoadingProcess {
          index_buffer: Default::default(),
          vertex_buffer: Default::default(),
          loaded_demo: Default::default(),
+         material_textures: vec![],
+         assets_ready: Default::default(),
+         mesh_uniform_buffer: Default::default(),
          uniform_groups: vec![],
+         lighting: Default::default(),
+         environment_texture: Default::default(),
+         environment: Default::default(),
+         skybox: Default::default(),
       }
    }
 
-   fn rebuild_pipelines(&mut self) {
+   fn rebuild_pipelines(&mut self, lighting: &mut Lighting, skybox: &mut Skybox) {
       self.compile_shaders();
-      self.make_bind_groups();
-      self.build_pipelines();
+      self.build_pipelines(lighting, skybox);
    }
 
    fn compile_shaders(&mut self) {
-      self.vertex_shader = Some(self.loading_args
-         .get_vertex_shader(VERTEX_SHADER_VARIANT, None));
-      self.fragment_shader = Some(self.loading_args
-         .get_fragment_shader(FRAGMENT_SHADER_VARIANT, None));
+      let (vertex_shader, [fragment_shader]) = self.loading_args
+         .get_shaders(VERTEX_SHADER_VARIANT, [FRAGMENT_SHADER_VARIANT]);
+      self.vertex_shader = Some(vertex_shader);
+      self.fragment_shader = Some(fragment_shader);
    }
 
-   fn make_bind_groups(&mut self) {
+   fn start_loading_assets(&mut self) {
+      let mut asset_loader = self.loading_args.asset_loader.borrow_mut();
+      self.material_textures = [
+         // Most images are stored using sRGB, but normal and height maps are linear data
+         (ALBEDO_TEXTURE_PATH, wgpu::TextureFormat::Rgba8UnormSrgb),
+         (NORMAL_TEXTURE_PATH, wgpu::TextureFormat::Rgba8Unorm),
+         (HEIGHT_TEXTURE_PATH, wgpu::TextureFormat::Rgba8Unorm),
+      ].into_iter()
+         .map(|(path, format)| (asset_loader.load_texture(path.to_owned()), format))
+         .collect();
+      self.environment_texture = Some(asset_loader
+         .load_texture_float(ENVIRONMENT_TEXTURE_PATH.to_owned(), PixelFormat::Rgba16Float));
+      let handles = self.material_textures.iter()
+         .map(|(handle, _)| handle.clone())
+         .chain(self.environment_texture.clone());
+      self.assets_ready = Some(AssetsReady::new(self.loading_args.asset_loader.clone(), handles));
+      ibl::precompile_shaders(&self.loading_args);
+   }
 
+   fn bake_environment(&mut self) {
+      let guid = self.environment_texture.as_ref().unwrap().guid();
+      let (environment, skybox) = bake_environment(&self.loading_args, guid);
+      self.skybox = Some(skybox);
+      self.environment = Some(environment);
    }
-   
-   fn load_assets(&mut self) {
-      let mut asset_loader = self.loading_args.asset_loader.borrow_mut();
-      let mut load_texture = |path: &str, format: wgpu::TextureFormat| {
-         let guid = asset_loader.load_texture(path.to_owned());
-         let image = asset_loader.get_texture(guid).unwrap();
-         let image_size = image.dimensions();
-         let texture_size = wgpu::Extent3d {
-            width: image_size.0,
-            height: image_size.1,
-            depth_or_array_layers: image_size.2,
-        };
-        self.loading_args.webgpu.device.create_texture(
-            &wgpu::TextureDescriptor {
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                // Most images are stored using sRGB, so we need to reflect that here.
-                format,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some(path),
-                view_formats: &[],
-            }
-        )
-      };
-      let albedo_texture = load_texture("assets/materials/leather/Leather_Padded_001_basecolor.jpg", wgpu::TextureFormat::Rgba8UnormSrgb);
-      let occlusion_texture = load_texture("assets/materials/leather/Leather_Padded_001_ambientOcclusion.jpg", wgpu::TextureFormat::Rgba8UnormSrgb);
-      let normal_texture = load_texture("assets/materials/leather/Leather_Padded_001_normal.jpg", wgpu::TextureFormat::Rgba8UnormSrgb);
-      let height_texture = load_texture("assets/materials/leather/Leather_Padded_001_height.png", wgpu::TextureFormat::Rgba8UnormSrgb);
-      let roughness_texture = load_texture("assets/materials/leather/Leather_Padded_001_roughness.jpg",
-         wgpu::TextureFormat::Rgba8UnormSrgb);
-      let texture_bind_group = BindGroupInfo::builder()
-         .with_texture_2d(0, wgpu::ShaderStages::FRAGMENT,
-            wgpu::TextureSampleType::Float { filterable:true },
-            &Utils::texture_view(&albedo_texture, Some("albedo_lod")),)
-         .with_sampler(1, wgpu::ShaderStages::FRAGMENT,
-            &self.loading_args.premade.borrow().samplers.bilinear_sampler)
-         .build(&self.loading_args.webgpu.device, Some("Fractal Bind Group"), None);
-
-      self.uniform_groups = vec![texture_bind_group];
+
+   fn make_bind_groups(&mut self) {
+      let webgpu = self.loading_args.webgpu.clone();
+      let mesh_buffer = Buffer::new_uniform::<MeshUniformData>(
+         &webgpu.device, wgpu::BufferUsages::COPY_DST, Some("Mesh Bind Buffer"));
+      let material_bind_group = make_material_bind_group(&self.loading_args, &self.material_textures, &mesh_buffer);
+      self.mesh_uniform_buffer = Some(mesh_buffer);
+
+      self.uniform_groups = vec![material_bind_group];
+
+      let mut lighting = Lighting::new(&webgpu.device, self.graphics_level);
+      // the occluder triangle hovers above the mesh, everything fits into this sphere
+      lighting.set_scene_bounds(glam::Vec3::new(0.0, 0.0, 0.15), 0.75);
+      lighting.add_point_light(PointLight {
+         position: [-0.3, 0.2, 0.25],
+         ..Default::default()
+      });
+      let mut spot_light = SpotLight::default();
+      spot_light.position = [0.3, -0.2, 0.6];
+      lighting.add_spot_light(spot_light);
+      self.lighting = Some(lighting);
    }
 
    fn build_vertex_data(&mut self) {
@@ -228,6 +282,10 @@ impl DemoLoadingProcess {
          VertexPosUv { position: [-0.21918549, -0.44939706, 0.0], uv: [0.28081453, 0.05060294], },
          VertexPosUv { position: [0.35966998, -0.3473291, 0.0], uv: [0.85967, 0.1526709], },
          VertexPosUv { position: [0.44147372, 0.2347359, 0.0], uv: [0.9414737, 0.7347359], },
+         // occluder, casts a shadow onto the mesh
+         VertexPosUv { position: [-0.1, 0.05, 0.3], uv: [0.4, 0.55], },
+         VertexPosUv { position: [0.1, 0.05, 0.3], uv: [0.6, 0.55], },
+         VertexPosUv { position: [0.0, 0.2, 0.3], uv: [0.5, 0.7], },
       ];
       self.vertex_buffer = Some(Buffer::new_vertex_init(
          &wgpu.device, bytemuck::cast_slice(VERTICES),
@@ -237,28 +295,35 @@ impl DemoLoadingProcess {
          0, 1, 4,
          1, 2, 4,
          2, 3, 4,
+         5, 6, 7,
       ];
       self.index_buffer = Some(Buffer::new_index_init(
          &wgpu.device, bytemuck::cast_slice(INDICES),
          wgpu::IndexFormat::Uint16, BufferUsages::empty(), Some("Mesh indices")));
    }
 
-   fn build_pipelines(&mut self) {
+   fn build_pipelines(&mut self, lighting: &mut Lighting, skybox: &mut Skybox) {
       let mut builder = PipelineLayoutBuilder::new();
       let premade = self.loading_args.premade.borrow();
       builder = builder.with(&premade.global_uniform.bind_group_info);
       for group in self.uniform_groups.iter() {
          builder = builder.with(group);
       }
-      let layout_descriptor = wgpu::PipelineLayoutDescriptor {
-         label: Some("Render Pipeline Layout"),
-         bind_group_layouts: &[&premade.global_uniform.bind_group_info.layout],
-         push_constant_ranges: &[],
-      };
+      lighting.build_shadow_pipeline(&self.loading_args, VertexPosUv::layout());
+      builder = builder.with(&lighting.bind_group_info);
+      let environment = self.environment.as_ref().unwrap();
+      builder = builder.with(&environment.bind_group_info);
+      skybox.build_pipeline(&self.loading_args);
+      let layout_descriptor = builder.build_descriptor(Some("Render Pipeline Layout"));
       let render_pipeline_layout = self.loading_args.webgpu.device
          .create_pipeline_layout(&layout_descriptor);
       let vs = self.vertex_shader.take().unwrap();
       let fs = self.fragment_shader.take().unwrap();
+      let groups: Vec<&BindGroupInfo> = std::iter::once(&premade.global_uniform.bind_group_info)
+         .chain(&self.uniform_groups)
+         .chain([&lighting.bind_group_info, &environment.bind_group_info])
+         .collect();
+      self.loading_args.check_bindings(&[&vs, &fs], &groups);
       self.render_pipeline = Some(self.loading_args.get_pipeline(
          &RenderPipelineFlatDescriptor::new(
          &layout_descriptor,
@@ -291,13 +356,42 @@ impl DemoLoadingProcess {
    }
 
    fn start_switching_graphics_level(&mut self) {
-      self.loaded_demo = Some(Demo {
+      let mut loaded_demo = Demo {
          render_pipeline: self.render_pipeline.take().unwrap(),
          index_buffer: self.index_buffer.take().unwrap(),
          vertex_buffer: self.vertex_buffer.take().unwrap(),
          pending_graphics_level_switch: None,
          graphcis_level: self.graphics_level,
-      });
+         uniform_groups: vec![],
+         mesh_uniform_data: MeshUniformData {
+            parallax_scale: 0.05,
+            parallax_steps: 0,
+            parallax_shadow_steps: 0,
+            debug_view: 0,
+            eye_position: [0.0, 0.0, 1.5],
+            shadow_softness: 8.0,
+            roughness: 0.6,
+            ibl_intensity: 1.0,
+            __padding: Default::default(),
+         },
+         lighting: self.lighting.take().unwrap(),
+         environment: self.environment.take().unwrap(),
+         skybox: self.skybox.take().unwrap(),
+         mesh_uniform_buffer: self.mesh_uniform_buffer.take().unwrap(),
+         use_parallax: true,
+         level_parallax_steps: (0, 0),
+         show_parallax_uv: false,
+         texture_generations: vec![],
+         asset_errors: vec![],
+         material_textures: std::mem::take(&mut self.material_textures),
+         environment_texture: self.environment_texture.take().unwrap(),
+      };
+      let asset_loader = self.loading_args.asset_loader.borrow();
+      loaded_demo.texture_generations = loaded_demo.texture_generations(&asset_loader);
+      loaded_demo.update_asset_errors(&asset_loader);
+      drop(asset_loader);
+      std::mem::swap(&mut loaded_demo.uniform_groups, &mut self.uniform_groups);
+      self.loaded_demo = Some(loaded_demo);
       self.loaded_demo.as_mut().unwrap()
          .start_switching_graphics_level(self.loading_args.clone(), self.graphics_level)
          .expect("WebGPU surface error");
@@ -310,23 +404,143 @@ pub struct Demo {
    vertex_buffer: VertexBuffer,
    pending_graphics_level_switch: Option<GraphicsSwitchingProcess>,
    graphcis_level: GraphicsLevel,
+   uniform_groups: Vec<BindGroupInfo>,
+   mesh_uniform_data: MeshUniformData,
+   mesh_uniform_buffer: UniformBuffer,
+   use_parallax: bool,
+   // steps of the current graphics level, applied while `use_parallax` is on
+   level_parallax_steps: (u32, u32),
+   show_parallax_uv: bool,
+   lighting: Lighting,
+   environment: Rc<EnvironmentMaps>,
+   skybox: Skybox,
+   // the handles keep the source textures from being evicted while the demo is alive
+   material_textures: Vec<(Handle<TextureAsset>, wgpu::TextureFormat)>,
+   environment_texture: Handle<TextureAsset>,
+   // reload generations of the textures above, the environment is the last one
+   texture_generations: Vec<u64>,
+   // textures shown as the error texture
+   asset_errors: Vec<String>,
 }
 
-impl IDemo for Demo {
-   fn tick(&mut self, _input: &ExternalState) {
+// cubemaps are baked once per environment, then reused by subsequent loads of the demo
+fn bake_environment(loading_args: &LoadingArgs, guid: AssetGUID) -> (Rc<EnvironmentMaps>, Skybox) {
+   let mut asset_loader = loading_args.asset_loader.borrow_mut();
+   let premade = loading_args.premade.borrow();
+   let mut ibl_cache = premade.ibl_cache.borrow_mut();
+   ibl_cache.unload_evicted(&asset_loader);
+   let equirect = asset_loader.get_texture(guid)
+      .expect("Mesh demo environment was unloaded before it was used");
+   let environment = ibl_cache.get_or_bake(loading_args, guid, equirect);
+   let skybox = Skybox::new(&loading_args.webgpu.device,
+      &environment, &premade.samplers.trilinear_sampler);
+   (environment, skybox)
+}
+
+fn make_material_bind_group(
+   loading_args: &LoadingArgs,
+   material_textures: &[(Handle<TextureAsset>, wgpu::TextureFormat)],
+   mesh_buffer: &UniformBuffer,
+) -> BindGroupInfo {
+   let mut asset_loader = loading_args.asset_loader.borrow_mut();
+   let views = material_textures.iter()
+      .map(|(handle, format)| {
+         let texture = asset_loader
+            .create_gpu_texture(handle.guid(), loading_args, *format, Some("Mesh material texture"))
+            .expect("Mesh demo texture was unloaded before it was used");
+         Utils::texture_view(&texture, Some("Mesh material view"))
+      })
+      .collect::<Vec<_>>();
+   let premade = loading_args.premade.borrow();
+   BindGroupInfo::builder()
+      .with_texture_2d(0, wgpu::ShaderStages::FRAGMENT,
+         wgpu::TextureSampleType::Float { filterable:true }, &views[0])
+      .with_texture_2d(1, wgpu::ShaderStages::FRAGMENT,
+         wgpu::TextureSampleType::Float { filterable:true }, &views[1])
+      .with_texture_2d(2, wgpu::ShaderStages::FRAGMENT,
+         wgpu::TextureSampleType::Float { filterable:true }, &views[2])
+      .with_sampler(3, wgpu::ShaderStages::FRAGMENT, &premade.samplers.anisotropic_sampler)
+      // mesh settings share the group, WebGL2 allows only 4 bind groups
+      .with_uniform_buffer(4, wgpu::ShaderStages::FRAGMENT, &mesh_buffer.buffer)
+      .with_uniform_layout::<MeshUniformData>(4)
+      .build(&loading_args.webgpu.device, Some("Mesh Material Bind Group"), None)
+}
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "MeshSettings")]
+pub(crate) struct MeshUniformData {
+   parallax_scale: f32,
+   parallax_steps: u32,
+   parallax_shadow_steps: u32,
+   debug_view: u32,
+   eye_position: [f32; 3],
+   shadow_softness: f32,
+   roughness: f32,
+   ibl_intensity: f32,
+   __padding: [f32; 2],
+}
+
+// (parallax steps, self-shadowing steps), parallax is disabled at 0 steps
+fn parallax_steps(graphics_level: GraphicsLevel) -> (u32, u32) {
+   match graphics_level {
+      GraphicsLevel::Minimal => (0, 0),
+      GraphicsLevel::Low => (8, 0),
+      GraphicsLevel::Medium => (16, 8),
+      GraphicsLevel::High => (32, 16),
+      GraphicsLevel::Ultra => (64, 32),
+   }
+}
+
+impl IDemo for Demo {
+   fn tick(&mut self, input: &ExternalState) {
+      let time = input.time_now_sec() as f32;
+      // orbit the light and sway the viewer, so the parallax and the shadows are noticeable
+      self.lighting.directional_mut().direction = [-(0.7 * time).cos(), -(0.7 * time).sin(), -0.6];
+      self.mesh_uniform_data.eye_position = [0.4 * (0.3 * time).sin(), 0.3 * (0.2 * time).cos(), 1.5];
+      let (steps, shadow_steps) = if self.use_parallax {
+         self.level_parallax_steps
+      } else {
+         (0, 0)
+      };
+      self.mesh_uniform_data.parallax_steps = steps;
+      self.mesh_uniform_data.parallax_shadow_steps = shadow_steps;
+      self.mesh_uniform_data.debug_view =
+         (self.show_parallax_uv || input.debug_mode() == Some(2)) as u32;
    }
 
    fn render(&mut self, args: RenderArgs) -> Result<(), wgpu::SurfaceError> {
+      self.mesh_uniform_buffer.write(&args.webgpu.queue, 0, &[self.mesh_uniform_data]);
+      self.lighting.update_gpu(&args.webgpu.queue);
+      // the mesh itself is drawn in clip space, the camera only orients the sky
+      let eye = glam::Vec3::from(self.mesh_uniform_data.eye_position);
+      self.skybox.set_camera(
+         glam::Mat4::look_at_rh(eye, glam::Vec3::ZERO, glam::Vec3::Y),
+         glam::Mat4::perspective_rh(60_f32.to_radians(), args.global_uniform.stable_data.aspect_ratio, 0.1, 10.0));
+      self.skybox.update_gpu(&args.webgpu.queue);
+
       let color = Some(Utils::surface_view(args.backbuffer));
       let mut encoder = args.webgpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
          label: Some("Render Encoder"),
       });
 
+      self.lighting.render_shadow_pass(&mut encoder, |render_pass| {
+         self.vertex_buffer.bind(render_pass, 0);
+         self.index_buffer.bind(render_pass);
+         render_pass.draw_indexed(0..self.index_buffer.num_indices, 0, 0..1);
+      });
+
       {
          let mut render_pass = Utils::default_renderpass(&mut encoder, &color,&None);
+         self.skybox.render(&mut render_pass);
          const DEMO_UNIFORM_BIND_GROUP_INDEX: u32 = 0;
+         const MATERIAL_BIND_GROUP_INDEX: u32 = 1;
+         const LIGHTS_BIND_GROUP_INDEX: u32 = 2;
+         const IBL_BIND_GROUP_INDEX: u32 = 3;
          render_pass.set_bind_group(DEMO_UNIFORM_BIND_GROUP_INDEX, &args.global_uniform.bind_group_info.bind_group, &[]);
+         render_pass.set_bind_group(MATERIAL_BIND_GROUP_INDEX, &self.uniform_groups[0].bind_group, &[]);
+         render_pass.set_bind_group(LIGHTS_BIND_GROUP_INDEX, &self.lighting.bind_group_info.bind_group, &[]);
+         render_pass.set_bind_group(IBL_BIND_GROUP_INDEX, &self.environment.bind_group_info.bind_group, &[]);
          render_pass.set_pipeline(&self.render_pipeline);
          const VERTEX_POS_UV_LOCATION: u32 = 0;
          self.vertex_buffer.bind(&mut render_pass, VERTEX_POS_UV_LOCATION);
@@ -341,20 +555,67 @@ impl IDemo for Demo {
 
    fn rebuild_pipelines(&mut self, loading_args: LoadingArgs) {
       let mut loader = DemoLoadingProcess::new(loading_args, self.graphcis_level);
-      loader.rebuild_pipelines();
+      // bind groups are kept, only their layouts are needed to rebuild the pipeline
+      std::mem::swap(&mut loader.uniform_groups, &mut self.uniform_groups);
+      loader.environment = Some(self.environment.clone());
+      loader.rebuild_pipelines(&mut self.lighting, &mut self.skybox);
+      std::mem::swap(&mut loader.uniform_groups, &mut self.uniform_groups);
       self.render_pipeline = loader.render_pipeline.take().unwrap();
    }
 
+   fn assets_reloaded(&mut self, args: LoadingArgs) {
+      let generations = self.texture_generations(&args.asset_loader.borrow());
+      if generations == self.texture_generations {
+         return;
+      }
+      if generations.last() != self.texture_generations.last() {
+         let guid = self.environment_texture.guid();
+         args.premade.borrow().ibl_cache.borrow_mut().unload(guid);
+         (self.environment, self.skybox) = bake_environment(&args, guid);
+      }
+      self.texture_generations = generations;
+      self.update_asset_errors(&args.asset_loader.borrow());
+      self.uniform_groups = vec![make_material_bind_group(&args, &self.material_textures, &self.mesh_uniform_buffer)];
+      // the environment bind group and the skybox are new as well
+      self.rebuild_pipelines(args);
+   }
+
    #[cfg(any(feature = "imgui_win", feature = "imgui_web"))]
    fn render_imgui(&mut self, ui: &imgui::Ui, args: super::imgui_web::ImguiRenderArgs) {
       use imgui::*;
-      let window = ui.window("Uv Sandbox Demo");
+      let window = ui.window("Mesh Demo");
       window
          .size(args.size, Condition::FirstUseEver)
          .position(args.position, Condition::FirstUseEver)
          .always_auto_resize(true)
          .build(|| {
+            ui.checkbox("Parallax occlusion", &mut self.use_parallax);
+            ui.checkbox("Show parallax UV", &mut self.show_parallax_uv);
+            imgui::Drag::new("Parallax scale")
+               .range(0.0, 0.2)
+               .speed(0.001)
+               .build(ui, &mut self.mesh_uniform_data.parallax_scale);
+            imgui::Drag::new("Shadow softness")
+               .range(0.0, 32.0)
+               .speed(0.1)
+               .build(ui, &mut self.mesh_uniform_data.shadow_softness);
+            imgui::Drag::new("Roughness")
+               .range(0.0, 1.0)
+               .speed(0.01)
+               .build(ui, &mut self.mesh_uniform_data.roughness);
+            imgui::Drag::new("IBL intensity")
+               .range(0.0, 4.0)
+               .speed(0.01)
+               .build(ui, &mut self.mesh_uniform_data.ibl_intensity);
+            let (steps, shadow_steps) = self.level_parallax_steps;
+            ui.text(format!("Parallax steps: {} / shadow steps: {}", steps, shadow_steps));
+            for error in &self.asset_errors {
+               ui.text_colored([1.0, 0.3, 0.3, 1.0], error);
+            }
          });
+      // the mesh is drawn without a camera, world space is clip space
+      let lighting_args = super::imgui_web::ImguiRenderArgs::new_down_from(&args, [0.0, 10.0]);
+      self.lighting.render_imgui(ui, lighting_args, glam::Mat4::IDENTITY);
    }
 
    fn start_switching_graphics_level(&mut self, _args: LoadingArgs, graphics_level: GraphicsLevel) -> Result<(), wgpu::SurfaceError> {
@@ -388,6 +649,26 @@ impl IDemo for Demo {
 }
 
 impl Demo {
+   fn update_asset_errors(&mut self, asset_loader: &AssetLoader) {
+      self.asset_errors = self.material_textures.iter()
+         .map(|(handle, _)| handle)
+         .chain([&self.environment_texture])
+         .filter_map(|handle| asset_loader.error(handle.guid()))
+         .map(|e| e.to_string())
+         .collect();
+      for error in &self.asset_errors {
+         log::warn!("Mesh demo renders the error texture: {}", error);
+      }
+   }
+
+   fn texture_generations(&self, asset_loader: &AssetLoader) -> Vec<u64> {
+      self.material_textures.iter()
+         .map(|(handle, _)| handle)
+         .chain([&self.environment_texture])
+         .map(|handle| asset_loader.generation(handle.guid()))
+         .collect()
+   }
+
    pub fn start_loading<'a>(args: LoadingArgs, graphics_level: GraphicsLevel) -> Box<dyn DemoLoadingFuture> {
       Box::new(DemoLoadingProcess::new(args, graphics_level))
    }
@@ -395,7 +676,7 @@ impl Demo {
 
 pub struct GraphicsSwitchingProcess {
    progress: f32,
-   #[allow(unused)] graphics_level: GraphicsLevel,
+   graphics_level: GraphicsLevel,
 }
 
 impl Progress for GraphicsSwitchingProcess {
@@ -405,8 +686,11 @@ impl Progress for GraphicsSwitchingProcess {
 }
 
 impl GraphicsSwitchingProcess {
-   pub fn poll(demo: &mut Demo, _webgpu: &Webgpu) -> std::task::Poll<()> {
+   pub fn poll(demo: &mut Demo, webgpu: &Webgpu) -> std::task::Poll<()> {
       let self_ = demo.pending_graphics_level_switch.as_mut().unwrap();
+      demo.graphcis_level = self_.graphics_level;
+      demo.lighting.set_graphics_level(&webgpu.device, self_.graphics_level);
+      demo.level_parallax_steps = parallax_steps(self_.graphics_level);
       self_.progress = 1.0;
       std::task::Poll::Ready(())
    }
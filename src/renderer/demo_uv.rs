@@ -7,8 +7,8 @@ use crate::renderer::webgpu::Utils;
 use super::shader_loader::{FragmentShaderVariant, VertexShaderVariant};
 use super::{DemoLoadingFuture, DemoLoadingSimpleFuture, Dispose, ExternalState, GraphicsLevel, IDemo, LoadingArgs, Progress, RenderArgs, SimpleFuture, Webgpu};
 
-const VERTEX_SHADER_VARIANT:   VertexShaderVariant   = VertexShaderVariant::TriangleFullscreen;
-const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::Uv;
+pub(crate) const VERTEX_SHADER_VARIANT:   VertexShaderVariant   = VertexShaderVariant::TriangleFullscreen;
+pub(crate) const FRAGMENT_SHADER_VARIANT: FragmentShaderVariant = FragmentShaderVariant::Uv;
 
 #[derive(Default)]
 enum DemoLoadingStage {
@@ -86,8 +86,7 @@ impl SimpleFuture for DemoLoadingProcess {
       use DemoLoadingStage::*;
       match self.stage {
          CompileShaders => {
-            let vertex_shader = self.loading_args.get_vertex_shader(VERTEX_SHADER_VARIANT, None);
-            let fragment_shader = self.loading_args.get_fragment_shader(FRAGMENT_SHADER_VARIANT, None);
+            let (vertex_shader, [fragment_shader]) = self.loading_args.get_shaders(VERTEX_SHADER_VARIANT, [FRAGMENT_SHADER_VARIANT]);
             self.vertex_shader = Some(vertex_shader);
             self.fragment_shader = Some(fragment_shader);
             self.stage_percent = 0.6;
@@ -105,6 +104,7 @@ impl SimpleFuture for DemoLoadingProcess {
                &layout_descriptor);
             let vs = self.vertex_shader.take().unwrap();
             let fs = self.fragment_shader.take().unwrap();
+            self.loading_args.check_bindings(&[&vs, &fs], &[&premade.borrow().global_uniform.bind_group_info]);
             self.render_pipeline = Some(self.loading_args.get_pipeline(
                &RenderPipelineFlatDescriptor::new(
                &layout_descriptor,
@@ -291,7 +291,7 @@ impl GraphicsSwitchingProcess {
 mod tests {
     use std::cell::RefCell;
 
-    use crate::renderer::Premade;
+    use crate::renderer::{asset_loader::AssetLoader, Premade};
 
     use super::*;
 
@@ -303,9 +303,10 @@ mod tests {
             webgpu,
             color_texture_format: wgpu::TextureFormat::Rgba8Unorm,
             premade,
+            asset_loader: Rc::new(RefCell::new(AssetLoader::new())),
         };
-        loader.get_vertex_shader(VERTEX_SHADER_VARIANT, None);
-        loader.get_fragment_shader(FRAGMENT_SHADER_VARIANT, None);
+        loader.get_vertex_shader(VERTEX_SHADER_VARIANT).unwrap();
+        loader.get_fragment_shader(FRAGMENT_SHADER_VARIANT).unwrap();
     }
 
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
 use wgpu::ShaderStages;
 
+use wgsl_derive::WgslStruct;
+
 use crate::{renderer::webgpu::buffer::Buffer, timer::ScopedTimer};
 
 use super::{webgpu::{buffer::UniformBuffer, uniform::BindGroupInfo}, ExternalState};
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "DemoSettingsStable")]
 pub struct StableGlobalUniformData {
    pub color_attachment_size: [u32; 2],
    pub aspect_ratio: f32,
@@ -13,7 +16,8 @@ pub struct StableGlobalUniformData {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "DemoSettingsDynamic")]
 pub struct DynamicGlobalUniformData {
    pub mouse_position: [f32; 2],
    __padding: [u32; 2],
@@ -48,6 +52,8 @@ impl GlobalUniform {
             &uniform_buffer.buffer, (stable_buffer_offset, STABLE_DATA_SIZE))
          .with_uniform_buffer_range(1, uniform_visibility,
             &uniform_buffer.buffer, (dynamic_buffer_offset, DYNAMIC_DATA_SIZE))
+         .with_uniform_layout::<StableGlobalUniformData>(0)
+         .with_uniform_layout::<DynamicGlobalUniformData>(1)
          .build(&device, Some("Demo Bind Group"), None);
 
       let stable_data = StableGlobalUniformData {
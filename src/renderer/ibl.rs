@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use glam::Mat4;
+use wgpu::ShaderStages;
+use wgsl_derive::WgslStruct;
+
+use crate::image_loader::{self, PixelFormat};
+use crate::timer::ScopedTimer;
+
+use super::asset_loader::{AssetGUID, AssetLoader, TextureAsset};
+use super::mipmap;
+use super::pipeline_loader::RenderPipelineFlatDescriptor;
+use super::shader_loader::{FragmentShaderVariant, VertexShaderVariant};
+use super::webgpu::buffer::{Buffer, UniformBuffer};
+use super::webgpu::texture::TextureBuilder;
+use super::webgpu::uniform::BindGroupInfo;
+use super::webgpu::{PipelineLayoutBuilder, Utils};
+use super::LoadingArgs;
+
+pub const ENVIRONMENT_CUBE_SIZE: u32 = 512;
+pub const IRRADIANCE_CUBE_SIZE: u32 = 32;
+pub const PREFILTERED_CUBE_SIZE: u32 = 128;
+// roughness 0 at mip 0, roughness 1 at the last mip
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+
+const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+const NUM_CUBE_FACES: u32 = 6;
+
+// Queues the shaders of baking and the skybox, to be compiled while the environment image downloads
+pub fn precompile_shaders(loading_args: &LoadingArgs) {
+   use FragmentShaderVariant::*;
+   loading_args.precompile_shaders([VertexShaderVariant::TriangleFullscreen.into()]);
+   loading_args.precompile_shaders([EquirectToCube, MipmapBlit, IrradianceConvolution, SpecularPrefilter, BrdfLut, Skybox].map(Into::into));
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "BakeSettings")]
+pub(crate) struct BakeUniformData {
+   face: u32,
+   roughness: f32,
+   __padding: [f32; 2],
+}
+
+// Cubemaps baked from one equirectangular environment image.
+// `bind_group_info` is the IBL group for PBR shading:
+// irradiance cube (0), prefiltered specular cube (1), BRDF LUT (2), trilinear sampler (3)
+pub struct EnvironmentMaps {
+   pub environment_view: wgpu::TextureView,
+   pub irradiance_view: wgpu::TextureView,
+   pub prefiltered_view: wgpu::TextureView,
+   pub bind_group_info: BindGroupInfo,
+   _textures: [wgpu::Texture; 3],
+}
+
+struct BrdfLut {
+   _texture: wgpu::Texture,
+   view: wgpu::TextureView,
+}
+
+// Environments are baked once per asset, the BRDF LUT is shared by all of them
+pub struct IblCache {
+   environments: HashMap<AssetGUID, Rc<EnvironmentMaps>>,
+   brdf_lut: Option<BrdfLut>,
+}
+
+impl IblCache {
+   pub fn new() -> Self {
+      Self {
+         environments: HashMap::new(),
+         brdf_lut: None,
+      }
+   }
+
+   pub fn get(&self, guid: AssetGUID) -> Option<Rc<EnvironmentMaps>> {
+      self.environments.get(&guid).cloned()
+   }
+
+   // `equirect` must be fully loaded, it's uploaded to GPU and converted with several render passes
+   pub fn get_or_bake(&mut self, loading_args: &LoadingArgs, guid: AssetGUID, equirect: &TextureAsset) -> Rc<EnvironmentMaps> {
+      if let Some(environment) = self.environments.get(&guid) {
+         return environment.clone();
+      }
+      let environment = Rc::new(self.bake_environment(loading_args, equirect));
+      self.environments.insert(guid, environment.clone());
+      environment
+   }
+
+   pub fn unload(&mut self, guid: AssetGUID) {
+      self.environments.remove(&guid);
+   }
+
+   // drops environments baked from textures the asset loader has evicted
+   pub fn unload_evicted(&mut self, asset_loader: &AssetLoader) {
+      self.environments.retain(|guid, _| asset_loader.contains(*guid));
+   }
+
+   fn bake_environment(&mut self, loading_args: &LoadingArgs, equirect: &TextureAsset) -> EnvironmentMaps {
+      let _t = ScopedTimer::new("IblCache::bake_environment");
+      if self.brdf_lut.is_none() {
+         self.brdf_lut = Some(Self::bake_brdf_lut(loading_args));
+      }
+      let webgpu = loading_args.webgpu.clone();
+      let device = &webgpu.device;
+      let render_usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+
+      let (width, height, _) = equirect.dimensions();
+      // LDR images are treated as sRGB, HDR ones are sampled with filtering
+      let float32_filterable = device.features().contains(wgpu::Features::FLOAT32_FILTERABLE);
+      let converted;
+      let (format, data) = match equirect {
+         TextureAsset::Texture(info) if info.format == PixelFormat::Rgba32Float && !float32_filterable => {
+            converted = image_loader::convert_to_rgba16f(info);
+            (converted.format, converted.data.as_slice())
+         },
+         _ => (equirect.pixel_format(), equirect.data()),
+      };
+      let source = TextureBuilder::new_2d(wgpu::Extent3d {
+            width, height, depth_or_array_layers: 1,
+         }, format.texture_format(wgpu::TextureFormat::Rgba8UnormSrgb))
+         .add_usage(wgpu::TextureUsages::TEXTURE_BINDING)
+         .with_label(Some("Equirectangular environment"))
+         .build_with_data(device, &webgpu.queue, data);
+      let source_view = Utils::texture_view(&source, Some("Equirectangular environment view"));
+      // the prefilter samples coarser mips for rougher lobes, so its few samples don't alias
+      let environment = TextureBuilder::new_cube(ENVIRONMENT_CUBE_SIZE, CUBE_FORMAT)
+         .with_mip_level_count(mipmap::full_mip_level_count(ENVIRONMENT_CUBE_SIZE, ENVIRONMENT_CUBE_SIZE))
+         .add_usage(render_usage)
+         .with_label(Some("Environment cube"))
+         .build(device);
+      let irradiance = TextureBuilder::new_cube(IRRADIANCE_CUBE_SIZE, CUBE_FORMAT)
+         .add_usage(render_usage)
+         .with_label(Some("Irradiance cube"))
+         .build(device);
+      let prefiltered = TextureBuilder::new_cube(PREFILTERED_CUBE_SIZE, CUBE_FORMAT)
+         .with_mip_level_count(PREFILTERED_MIP_LEVELS)
+         .add_usage(render_usage)
+         .with_label(Some("Prefiltered specular cube"))
+         .build(device);
+      let environment_view = Utils::cube_texture_view(&environment, Some("Environment cube view"));
+
+      // every draw reads its own slot of the uniform buffer with a dynamic offset
+      let mut slots = vec![];
+      slots.extend((0..NUM_CUBE_FACES).map(|face| BakeUniformData { face, roughness: 0.0, __padding: Default::default() }));
+      slots.extend((0..NUM_CUBE_FACES).map(|face| BakeUniformData { face, roughness: 0.0, __padding: Default::default() }));
+      for mip in 0..PREFILTERED_MIP_LEVELS {
+         let roughness = mip as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+         slots.extend((0..NUM_CUBE_FACES).map(|face| BakeUniformData { face, roughness, __padding: Default::default() }));
+      }
+      const SLOT_DATA_SIZE: u64 = std::mem::size_of::<BakeUniformData>() as u64;
+      let slot_size = (device.limits().min_uniform_buffer_offset_alignment as u64).max(SLOT_DATA_SIZE);
+      let mut slots_bytes = vec![0_u8; slot_size as usize * slots.len()];
+      for (i, slot) in slots.iter().enumerate() {
+         let offset = i * slot_size as usize;
+         slots_bytes[offset..offset + SLOT_DATA_SIZE as usize].copy_from_slice(bytemuck::bytes_of(slot));
+      }
+      let bake_buffer = Buffer::new_uniform_init(device, &slots_bytes,
+         wgpu::BufferUsages::empty(), Some("IBL Bake Bind Buffer"));
+
+      let premade = loading_args.premade.borrow();
+      let sampler = &premade.samplers.trilinear_sampler;
+      let bake_group = BindGroupInfo::builder()
+         .with_dynamic_uniform_buffer(0, ShaderStages::FRAGMENT, &bake_buffer.buffer, SLOT_DATA_SIZE)
+         .with_uniform_layout::<BakeUniformData>(0)
+         .build(device, Some("IBL Bake Bind Group"), None);
+      let equirect_group = BindGroupInfo::builder()
+         .with_texture_2d(0, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &source_view)
+         .with_sampler(1, ShaderStages::FRAGMENT, sampler)
+         .build(device, Some("IBL Equirect Bind Group"), None);
+      let environment_group = BindGroupInfo::builder()
+         .with_texture_cube(0, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &environment_view)
+         .with_sampler(1, ShaderStages::FRAGMENT, sampler)
+         .build(device, Some("IBL Environment Bind Group"), None);
+
+      let equirect_pipeline = Self::build_bake_pipeline(loading_args,
+         FragmentShaderVariant::EquirectToCube, &[&bake_group, &equirect_group], CUBE_FORMAT);
+      let irradiance_pipeline = Self::build_bake_pipeline(loading_args,
+         FragmentShaderVariant::IrradianceConvolution, &[&bake_group, &environment_group], CUBE_FORMAT);
+      let prefilter_pipeline = Self::build_bake_pipeline(loading_args,
+         FragmentShaderVariant::SpecularPrefilter, &[&bake_group, &environment_group], CUBE_FORMAT);
+
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+         label: Some("IBL Bake Encoder"),
+      });
+      let mut slot = 0;
+      let mut next_offset = || {
+         let offset = (slot * slot_size) as u32;
+         slot += 1;
+         offset
+      };
+      for face in 0..NUM_CUBE_FACES {
+         let target = Some(Utils::texture_view_layer(&environment, 0, face, Some("Environment face view")));
+         Self::draw_fullscreen(&mut encoder, &target, &equirect_pipeline, &[
+            (&bake_group.bind_group, &[next_offset()]),
+            (&equirect_group.bind_group, &[]),
+         ]);
+      }
+      mipmap::generate_mipmaps_gpu(loading_args, &mut encoder, &environment);
+      for face in 0..NUM_CUBE_FACES {
+         let target = Some(Utils::texture_view_layer(&irradiance, 0, face, Some("Irradiance face view")));
+         Self::draw_fullscreen(&mut encoder, &target, &irradiance_pipeline, &[
+            (&bake_group.bind_group, &[next_offset()]),
+            (&environment_group.bind_group, &[]),
+         ]);
+      }
+      for mip in 0..PREFILTERED_MIP_LEVELS {
+         for face in 0..NUM_CUBE_FACES {
+            let target = Some(Utils::texture_view_layer(&prefiltered, mip, face, Some("Prefiltered face view")));
+            Self::draw_fullscreen(&mut encoder, &target, &prefilter_pipeline, &[
+               (&bake_group.bind_group, &[next_offset()]),
+               (&environment_group.bind_group, &[]),
+            ]);
+         }
+      }
+      webgpu.queue.submit(std::iter::once(encoder.finish()));
+
+      let irradiance_view = Utils::cube_texture_view(&irradiance, Some("Irradiance cube view"));
+      let prefiltered_view = Utils::cube_texture_view(&prefiltered, Some("Prefiltered specular cube view"));
+      let brdf_lut = self.brdf_lut.as_ref().unwrap();
+      let bind_group_info = BindGroupInfo::builder()
+         .with_texture_cube(0, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &irradiance_view)
+         .with_texture_cube(1, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &prefiltered_view)
+         .with_texture_2d(2, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &brdf_lut.view)
+         .with_sampler(3, ShaderStages::FRAGMENT, sampler)
+         .build(device, Some("IBL Bind Group"), None);
+      EnvironmentMaps {
+         environment_view,
+         irradiance_view,
+         prefiltered_view,
+         bind_group_info,
+         _textures: [environment, irradiance, prefiltered],
+      }
+   }
+
+   fn bake_brdf_lut(loading_args: &LoadingArgs) -> BrdfLut {
+      let _t = ScopedTimer::new("IblCache::bake_brdf_lut");
+      let device = &loading_args.webgpu.device;
+      let texture = TextureBuilder::new_2d(wgpu::Extent3d {
+            width: BRDF_LUT_SIZE, height: BRDF_LUT_SIZE, depth_or_array_layers: 1,
+         }, BRDF_LUT_FORMAT)
+         .add_usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+         .with_label(Some("BRDF LUT"))
+         .build(device);
+      let view = Some(Utils::texture_view(&texture, Some("BRDF LUT view")));
+      let pipeline = Self::build_bake_pipeline(loading_args,
+         FragmentShaderVariant::BrdfLut, &[], BRDF_LUT_FORMAT);
+      let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+         label: Some("BRDF LUT Encoder"),
+      });
+      Self::draw_fullscreen(&mut encoder, &view, &pipeline, &[]);
+      loading_args.webgpu.queue.submit(std::iter::once(encoder.finish()));
+      BrdfLut { _texture: texture, view: view.unwrap() }
+   }
+
+   fn build_bake_pipeline(loading_args: &LoadingArgs, variant: FragmentShaderVariant, groups: &[&BindGroupInfo], format: wgpu::TextureFormat) -> Rc<wgpu::RenderPipeline> {
+      let (vs, [fs]) = loading_args.get_shaders(VertexShaderVariant::TriangleFullscreen, [variant]);
+      loading_args.check_bindings(&[&vs, &fs], groups);
+      let layout_builder = PipelineLayoutBuilder::from_uniform_iter(groups.iter().copied());
+      let layout_descriptor = layout_builder.build_descriptor(Some("IBL Bake Pipeline Layout"));
+      let pipeline_layout = loading_args.webgpu.device.create_pipeline_layout(&layout_descriptor);
+      loading_args.get_pipeline(&RenderPipelineFlatDescriptor::new(
+         &layout_descriptor,
+         &wgpu::RenderPipelineDescriptor {
+            label: Some("IBL Bake Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+               module: &vs,
+               entry_point: "vs_main",
+               buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+               module: &fs,
+               entry_point: "fs_main",
+               targets: &[Some(wgpu::ColorTargetState {
+                  format,
+                  blend: Some(wgpu::BlendState::REPLACE),
+                  write_mask: wgpu::ColorWrites::ALL,
+               })],
+            }),
+            primitive: Utils::default_primitive_state(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+         }))
+   }
+
+   fn draw_fullscreen(encoder: &mut wgpu::CommandEncoder, target: &Option<wgpu::TextureView>, pipeline: &wgpu::RenderPipeline, bind_groups: &[(&wgpu::BindGroup, &[u32])]) {
+      let mut render_pass = Utils::default_renderpass(encoder, target, &None);
+      render_pass.set_pipeline(pipeline);
+      for (i, (bind_group, offsets)) in bind_groups.iter().enumerate() {
+         render_pass.set_bind_group(i as u32, bind_group, offsets);
+      }
+      render_pass.draw(0..3, 0..1);
+   }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "SkyboxSettings")]
+pub(crate) struct SkyboxUniformData {
+   inv_view_proj: [[f32; 4]; 4],
+   exposure: f32,
+   __padding: [f32; 3],
+}
+
+// Fullscreen background sampling the environment cube, drawn before opaque geometry
+pub struct Skybox {
+   data: SkyboxUniformData,
+   uniform_buffer: UniformBuffer,
+   bind_group_info: BindGroupInfo,
+   pipeline: Option<Rc<wgpu::RenderPipeline>>,
+}
+
+impl Skybox {
+   pub fn new(device: &wgpu::Device, environment: &EnvironmentMaps, sampler: &wgpu::Sampler) -> Self {
+      let uniform_buffer = Buffer::new_uniform::<SkyboxUniformData>(
+         device, wgpu::BufferUsages::COPY_DST, Some("Skybox Bind Buffer"));
+      let bind_group_info = BindGroupInfo::builder()
+         .with_uniform_buffer(0, ShaderStages::FRAGMENT, &uniform_buffer.buffer)
+         .with_uniform_layout::<SkyboxUniformData>(0)
+         .with_texture_cube(1, ShaderStages::FRAGMENT,
+            wgpu::TextureSampleType::Float { filterable: true }, &environment.environment_view)
+         .with_sampler(2, ShaderStages::FRAGMENT, sampler)
+         .build(device, Some("Skybox Bind Group"), None);
+      Self {
+         data: SkyboxUniformData {
+            inv_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            exposure: 1.0,
+            __padding: Default::default(),
+         },
+         uniform_buffer,
+         bind_group_info,
+         pipeline: None,
+      }
+   }
+
+   pub fn build_pipeline(&mut self, loading_args: &LoadingArgs) {
+      let (vs, [fs]) = loading_args.get_shaders(VertexShaderVariant::TriangleFullscreen, [FragmentShaderVariant::Skybox]);
+      loading_args.check_bindings(&[&vs, &fs], &[&self.bind_group_info]);
+      let layout_builder = PipelineLayoutBuilder::new()
+         .with(&self.bind_group_info);
+      let layout_descriptor = layout_builder.build_descriptor(Some("Skybox Pipeline Layout"));
+      let pipeline_layout = loading_args.webgpu.device.create_pipeline_layout(&layout_descriptor);
+      self.pipeline = Some(loading_args.get_pipeline(&RenderPipelineFlatDescriptor::new(
+         &layout_descriptor,
+         &wgpu::RenderPipelineDescriptor {
+            label: Some("Skybox Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+               module: &vs,
+               entry_point: "vs_main",
+               buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+               module: &fs,
+               entry_point: "fs_main",
+               targets: &[Some(wgpu::ColorTargetState {
+                  format: loading_args.color_texture_format,
+                  blend: Some(wgpu::BlendState::REPLACE),
+                  write_mask: wgpu::ColorWrites::ALL,
+               })],
+            }),
+            primitive: Utils::default_primitive_state(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+         })));
+   }
+
+   // translation of the view is ignored, the sky is infinitely far
+   pub fn set_camera(&mut self, view: Mat4, proj: Mat4) {
+      let mut rotation = view;
+      rotation.w_axis = glam::Vec4::W;
+      self.data.inv_view_proj = (proj * rotation).inverse().to_cols_array_2d();
+   }
+
+   pub fn set_exposure(&mut self, exposure: f32) {
+      self.data.exposure = exposure;
+   }
+
+   pub fn update_gpu(&self, queue: &wgpu::Queue) {
+      self.uniform_buffer.write(queue, 0, &[self.data]);
+   }
+
+   // binds its own group 0, callers should rebind theirs afterwards
+   pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+      let Some(pipeline) = self.pipeline.as_ref() else {
+         log::warn!("Skybox is skipped, its pipeline was not built");
+         return;
+      };
+      render_pass.set_pipeline(pipeline);
+      render_pass.set_bind_group(0, &self.bind_group_info.bind_group, &[]);
+      render_pass.draw(0..3, 0..1);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use std::cell::RefCell;
+   use crate::image_loader::TextureInfo;
+   use crate::renderer::{asset_loader::AssetLoader, Premade, Webgpu};
+
+   use super::*;
+
+   #[test]
+   fn environment_bakes() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let premade = Premade::new(&webgpu.device);
+      let loading_args = LoadingArgs {
+         webgpu: Rc::new(webgpu),
+         color_texture_format: wgpu::TextureFormat::Rgba8Unorm,
+         premade: Rc::new(RefCell::new(premade)),
+         asset_loader: Rc::new(RefCell::new(AssetLoader::new())),
+      };
+      // sky above, ground below
+      let (width, height) = (8, 4);
+      let data = (0..width * height)
+         .flat_map(|i| if i < width * height / 2 { [120_u8, 170, 255, 255] } else { [90, 70, 40, 255] })
+         .collect();
+      let equirect = TextureAsset::Texture(TextureInfo {
+         data, width, height, depth: 1, format: PixelFormat::Rgba8, mip_level_count: 1,
+      });
+      let mut cache = IblCache::new();
+      let environment = cache.bake_environment(&loading_args, &equirect);
+      assert!(cache.brdf_lut.is_some());
+      // mips down to 1x1 for the specular prefilter
+      assert_eq!(environment._textures[0].mip_level_count(), 10);
+
+      // HDR sun much brighter than the sky, converted to Rgba16Float without FLOAT32_FILTERABLE
+      let data = (0..width * height)
+         .flat_map(|i| if i == 0 { [50.0_f32, 45.0, 40.0, 1.0] } else { [0.5, 0.7, 1.0, 1.0] })
+         .flat_map(f32::to_le_bytes)
+         .collect();
+      let equirect = TextureAsset::Texture(TextureInfo {
+         data, width, height, depth: 1, format: PixelFormat::Rgba32Float, mip_level_count: 1,
+      });
+      cache.bake_environment(&loading_args, &equirect);
+   }
+}
@@ -23,6 +23,7 @@ impl ImguiRenderArgs {
 
 #[cfg(feature = "web")]
 pub mod web_platform;
+pub mod shader_editor;
 
 #[cfg(feature = "imgui_win")]
 pub fn init_from_winit(window: &winit::window::Window) -> (imgui::Context, imgui_winit_support::WinitPlatform) {
@@ -0,0 +1,168 @@
+use imgui::{Condition, Ui};
+
+use crate::renderer::preprocessor::Preprocessed;
+use crate::renderer::shader_loader::{ShaderError, ShaderLoader, UsedShader};
+use super::ImguiRenderArgs;
+
+const ERROR_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+// Shaders of the current demo, their preprocessed text, and edits of their files in memory.
+// `render` returns true once an edit is applied or dropped, pipelines have to be rebuilt then
+#[derive(Default)]
+pub struct ShaderEditor {
+   pub open: bool,
+   shader: Option<UsedShader>,
+   // the shader's file or one of its includes
+   file: Option<String>,
+   text: String,
+   // with the current edits, or why preprocessing failed
+   preprocessed: Option<Result<Preprocessed, ShaderError>>,
+   status: String,
+}
+
+impl ShaderEditor {
+   pub fn render(&mut self, ui: &Ui, args: ImguiRenderArgs, shader_loader: &mut ShaderLoader) -> bool {
+      if !self.open {
+         return false;
+      }
+      let (mut open, mut applied) = (true, false);
+      ui.window("Shader editor")
+         .size(args.size, Condition::FirstUseEver)
+         .position(args.position, Condition::FirstUseEver)
+         .opened(&mut open)
+         .build(|| applied = self.contents(ui, shader_loader));
+      self.open = open;
+      applied
+   }
+
+   fn contents(&mut self, ui: &Ui, shader_loader: &mut ShaderLoader) -> bool {
+      ui.child_window("Shaders").size([0.0, 90.0]).border(true).build(|| {
+         for shader in shader_loader.used_shaders().to_vec() {
+            let selected = self.shader.as_ref() == Some(&shader);
+            if ui.selectable_config(shader_label(&shader)).selected(selected).build() && !selected {
+               self.open_shader(shader_loader, shader);
+            }
+         }
+      });
+      let (Some(shader), Some(file)) = (self.shader.clone(), self.file.clone()) else {
+         ui.text_disabled("Select a shader the demo uses");
+         return false;
+      };
+      let files: Vec<String> = match &self.preprocessed {
+         Some(Ok(preprocessed)) => preprocessed.files.iter().map(|(file, _)| file.to_string()).collect(),
+         _ => vec![shader.path.clone()],
+      };
+      let mut file_index = files.iter().position(|path| *path == file).unwrap_or(0);
+      if ui.combo_simple_string("File", &mut file_index, &files) {
+         // changes which weren't applied are dropped
+         self.open_file(shader_loader, &files[file_index]);
+      }
+      let errors: Vec<ShaderError> = shader_loader.errors().values().cloned().collect();
+      let mut applied = false;
+      if let Some(_tab_bar) = ui.tab_bar("Shader editor tabs") {
+         if let Some(_tab) = ui.tab_item("Source") {
+            applied = self.source_tab(ui, shader_loader, &shader, &file, &errors);
+         }
+         if let Some(_tab) = ui.tab_item("Preprocessed") {
+            self.preprocessed_tab(ui, &errors);
+         }
+      }
+      applied
+   }
+
+   fn source_tab(&mut self, ui: &Ui, shader_loader: &mut ShaderLoader, shader: &UsedShader, file: &str, errors: &[ShaderError]) -> bool {
+      let mut applied = false;
+      if ui.button("Recompile") {
+         // an edit equal to the file would only hide later changes of it
+         if !self.read(shader_loader, file).is_ok_and(|text| text == self.text) {
+            shader_loader.edit(file, self.text.clone());
+         }
+         self.status.clear();
+         applied = true;
+      }
+      ui.same_line();
+      if ui.button("Revert") {
+         applied = shader_loader.discard_edit(file);
+         self.open_file(shader_loader, file);
+      }
+      #[cfg(not(feature = "web"))]
+      if shader_loader.is_edited(file) {
+         ui.same_line();
+         if ui.button("Save to disk") {
+            self.status = match shader_loader.save_edit(file) {
+               Ok(saved) => format!("Saved {}", saved.display()),
+               Err(e) => e,
+            };
+         }
+      }
+      if shader_loader.is_edited(file) {
+         ui.same_line();
+         ui.text_disabled("edited in memory");
+      }
+      if !self.status.is_empty() {
+         ui.text_disabled(&self.status);
+      }
+      for error in errors.iter().filter(|error| error.file == file) {
+         ui.text_colored(ERROR_COLOR, error.to_string());
+      }
+      ui.input_text_multiline("##source", &mut self.text, [-1.0, -1.0])
+         .allow_tab_input(true)
+         .build();
+      if applied {
+         self.preprocessed = Some(shader_loader.preprocessed(shader));
+      }
+      applied
+   }
+
+   // lines with an error are highlighted, the message follows them
+   fn preprocessed_tab(&self, ui: &Ui, errors: &[ShaderError]) {
+      ui.child_window("Preprocessed text").build(|| match &self.preprocessed {
+         Some(Ok(preprocessed)) => {
+            for (i, (line, origin)) in preprocessed.text.lines().zip(&preprocessed.origins).enumerate() {
+               let text = format!("{:4} {line}", i + 1);
+               let error = errors.iter()
+                  .find(|error| error.file == *origin.file && error.location.map(|(line, _)| line) == Some(origin.line));
+               match error {
+                  Some(error) => {
+                     ui.text_colored(ERROR_COLOR, text);
+                     ui.text_colored(ERROR_COLOR, format!("     {}:{}: {}", origin.file, origin.line, error.message));
+                  },
+                  None => ui.text(text),
+               }
+            }
+         },
+         Some(Err(error)) => ui.text_colored(ERROR_COLOR, error.to_string()),
+         None => {},
+      });
+   }
+
+   fn open_shader(&mut self, shader_loader: &ShaderLoader, shader: UsedShader) {
+      self.preprocessed = Some(shader_loader.preprocessed(&shader));
+      self.open_file(shader_loader, &shader.path);
+      self.shader = Some(shader);
+   }
+
+   fn open_file(&mut self, shader_loader: &ShaderLoader, file: &str) {
+      match self.read(shader_loader, file) {
+         Ok(text) => {
+            self.text = text;
+            self.status.clear();
+         },
+         Err(e) => {
+            self.text.clear();
+            self.status = e;
+         },
+      }
+      self.file = Some(file.to_owned());
+   }
+
+   fn read(&self, shader_loader: &ShaderLoader, file: &str) -> Result<String, String> {
+      let bytes = shader_loader.sources().read_now(file).map_err(|e| e.to_string())?;
+      String::from_utf8(bytes).map_err(|e| format!("{file}: {e}"))
+   }
+}
+
+// "shaders/mandelbrot.fs.wgsl USE_ANTIALIASING=1"
+fn shader_label(shader: &UsedShader) -> String {
+   shader.defines.iter().fold(shader.path.clone(), |label, (name, value)| format!("{label} {name}={value}"))
+}
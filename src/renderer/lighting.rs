@@ -0,0 +1,475 @@
+use std::rc::Rc;
+
+use glam::{Mat4, Vec3};
+use wgpu::ShaderStages;
+use wgsl_derive::WgslStruct;
+
+use crate::timer::ScopedTimer;
+use crate::GraphicsLevel;
+
+use super::pipeline_loader::RenderPipelineFlatDescriptor;
+use super::shader_loader::VertexShaderVariant;
+use super::webgpu::buffer::{Buffer, UniformBuffer};
+use super::webgpu::texture::TextureBuilder;
+use super::webgpu::uniform::BindGroupInfo;
+use super::webgpu::{PipelineLayoutBuilder, Utils};
+use super::LoadingArgs;
+
+pub const MAX_POINT_LIGHTS: usize = 4;
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+const SHADOW_MAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// directional light + its view projection, the part of the buffer needed by the shadow pass
+const SHADOW_CASTER_DATA_SIZE: u64 = (std::mem::size_of::<DirectionalLight>() + std::mem::size_of::<[[f32; 4]; 4]>()) as u64;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+pub struct DirectionalLight {
+   // direction in which the light rays travel
+   pub direction: [f32; 3],
+   pub intensity: f32,
+   pub color: [f32; 3],
+   pub shadow_bias: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+pub struct PointLight {
+   pub position: [f32; 3],
+   pub range: f32,
+   pub color: [f32; 3],
+   pub intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+pub struct SpotLight {
+   pub position: [f32; 3],
+   pub range: f32,
+   pub direction: [f32; 3],
+   pub intensity: f32,
+   pub color: [f32; 3],
+   // cosines of the cone half-angles, full intensity inside `inner_cos`, zero outside `outer_cos`
+   pub inner_cos: f32,
+   pub outer_cos: f32,
+   __padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(name = "Lights")]
+pub struct LightsUniformData {
+   pub directional: DirectionalLight,
+   pub shadow_view_proj: [[f32; 4]; 4],
+   pub point_lights: [PointLight; MAX_POINT_LIGHTS],
+   pub spot_lights: [SpotLight; MAX_SPOT_LIGHTS],
+   pub num_point_lights: u32,
+   pub num_spot_lights: u32,
+   pub shadow_map_size: f32,
+   // PCF kernel is (2*radius + 1)^2 taps, negative radius disables shadows
+   pub pcf_radius: i32,
+}
+
+impl Default for DirectionalLight {
+   fn default() -> Self {
+      Self {
+         direction: [-0.4, -0.5, -0.75],
+         intensity: 1.0,
+         color: [1.0, 0.97, 0.9],
+         shadow_bias: 0.002,
+      }
+   }
+}
+
+impl Default for PointLight {
+   fn default() -> Self {
+      Self {
+         position: [0.0, 0.0, 0.5],
+         range: 1.5,
+         color: [1.0, 0.6, 0.3],
+         intensity: 1.0,
+      }
+   }
+}
+
+impl Default for SpotLight {
+   fn default() -> Self {
+      Self {
+         position: [0.0, 0.0, 1.0],
+         range: 3.0,
+         direction: [0.0, 0.0, -1.0],
+         intensity: 1.0,
+         color: [0.3, 0.6, 1.0],
+         inner_cos: 0.95,
+         outer_cos: 0.85,
+         __padding: Default::default(),
+      }
+   }
+}
+
+// (shadow map resolution, PCF radius), shadows are disabled at `None`
+fn shadow_settings(graphics_level: GraphicsLevel) -> Option<(u32, i32)> {
+   match graphics_level {
+      GraphicsLevel::Minimal => None,
+      GraphicsLevel::Low => Some((512, 0)),
+      GraphicsLevel::Medium => Some((1024, 1)),
+      GraphicsLevel::High => Some((2048, 1)),
+      GraphicsLevel::Ultra => Some((4096, 2)),
+   }
+}
+
+pub struct ShadowMap {
+   pub texture: wgpu::Texture,
+   pub view: wgpu::TextureView,
+   pub size: u32,
+}
+
+impl ShadowMap {
+   fn new(device: &wgpu::Device, size: u32) -> Self {
+      let texture = TextureBuilder::new_2d(wgpu::Extent3d {
+            width: size, height: size, depth_or_array_layers: 1,
+         }, SHADOW_MAP_FORMAT)
+         .add_usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+         .with_label(Some("Shadow map"))
+         .build(device);
+      let view = Utils::texture_view(&texture, Some("Shadow map view"));
+      Self { texture, view, size }
+   }
+}
+
+// Lights of a scene, and the shadow map of its directional light.
+// Demos own an instance, bind `bind_group_info` to sample the lights in shaders,
+// and call `render_shadow_pass` to draw shadow casters before the main pass
+pub struct Lighting {
+   pub data: LightsUniformData,
+   pub uniform_buffer: UniformBuffer,
+   pub bind_group_info: BindGroupInfo,
+   shadow_pass_bind_group: BindGroupInfo,
+   shadow_map: ShadowMap,
+   shadow_sampler: wgpu::Sampler,
+   shadow_pipeline: Option<Rc<wgpu::RenderPipeline>>,
+   shadows_enabled: bool,
+   scene_bounds: (Vec3, f32),
+}
+
+impl Lighting {
+   pub fn new(device: &wgpu::Device, graphics_level: GraphicsLevel) -> Self {
+      let _t = ScopedTimer::new("Lighting::new");
+      let uniform_buffer = Buffer::new_uniform::<LightsUniformData>(
+         device, wgpu::BufferUsages::COPY_DST, Some("Lights Bind Buffer"));
+      let shadow_sampler = device.create_sampler(&Utils::shadow_comparison_sampler());
+      let settings = shadow_settings(graphics_level);
+      // a minimal shadow map is still allocated to keep the bind group layout valid
+      let shadow_map = ShadowMap::new(device, settings.map_or(1, |(size, _)| size));
+      let bind_group_info = Self::make_bind_group(device, &uniform_buffer, &shadow_map, &shadow_sampler);
+      let shadow_pass_bind_group = BindGroupInfo::builder()
+         .with_uniform_buffer_range(0, ShaderStages::VERTEX,
+            &uniform_buffer.buffer, (0, SHADOW_CASTER_DATA_SIZE))
+         .build(device, Some("Shadow Pass Bind Group"), None);
+      let mut data = LightsUniformData {
+         directional: Default::default(),
+         shadow_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+         point_lights: [Default::default(); MAX_POINT_LIGHTS],
+         spot_lights: [Default::default(); MAX_SPOT_LIGHTS],
+         num_point_lights: 0,
+         num_spot_lights: 0,
+         shadow_map_size: shadow_map.size as f32,
+         pcf_radius: settings.map_or(-1, |(_, radius)| radius),
+      };
+      data.shadow_view_proj = Self::directional_view_proj(&data.directional, Vec3::ZERO, 1.0).to_cols_array_2d();
+      Self {
+         data,
+         uniform_buffer,
+         bind_group_info,
+         shadow_pass_bind_group,
+         shadow_map,
+         shadow_sampler,
+         shadow_pipeline: None,
+         shadows_enabled: settings.is_some(),
+         scene_bounds: (Vec3::ZERO, 1.0),
+      }
+   }
+
+   fn make_bind_group(device: &wgpu::Device, uniform_buffer: &UniformBuffer, shadow_map: &ShadowMap, shadow_sampler: &wgpu::Sampler) -> BindGroupInfo {
+      let visibility = ShaderStages::FRAGMENT | ShaderStages::VERTEX;
+      BindGroupInfo::builder()
+         .with_uniform_buffer(0, visibility, &uniform_buffer.buffer)
+         .with_uniform_layout::<LightsUniformData>(0)
+         .with_texture_2d(1, ShaderStages::FRAGMENT, wgpu::TextureSampleType::Depth, &shadow_map.view)
+         .with_comparison_sampler(2, ShaderStages::FRAGMENT, shadow_sampler)
+         .build(device, Some("Lights Bind Group"), None)
+   }
+
+   // resizes the shadow map, the bind group layout stays the same, so pipelines remain valid
+   pub fn set_graphics_level(&mut self, device: &wgpu::Device, graphics_level: GraphicsLevel) {
+      let settings = shadow_settings(graphics_level);
+      let size = settings.map_or(1, |(size, _)| size);
+      self.shadows_enabled = settings.is_some();
+      self.data.pcf_radius = settings.map_or(-1, |(_, radius)| radius);
+      if size != self.shadow_map.size {
+         self.shadow_map = ShadowMap::new(device, size);
+         self.bind_group_info.rebuild(device, vec![
+            wgpu::BindGroupEntry { binding: 0, resource: self.uniform_buffer.buffer.as_entire_binding() },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&self.shadow_map.view) },
+            wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(&self.shadow_sampler) },
+         ], Some("Lights Bind Group"));
+      }
+      self.data.shadow_map_size = size as f32;
+   }
+
+   // depth-only pipeline for shadow casters, whose vertex buffer has position at location 0
+   pub fn build_shadow_pipeline(&mut self, loading_args: &LoadingArgs, vertex_layout: wgpu::VertexBufferLayout) {
+      let _t = ScopedTimer::new("Lighting::build_shadow_pipeline");
+      let vs = loading_args.get_vertex_shader(VertexShaderVariant::ShadowDepth)
+         .unwrap_or_else(|_| loading_args.error_shader());
+      loading_args.check_bindings(&[&vs], &[&self.shadow_pass_bind_group]);
+      let layout_builder = PipelineLayoutBuilder::new()
+         .with(&self.shadow_pass_bind_group);
+      let layout_descriptor = layout_builder.build_descriptor(Some("Shadow Pipeline Layout"));
+      let pipeline_layout = loading_args.webgpu.device.create_pipeline_layout(&layout_descriptor);
+      self.shadow_pipeline = Some(loading_args.get_pipeline(&RenderPipelineFlatDescriptor::new(
+         &layout_descriptor,
+         &wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+               module: &vs,
+               entry_point: "vs_main",
+               buffers: &[vertex_layout],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+               // flat casters should cast shadows from both sides
+               cull_mode: None,
+               ..Utils::default_primitive_state()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+               format: SHADOW_MAP_FORMAT,
+               depth_write_enabled: true,
+               depth_compare: wgpu::CompareFunction::LessEqual,
+               stencil: Default::default(),
+               bias: wgpu::DepthBiasState {
+                  constant: 2,
+                  slope_scale: 2.0,
+                  clamp: 0.0,
+               },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+         })));
+   }
+
+   pub fn shadows_enabled(&self) -> bool { self.shadows_enabled }
+
+   pub fn directional_mut(&mut self) -> &mut DirectionalLight { &mut self.data.directional }
+
+   pub fn point_lights(&self) -> &[PointLight] {
+      &self.data.point_lights[..self.data.num_point_lights as usize]
+   }
+
+   pub fn spot_lights(&self) -> &[SpotLight] {
+      &self.data.spot_lights[..self.data.num_spot_lights as usize]
+   }
+
+   pub fn add_point_light(&mut self, light: PointLight) -> Option<usize> {
+      let idx = self.data.num_point_lights as usize;
+      if idx >= MAX_POINT_LIGHTS {
+         return None;
+      }
+      self.data.point_lights[idx] = light;
+      self.data.num_point_lights += 1;
+      Some(idx)
+   }
+
+   pub fn add_spot_light(&mut self, light: SpotLight) -> Option<usize> {
+      let idx = self.data.num_spot_lights as usize;
+      if idx >= MAX_SPOT_LIGHTS {
+         return None;
+      }
+      self.data.spot_lights[idx] = light;
+      self.data.num_spot_lights += 1;
+      Some(idx)
+   }
+
+   pub fn remove_point_light(&mut self, idx: usize) {
+      let count = self.data.num_point_lights as usize;
+      if idx < count {
+         self.data.point_lights.copy_within(idx + 1..count, idx);
+         self.data.num_point_lights -= 1;
+      }
+   }
+
+   pub fn remove_spot_light(&mut self, idx: usize) {
+      let count = self.data.num_spot_lights as usize;
+      if idx < count {
+         self.data.spot_lights.copy_within(idx + 1..count, idx);
+         self.data.num_spot_lights -= 1;
+      }
+   }
+
+   // bounding sphere of the shadow casters, the directional shadow frustum is fit around it
+   pub fn set_scene_bounds(&mut self, center: Vec3, radius: f32) {
+      self.scene_bounds = (center, radius);
+   }
+
+   fn directional_view_proj(light: &DirectionalLight, center: Vec3, radius: f32) -> Mat4 {
+      let direction = Vec3::from(light.direction).normalize_or_zero();
+      let direction = if direction == Vec3::ZERO { Vec3::NEG_Z } else { direction };
+      let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+      let eye = center - direction * 2.0 * radius;
+      let view = Mat4::look_at_rh(eye, center, up);
+      let proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 4.0 * radius);
+      proj * view
+   }
+
+   pub fn update_gpu(&mut self, queue: &wgpu::Queue) {
+      let (center, radius) = self.scene_bounds;
+      self.data.shadow_view_proj = Self::directional_view_proj(
+         &self.data.directional, center, radius).to_cols_array_2d();
+      self.uniform_buffer.write(queue, 0, &[self.data]);
+   }
+
+   // `draw_casters` should bind vertex/index buffers and issue draw calls,
+   // the shadow pipeline and its bind group 0 are already set
+   pub fn render_shadow_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, draw_casters: impl FnOnce(&mut wgpu::RenderPass<'a>)) {
+      if !self.shadows_enabled {
+         return;
+      }
+      let Some(pipeline) = self.shadow_pipeline.as_ref() else {
+         log::warn!("Lighting shadow pass is skipped, shadow pipeline was not built");
+         return;
+      };
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+         label: Some("Shadow Pass"),
+         color_attachments: &[],
+         depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &self.shadow_map.view,
+            depth_ops: Some(wgpu::Operations {
+               load: wgpu::LoadOp::Clear(1.0),
+               store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+         }),
+         occlusion_query_set: None,
+         timestamp_writes: None,
+      });
+      render_pass.set_pipeline(pipeline);
+      render_pass.set_bind_group(0, &self.shadow_pass_bind_group.bind_group, &[]);
+      draw_casters(&mut render_pass);
+   }
+
+   // editor window with gizmos drawn over the scene,
+   // `view_proj` maps world positions of the lights into clip space of the demo's camera
+   #[cfg(any(feature = "imgui_win", feature = "imgui_web"))]
+   pub fn render_imgui(&mut self, ui: &imgui::Ui, args: super::imgui_web::ImguiRenderArgs, view_proj: Mat4) {
+      use imgui::*;
+      let window = ui.window("Lighting");
+      window
+         .size(args.size, Condition::FirstUseEver)
+         .position(args.position, Condition::FirstUseEver)
+         .always_auto_resize(true)
+         .build(|| {
+            if ui.collapsing_header("Directional", TreeNodeFlags::DEFAULT_OPEN) {
+               let light = &mut self.data.directional;
+               imgui::Drag::new("Direction##dir")
+                  .range(-1.0, 1.0)
+                  .speed(0.01)
+                  .build_array(ui, &mut light.direction);
+               ui.color_edit3("Color##dir", &mut light.color);
+               imgui::Drag::new("Intensity##dir")
+                  .range(0.0, 10.0)
+                  .speed(0.01)
+                  .build(ui, &mut light.intensity);
+               imgui::Drag::new("Shadow bias")
+                  .range(0.0, 0.05)
+                  .speed(0.0001)
+                  .build(ui, &mut light.shadow_bias);
+               ui.text(format!("Shadow map: {}x{}, PCF radius: {}",
+                  self.shadow_map.size, self.shadow_map.size, self.data.pcf_radius));
+            }
+            if ui.collapsing_header("Point lights", TreeNodeFlags::empty()) {
+               let mut to_remove = None;
+               for (i, light) in self.data.point_lights.iter_mut()
+                  .take(self.data.num_point_lights as usize).enumerate() {
+                  let _id = ui.push_id_usize(i);
+                  imgui::Drag::new("Position").speed(0.01).build_array(ui, &mut light.position);
+                  ui.color_edit3("Color", &mut light.color);
+                  imgui::Drag::new("Intensity").range(0.0, 10.0).speed(0.01).build(ui, &mut light.intensity);
+                  imgui::Drag::new("Range").range(0.01, 100.0).speed(0.01).build(ui, &mut light.range);
+                  if ui.button("Remove") {
+                     to_remove = Some(i);
+                  }
+                  ui.separator();
+               }
+               if let Some(i) = to_remove {
+                  self.remove_point_light(i);
+               }
+               if ui.button("Add point light") {
+                  self.add_point_light(Default::default());
+               }
+            }
+            if ui.collapsing_header("Spot lights", TreeNodeFlags::empty()) {
+               let mut to_remove = None;
+               for (i, light) in self.data.spot_lights.iter_mut()
+                  .take(self.data.num_spot_lights as usize).enumerate() {
+                  let _id = ui.push_id_usize(i);
+                  imgui::Drag::new("Position").speed(0.01).build_array(ui, &mut light.position);
+                  imgui::Drag::new("Direction").range(-1.0, 1.0).speed(0.01).build_array(ui, &mut light.direction);
+                  ui.color_edit3("Color", &mut light.color);
+                  imgui::Drag::new("Intensity").range(0.0, 10.0).speed(0.01).build(ui, &mut light.intensity);
+                  imgui::Drag::new("Range").range(0.01, 100.0).speed(0.01).build(ui, &mut light.range);
+                  imgui::Drag::new("Inner cos").range(light.outer_cos, 1.0).speed(0.001).build(ui, &mut light.inner_cos);
+                  imgui::Drag::new("Outer cos").range(0.0, light.inner_cos).speed(0.001).build(ui, &mut light.outer_cos);
+                  if ui.button("Remove") {
+                     to_remove = Some(i);
+                  }
+                  ui.separator();
+               }
+               if let Some(i) = to_remove {
+                  self.remove_spot_light(i);
+               }
+               if ui.button("Add spot light") {
+                  self.add_spot_light(Default::default());
+               }
+            }
+         });
+      self.render_gizmos(ui, view_proj);
+   }
+
+   #[cfg(any(feature = "imgui_win", feature = "imgui_web"))]
+   fn render_gizmos(&self, ui: &imgui::Ui, view_proj: Mat4) {
+      let display_size = ui.io().display_size;
+      let to_screen = |world: Vec3| -> Option<[f32; 2]> {
+         let clip = view_proj * world.extend(1.0);
+         if clip.w <= 0.0 {
+            return None;
+         }
+         let ndc = clip.truncate() / clip.w;
+         Some([(ndc.x * 0.5 + 0.5) * display_size[0], (0.5 - ndc.y * 0.5) * display_size[1]])
+      };
+      let to_color = |color: [f32; 3]| [color[0], color[1], color[2], 1.0];
+      let draw_list = ui.get_foreground_draw_list();
+
+      let (center, radius) = self.scene_bounds;
+      let direction = Vec3::from(self.data.directional.direction).normalize_or_zero();
+      if let (Some(from), Some(to)) = (to_screen(center - direction * radius), to_screen(center)) {
+         let color = to_color(self.data.directional.color);
+         draw_list.add_line(from, to, color).thickness(2.0).build();
+         draw_list.add_circle(from, 6.0, color).filled(true).build();
+      }
+      for light in self.point_lights() {
+         if let Some(position) = to_screen(light.position.into()) {
+            draw_list.add_circle(position, 6.0, to_color(light.color)).filled(true).build();
+         }
+      }
+      for light in self.spot_lights() {
+         let position = Vec3::from(light.position);
+         let direction = Vec3::from(light.direction).normalize_or_zero();
+         if let (Some(from), Some(to)) = (to_screen(position), to_screen(position + direction * 0.25 * light.range)) {
+            let color = to_color(light.color);
+            draw_list.add_line(from, to, color).thickness(2.0).build();
+            draw_list.add_circle(from, 6.0, color).build();
+         }
+      }
+   }
+}
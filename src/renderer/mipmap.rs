@@ -0,0 +1,200 @@
+use std::rc::Rc;
+
+use wgpu::ShaderStages;
+
+use crate::image_loader::{PixelFormat, TextureInfo};
+use crate::timer::ScopedTimer;
+
+use super::pipeline_loader::RenderPipelineFlatDescriptor;
+use super::shader_loader::{FragmentShaderVariant, VertexShaderVariant};
+use super::webgpu::uniform::BindGroupInfo;
+use super::webgpu::{PipelineLayoutBuilder, Utils};
+use super::LoadingArgs;
+
+// mip levels down to 1x1
+pub fn full_mip_level_count(width: u32, height: u32) -> u32 {
+   32 - width.max(height).max(1).leading_zeros()
+}
+
+// mips are generated with render passes, each level sampling the previous one
+pub fn supports_gpu_mipmaps(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+   let renderable = format.guaranteed_format_features(device.features())
+      .allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+   let filterable = matches!(format.sample_type(None, Some(device.features())),
+      Some(wgpu::TextureSampleType::Float { filterable: true }));
+   renderable && filterable
+}
+
+// CPU fallback handles formats with 8 bits per channel
+pub fn supports_cpu_mipmaps(format: wgpu::TextureFormat) -> bool {
+   use wgpu::TextureFormat::*;
+   matches!(format, R8Unorm | Rg8Unorm | Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb)
+}
+
+// fills mips 1.. of every layer of `texture` from its mip 0,
+// the texture needs RENDER_ATTACHMENT and TEXTURE_BINDING usages
+pub fn generate_mipmaps_gpu(loading_args: &LoadingArgs, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+   let _t = ScopedTimer::new("generate_mipmaps_gpu");
+   assert_eq!(texture.dimension(), wgpu::TextureDimension::D2, "Mipmaps of 1D/3D textures are not supported");
+   let device = &loading_args.webgpu.device;
+   let premade = loading_args.premade.borrow();
+   let mut pipeline = None;
+   for layer in 0..texture.depth_or_array_layers() {
+      for mip in 1..texture.mip_level_count() {
+         let source_view = Utils::texture_view_layer(texture, mip - 1, layer, Some("Mipmap source view"));
+         let target = Some(Utils::texture_view_layer(texture, mip, layer, Some("Mipmap target view")));
+         let bind_group = BindGroupInfo::builder()
+            .with_texture_2d(0, ShaderStages::FRAGMENT,
+               wgpu::TextureSampleType::Float { filterable: true }, &source_view)
+            .with_sampler(1, ShaderStages::FRAGMENT, &premade.samplers.trilinear_sampler)
+            .build(device, Some("Mipmap Bind Group"), None);
+         let pipeline = pipeline.get_or_insert_with(||
+            build_blit_pipeline(loading_args, &bind_group, texture.format()));
+         let mut render_pass = Utils::default_renderpass(encoder, &target, &None);
+         render_pass.set_pipeline(pipeline);
+         render_pass.set_bind_group(0, &bind_group.bind_group, &[]);
+         render_pass.draw(0..3, 0..1);
+      }
+   }
+}
+
+fn build_blit_pipeline(loading_args: &LoadingArgs, bind_group: &BindGroupInfo, format: wgpu::TextureFormat) -> Rc<wgpu::RenderPipeline> {
+   let (vs, [fs]) = loading_args.get_shaders(VertexShaderVariant::TriangleFullscreen, [FragmentShaderVariant::MipmapBlit]);
+   loading_args.check_bindings(&[&vs, &fs], &[bind_group]);
+   let layout_builder = PipelineLayoutBuilder::new()
+      .with(bind_group);
+   let layout_descriptor = layout_builder.build_descriptor(Some("Mipmap Pipeline Layout"));
+   let pipeline_layout = loading_args.webgpu.device.create_pipeline_layout(&layout_descriptor);
+   loading_args.get_pipeline(&RenderPipelineFlatDescriptor::new(
+      &layout_descriptor,
+      &wgpu::RenderPipelineDescriptor {
+         label: Some("Mipmap Pipeline"),
+         layout: Some(&pipeline_layout),
+         vertex: wgpu::VertexState {
+            module: &vs,
+            entry_point: "vs_main",
+            buffers: &[],
+         },
+         fragment: Some(wgpu::FragmentState {
+            module: &fs,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+               format,
+               blend: Some(wgpu::BlendState::REPLACE),
+               write_mask: wgpu::ColorWrites::ALL,
+            })],
+         }),
+         primitive: Utils::default_primitive_state(),
+         depth_stencil: None,
+         multisample: wgpu::MultisampleState::default(),
+         multiview: None,
+      }))
+}
+
+// 2x2 box filter down to 1x1, levels are stored one after another in the returned data.
+// With `srgb` the first 3 channels are averaged in linear space, alpha is always linear
+pub fn generate_mipmaps_cpu(info: &TextureInfo, srgb: bool) -> TextureInfo {
+   let _t = ScopedTimer::new("generate_mipmaps_cpu");
+   assert_eq!(info.depth, 1, "CPU mipmaps of texture arrays are not supported");
+   assert_eq!(info.format, PixelFormat::Rgba8, "CPU mipmaps are generated for 8 bit data only");
+   let stride = info.pixel_stride() as usize;
+   let level_size = |width: u32, height: u32| width as usize * height as usize * stride;
+   let mip_level_count = full_mip_level_count(info.width, info.height);
+   let base_level = &info.data[..level_size(info.width, info.height)];
+   let to_linear: Vec<f32> = (0..=255_u8).map(|c| srgb_to_linear(c as f32 / 255.0)).collect();
+
+   let mut data = Vec::with_capacity(base_level.len() * 4 / 3 + stride);
+   data.extend_from_slice(base_level);
+   let (mut width, mut height) = (info.width, info.height);
+   let mut source_offset = 0;
+   for _ in 1..mip_level_count {
+      let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+      let target_offset = data.len();
+      for y in 0..next_height {
+         for x in 0..next_width {
+            // clamped, so odd sizes drop the last row/column instead of reading out of bounds
+            let xs = [(2 * x).min(width - 1), (2 * x + 1).min(width - 1)];
+            let ys = [(2 * y).min(height - 1), (2 * y + 1).min(height - 1)];
+            for channel in 0..stride {
+               let is_color = srgb && channel < 3;
+               let mut sum = 0.0;
+               for sy in ys {
+                  for sx in xs {
+                     let texel = data[source_offset + (sy * width + sx) as usize * stride + channel];
+                     sum += if is_color { to_linear[texel as usize] } else { texel as f32 / 255.0 };
+                  }
+               }
+               let average = sum / 4.0;
+               let encoded = if is_color { linear_to_srgb(average) } else { average };
+               data.push((encoded * 255.0 + 0.5).clamp(0.0, 255.0) as u8);
+            }
+         }
+      }
+      source_offset = target_offset;
+      (width, height) = (next_width, next_height);
+   }
+   TextureInfo {
+      data,
+      width: info.width,
+      height: info.height,
+      depth: 1,
+      format: info.format,
+      mip_level_count,
+   }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+   if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+   if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+#[cfg(test)]
+mod tests {
+   use std::cell::RefCell;
+   use crate::renderer::{asset_loader::AssetLoader, webgpu::texture::TextureBuilder, Premade, Webgpu};
+
+   use super::*;
+
+   #[test]
+   fn cpu_mipmaps_average_in_linear_space() {
+      // 3x2 checker of black and white, the last column is dropped on the way to 1x1
+      let (b, w) = ([0_u8, 0, 0, 255], [255_u8, 255, 255, 255]);
+      let info = TextureInfo {
+         data: [b, w, b, w, b, b].concat(),
+         width: 3, height: 2, depth: 1, format: PixelFormat::Rgba8, mip_level_count: 1,
+      };
+      let srgb = generate_mipmaps_cpu(&info, true);
+      assert_eq!(srgb.mip_level_count, 2);
+      assert_eq!(srgb.data.len(), (3 * 2 + 1) * 4);
+      assert_eq!(&srgb.data[24..28], &[188, 188, 188, 255]);
+      let linear = generate_mipmaps_cpu(&info, false);
+      assert_eq!(&linear.data[24..28], &[128, 128, 128, 255]);
+   }
+
+   #[test]
+   fn gpu_mipmaps_generate() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let premade = Premade::new(&webgpu.device);
+      let loading_args = LoadingArgs {
+         webgpu: Rc::new(webgpu),
+         color_texture_format: wgpu::TextureFormat::Rgba8Unorm,
+         premade: Rc::new(RefCell::new(premade)),
+         asset_loader: Rc::new(RefCell::new(AssetLoader::new())),
+      };
+      let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+      assert!(supports_gpu_mipmaps(&loading_args.webgpu.device, format));
+      let texture = TextureBuilder::new_2d(wgpu::Extent3d {
+            width: 16, height: 8, depth_or_array_layers: 1,
+         }, format)
+         .with_mip_level_count(full_mip_level_count(16, 8))
+         .add_usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+         .build(&loading_args.webgpu.device);
+      let mut encoder = loading_args.webgpu.device.create_command_encoder(&Default::default());
+      generate_mipmaps_gpu(&loading_args, &mut encoder, &texture);
+      loading_args.webgpu.queue.submit(std::iter::once(encoder.finish()));
+      assert_eq!(texture.mip_level_count(), 5);
+   }
+}
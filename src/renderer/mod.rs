@@ -10,12 +10,19 @@ pub mod global_uniform;
 pub use global_uniform::*;
 mod pipeline_loader;
 mod shader_loader;
+mod shader_reflection;
 pub mod demo_stub;
 pub mod demo_uv;
 pub mod demo_fractal;
 pub mod demo_mesh;
+pub mod lighting;
+pub mod ibl;
+pub mod mipmap;
 mod preprocessor;
 pub mod asset_loader;
+pub mod asset_kinds;
+#[cfg(feature = "win")]
+pub mod asset_watcher;
 pub mod premade;
 pub use premade::*;
 
@@ -24,7 +31,7 @@ use wgpu::SurfaceTexture;
 
 use std::{cell::RefCell, pin::Pin, rc::Rc};
 
-use self::{asset_loader::AssetLoader, pipeline_loader::RenderPipelineFlatDescriptor, preprocessor::Preprocessor, shader_loader::{FragmentShaderVariant, VertexShaderVariant}};
+use self::{asset_loader::AssetLoader, webgpu::uniform::BindGroupInfo, pipeline_loader::RenderPipelineFlatDescriptor, shader_loader::{FragmentShaderVariant, ShaderError, ShaderKey, ShaderVariant, VertexShaderVariant}};
 
 //#[cfg(feature = "web")]
 pub mod wasm {
@@ -73,12 +80,41 @@ pub struct LoadingArgs {
 }
 
 impl LoadingArgs {
-   pub fn get_vertex_shader(&self, variant: VertexShaderVariant, preprocessor: Option<&mut Preprocessor>) -> Rc<wgpu::ShaderModule> {
-      self.premade.borrow().shader_loader.borrow_mut().get_shader(&self.webgpu.device, variant, preprocessor)
+   pub fn get_vertex_shader(&self, key: impl Into<ShaderKey<VertexShaderVariant>>) -> Result<Rc<wgpu::ShaderModule>, ShaderError> {
+      self.premade.borrow().shader_loader.borrow_mut().get_shader(&self.webgpu.device, key)
    }
 
-   pub fn get_fragment_shader(&self, variant: FragmentShaderVariant, preprocessor: Option<&mut Preprocessor>) -> Rc<wgpu::ShaderModule> {
-      self.premade.borrow().shader_loader.borrow_mut().get_shader(&self.webgpu.device, variant, preprocessor)
+   pub fn get_fragment_shader(&self, key: impl Into<ShaderKey<FragmentShaderVariant>>) -> Result<Rc<wgpu::ShaderModule>, ShaderError> {
+      self.premade.borrow().shader_loader.borrow_mut().get_shader(&self.webgpu.device, key)
+   }
+
+   pub fn error_shader(&self) -> Rc<wgpu::ShaderModule> {
+      self.premade.borrow().shader_loader.borrow_mut().error_shader(&self.webgpu.device)
+   }
+
+   // Shaders which don't compile are replaced by the error shader, their errors stay listed for the overlay.
+   // A broken vertex shader replaces the fragment shaders too, as their inputs wouldn't match it
+   pub fn shaders_or_error<const N: usize>(&self, vertex: Result<Rc<wgpu::ShaderModule>, ShaderError>, fragments: [Result<Rc<wgpu::ShaderModule>, ShaderError>; N]) -> (Rc<wgpu::ShaderModule>, [Rc<wgpu::ShaderModule>; N]) {
+      match vertex {
+         Ok(vertex) => (vertex, fragments.map(|fragment| fragment.unwrap_or_else(|_| self.error_shader()))),
+         Err(_) => (self.error_shader(), fragments.map(|_| self.error_shader())),
+      }
+   }
+
+   pub fn get_shaders<F: Into<ShaderKey<FragmentShaderVariant>>, const N: usize>(&self, vertex: impl Into<ShaderKey<VertexShaderVariant>>, fragments: [F; N]) -> (Rc<wgpu::ShaderModule>, [Rc<wgpu::ShaderModule>; N]) {
+      let vertex = self.get_vertex_shader(vertex);
+      self.shaders_or_error(vertex, fragments.map(|key| self.get_fragment_shader(key)))
+   }
+
+   // Compares the resources the shaders use with the layouts and Rust structs of the pipeline bind groups,
+   // given by group index. Mismatches are logged and listed with the shader errors
+   pub fn check_bindings(&self, shaders: &[&wgpu::ShaderModule], groups: &[&BindGroupInfo]) {
+      self.premade.borrow().shader_loader.borrow_mut().check_bindings(shaders, groups);
+   }
+
+   // compiled in the background, one per frame, so later requests hit the cache
+   pub fn precompile_shaders<T: ShaderVariant>(&self, keys: impl IntoIterator<Item=ShaderKey<T>>) {
+      self.premade.borrow().shader_loader.borrow_mut().precompile(keys);
    }
 
    pub fn get_pipeline(&self, flat_descriptor: &RenderPipelineFlatDescriptor) -> Rc<wgpu::RenderPipeline> {
@@ -93,6 +129,8 @@ pub trait IDemo {
    fn progress_switching_graphics_level(&self) -> f32;
    fn render(&mut self, args: RenderArgs) -> Result<(), wgpu::SurfaceError>;
    fn rebuild_pipelines(&mut self, args: LoadingArgs);
+   // the asset loader swapped reloaded assets, resources made from them have to be recreated
+   fn assets_reloaded(&mut self, _args: LoadingArgs) {}
    #[cfg(any(feature = "imgui_win", feature = "imgui_web"))]
    fn render_imgui(&mut self, ui: &imgui::Ui, args: imgui_web::ImguiRenderArgs);
    fn drop_demo(&mut self, webgpu: &Webgpu);
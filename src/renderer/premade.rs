@@ -1,17 +1,16 @@
 use std::cell::RefCell;
 
 use crate::renderer::GlobalUniform;
-use super::{pipeline_loader::PipelineLoader, shader_loader::ShaderLoader, webgpu::Utils};
+use super::{ibl::IblCache, pipeline_loader::PipelineLoader, shader_loader::ShaderLoader, webgpu::Utils};
 
-#[cfg(feature = "web")]
 const USE_SHADER_CACHE: bool = true;
-#[cfg(not(feature = "web"))]
-const USE_SHADER_CACHE: bool = false;
 const USE_PIPELINE_CACHE: bool = true;
 
 pub struct Samplers {
    pub bilinear_sampler: wgpu::Sampler,
    pub nearest_sampler: wgpu::Sampler,
+   pub trilinear_sampler: wgpu::Sampler,
+   pub anisotropic_sampler: wgpu::Sampler,
 }
 
 pub struct Premade {
@@ -19,6 +18,7 @@ pub struct Premade {
    pub global_uniform: GlobalUniform,
    pub shader_loader: RefCell<ShaderLoader>,
    pub pipeline_loader: RefCell<PipelineLoader>,
+   pub ibl_cache: RefCell<IblCache>,
 }
 
 impl Samplers {
@@ -26,6 +26,8 @@ impl Samplers {
       Self {
          bilinear_sampler: device.create_sampler(&Utils::bilinear_sampler()),
          nearest_sampler: device.create_sampler(&Utils::nearest_sampler()),
+         trilinear_sampler: device.create_sampler(&Utils::trilinear_sampler()),
+         anisotropic_sampler: device.create_sampler(&Utils::anisotropic_sampler(16)),
       }
    }
 }
@@ -39,6 +41,7 @@ impl Premade {
          global_uniform: GlobalUniform::new(device),
          shader_loader,
          pipeline_loader,
+         ibl_cache: RefCell::new(IblCache::new()),
       }
    }
 }
\ No newline at end of file
@@ -1,47 +1,476 @@
-use gpp;
-use std::hash::Hash;
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+// Directives of shader files, on lines starting with '#':
+//   #define NAME [value]   #undef NAME
+//   #ifdef NAME   #ifndef NAME   #if expression   #elif expression   #else   #endif
+//   #include "path"
+// Expressions are integer arithmetic and comparisons with `!`, `&&`, `||`, `true`, `false`, `defined(NAME)`
+// and names of defines, e.g. `#if QUALITY >= 2 && !defined(NO_SHADOWS)`. Integers may have WGSL's `u`/`i` suffix.
+// Names with a value are replaced by it in the shader text, outside of `//` comments
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
 pub struct Preprocessor {
-   context: gpp::Context,
+   // ordered, so equal definitions hash equally
+   defines: BTreeMap<String, String>,
+}
+
+// where a line of preprocessed text comes from
+#[derive(Clone, Debug)]
+pub struct SourceLine {
+   pub file: Rc<str>,
+   // 1-based
+   pub line: usize,
+}
+
+pub struct Preprocessed {
+   pub text: String,
+   // one per line of `text`
+   pub origins: Vec<SourceLine>,
+   // every file read, the processed one first, then its includes
+   pub files: Vec<(Rc<str>, String)>,
 }
 
+impl Preprocessed {
+   // origin of a 1-based line of `text` and the source line itself
+   pub fn source_line(&self, line: usize) -> Option<(&SourceLine, &str)> {
+      let origin = self.origins.get(line.checked_sub(1)?)?;
+      let (_, text) = self.files.iter().find(|(file, _)| *file == origin.file)?;
+      Some((origin, text.lines().nth(origin.line - 1)?))
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreprocessError {
+   pub file: String,
+   // 1-based
+   pub line: usize,
+   pub column: usize,
+   // the source line
+   pub text: String,
+   pub message: String,
+}
+
+impl std::fmt::Display for PreprocessError {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+   }
+}
+
+// Resolves an include of a file and reads it: (including file, include path) -> (resolved path, text)
+pub type IncludeReader<'a> = dyn FnMut(&str, &str) -> Result<(String, String), String> + 'a;
+
 impl Preprocessor {
    pub fn new() -> Self {
-      Self {
-         context: gpp::Context::new(),
+      Self::default()
+   }
+
+   pub fn define(&mut self, name: &str, value: &str) {
+      self.defines.insert(name.to_owned(), value.to_owned());
+   }
+
+   // Every file is included once, a file including itself, directly or not, is an error
+   pub fn process(&self, path: &str, text: String, include: &mut IncludeReader) -> Result<Preprocessed, PreprocessError> {
+      let mut state = State {
+         defines: self.defines.clone(),
+         include,
+         stack: vec![],
+         included: HashSet::new(),
+         output: Preprocessed { text: String::new(), origins: vec![], files: vec![] },
+      };
+      state.process_file(path.into(), text)?;
+      Ok(state.output)
+   }
+}
+
+struct Branch {
+   // lines are kept
+   active: bool,
+   // a branch of this #if was taken already
+   taken: bool,
+   parent_active: bool,
+   has_else: bool,
+   // the #if, for a missing #endif
+   line: usize,
+   text: String,
+}
+
+struct State<'a, 'b> {
+   defines: BTreeMap<String, String>,
+   include: &'a mut IncludeReader<'b>,
+   stack: Vec<Rc<str>>,
+   included: HashSet<Rc<str>>,
+   output: Preprocessed,
+}
+
+// (1-based column, message)
+type ExpressionError = (usize, String);
+
+impl State<'_, '_> {
+   fn process_file(&mut self, file: Rc<str>, text: String) -> Result<(), PreprocessError> {
+      // listed before its includes, the text is moved in once it's processed
+      let file_index = self.output.files.len();
+      self.output.files.push((file.clone(), String::new()));
+      self.stack.push(file.clone());
+      self.included.insert(file.clone());
+      let mut branches: Vec<Branch> = vec![];
+      for (i, line) in text.lines().enumerate() {
+         let error = |column: usize, message: String| PreprocessError {
+            file: file.to_string(),
+            line: i + 1,
+            column,
+            text: line.to_owned(),
+            message,
+         };
+         let active = branches.last().is_none_or(|branch| branch.active);
+         let Some(directive) = line.trim_start().strip_prefix('#') else {
+            if active {
+               self.output.text += &substitute(line, &self.defines, 0);
+               self.output.text.push('\n');
+               self.output.origins.push(SourceLine { file: file.clone(), line: i + 1 });
+            }
+            continue;
+         };
+         let hash_column = line.len() - line.trim_start().len() + 1;
+         let directive = directive.trim_start();
+         let name_length = directive.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(directive.len());
+         let (name, argument) = directive.split_at(name_length);
+         let argument = argument.split("//").next().unwrap().trim();
+         let argument_column = column_of(line, argument);
+         let expect_name = || match argument.split_whitespace().collect::<Vec<_>>()[..] {
+            [define] if is_identifier(define) => Ok(define),
+            _ => Err(error(argument_column, format!("#{name} expects a name"))),
+         };
+         match name {
+            "ifdef" | "ifndef" => {
+               let taken = self.defines.contains_key(expect_name()?) == (name == "ifdef");
+               branches.push(Branch { active: active && taken, taken, parent_active: active, has_else: false, line: i + 1, text: line.to_owned() });
+            },
+            "if" => {
+               // inactive expressions aren't evaluated, they may use names which aren't defined there
+               let taken = active && evaluate(line, argument, &self.defines, 0).map_err(|(column, message)| error(column, message))? != 0;
+               branches.push(Branch { active: taken, taken, parent_active: active, has_else: false, line: i + 1, text: line.to_owned() });
+            },
+            "elif" | "else" => {
+               let Some(branch) = branches.last_mut() else {
+                  return Err(error(hash_column, format!("#{name} without #if")));
+               };
+               if branch.has_else {
+                  return Err(error(hash_column, format!("#{name} after #else")));
+               }
+               let taken = match name {
+                  "elif" if branch.parent_active && !branch.taken =>
+                     evaluate(line, argument, &self.defines, 0).map_err(|(column, message)| error(column, message))? != 0,
+                  "elif" => false,
+                  _ => {
+                     branch.has_else = true;
+                     !branch.taken
+                  },
+               };
+               branch.active = branch.parent_active && taken;
+               branch.taken |= taken;
+            },
+            "endif" => {
+               if branches.pop().is_none() {
+                  return Err(error(hash_column, "#endif without #if".to_owned()));
+               }
+            },
+            // other directives of skipped branches don't apply
+            _ if !active => {},
+            "define" => {
+               let name_length = argument.find(char::is_whitespace).unwrap_or(argument.len());
+               let (define, value) = argument.split_at(name_length);
+               if !is_identifier(define) {
+                  return Err(error(argument_column, "#define expects a name".to_owned()));
+               }
+               self.defines.insert(define.to_owned(), value.trim().to_owned());
+            },
+            "undef" => {
+               let define = expect_name()?;
+               self.defines.remove(define);
+            },
+            "include" => {
+               let include_path = argument.strip_prefix('"').and_then(|argument| argument.strip_suffix('"'))
+                  .ok_or_else(|| error(hash_column, "expected #include \"path\"".to_owned()))?;
+               let (include_path, include_text) = (self.include)(&file, include_path)
+                  .map_err(|e| error(hash_column, format!("can't include {e}")))?;
+               if let Some(cycle_start) = self.stack.iter().position(|file| **file == include_path) {
+                  let cycle: Vec<&str> = self.stack[cycle_start..].iter().map(|file| &**file).collect();
+                  return Err(error(hash_column, format!("include cycle {} -> {include_path}", cycle.join(" -> "))));
+               }
+               if !self.included.contains(include_path.as_str()) {
+                  self.process_file(include_path.into(), include_text)?;
+               }
+            },
+            _ => return Err(error(hash_column, format!("unknown directive #{name}"))),
+         }
+      }
+      if let Some(branch) = branches.pop() {
+         return Err(PreprocessError {
+            file: file.to_string(),
+            line: branch.line,
+            column: branch.text.len() - branch.text.trim_start().len() + 1,
+            text: branch.text,
+            message: "#if without #endif".to_owned(),
+         });
       }
+      self.stack.pop();
+      self.output.files[file_index].1 = text;
+      Ok(())
    }
+}
+
+fn is_identifier(name: &str) -> bool {
+   name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+      && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// 1-based column of `part`, which is a slice of `line`
+fn column_of(line: &str, part: &str) -> usize {
+   part.as_ptr() as usize - line.as_ptr() as usize + 1
+}
 
-   pub fn define(&mut self, key: &str, value: &str) {
-      self.context.macros.insert(key.to_owned(), value.to_owned());
+// names of defines with a value are replaced by it, values are substituted too.
+// The rest of the line after `//` is kept as is
+fn substitute(line: &str, defines: &BTreeMap<String, String>, depth: usize) -> String {
+   let (code, comment) = line.find("//").map_or((line, ""), |at| line.split_at(at));
+   let mut text = String::with_capacity(line.len());
+   let mut rest = code;
+   while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+      // digits before a name belong to a number, e.g. the suffix of 4u
+      let (before, from_start) = rest.split_at(start);
+      let length = from_start.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(from_start.len());
+      let (word, after) = from_start.split_at(length);
+      text += before;
+      let in_number = before.ends_with(|c: char| c.is_ascii_digit());
+      match defines.get(word).filter(|value| !value.is_empty() && !in_number && depth < MAX_EXPANSION_DEPTH) {
+         Some(value) => text += &substitute(value, defines, depth + 1),
+         None => text += word,
+      }
+      rest = after;
+   }
+   text += rest;
+   text += comment;
+   text
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+   Number(i64),
+   Name(&'a str),
+   Operator(&'static str),
+}
+
+const OPERATORS: [&str; 18] = ["||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", ",", ";"];
+
+fn tokenize<'a>(line: &'a str, expression: &'a str) -> Result<Vec<(Token<'a>, usize)>, ExpressionError> {
+   let mut tokens = vec![];
+   let mut rest = expression.trim_start();
+   while !rest.is_empty() {
+      let column = column_of(line, rest);
+      let length = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+         let length = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+         let number = rest[..length].trim_end_matches(['u', 'i']);
+         let value = match number.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => number.parse(),
+         };
+         tokens.push((Token::Number(value.map_err(|_| (column, format!("{} isn't an integer", &rest[..length])))?), column));
+         length
+      } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+         let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+         tokens.push((Token::Name(&rest[..length]), column));
+         length
+      } else {
+         let operator = OPERATORS.iter().find(|operator| rest.starts_with(**operator))
+            .ok_or_else(|| (column, format!("unexpected '{}'", rest.chars().next().unwrap())))?;
+         tokens.push((Token::Operator(operator), column));
+         operator.len()
+      };
+      rest = rest[length..].trim_start();
+   }
+   Ok(tokens)
+}
+
+// a define's value refers to itself, directly or not
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+fn evaluate(line: &str, expression: &str, defines: &BTreeMap<String, String>, depth: usize) -> Result<i64, ExpressionError> {
+   let tokens = tokenize(line, expression)?;
+   let end = column_of(line, expression) + expression.len();
+   if tokens.is_empty() {
+      return Err((end, "expected an expression".to_owned()));
+   }
+   let mut parser = Parser { tokens, position: 0, end, defines, depth, skipped: 0 };
+   let value = parser.binary(0)?;
+   match parser.tokens.get(parser.position) {
+      Some((_, column)) => Err((*column, "expected an operator".to_owned())),
+      None => Ok(value),
+   }
+}
+
+struct Parser<'a> {
+   tokens: Vec<(Token<'a>, usize)>,
+   position: usize,
+   // column after the expression, for errors at its end
+   end: usize,
+   defines: &'a BTreeMap<String, String>,
+   depth: usize,
+   // inside the operand of `&&` or `||` which doesn't decide the result, like C it can't fail
+   skipped: usize,
+}
+
+impl<'a> Parser<'a> {
+   fn next(&mut self) -> Result<(Token<'a>, usize), ExpressionError> {
+      let token = self.tokens.get(self.position).copied()
+         .ok_or_else(|| (self.end, "unexpected end of the expression".to_owned()))?;
+      self.position += 1;
+      Ok(token)
    }
 
-   pub fn process(&mut self, str: &str) -> Option<String> {
-      match gpp::process_str(str, &mut self.context) {
-         Ok(out) => Some(out),
-         Err(e) => {
-            eprintln!("Error running preprocessor on shader: {}", e);
-            None
+   fn expect(&mut self, operator: &str) -> Result<(), ExpressionError> {
+      match self.next()? {
+         (Token::Operator(found), _) if found == operator => Ok(()),
+         (_, column) => Err((column, format!("expected '{operator}'"))),
+      }
+   }
+
+   // operators binding tighter than `min_precedence` are left to the caller
+   fn binary(&mut self, min_precedence: u8) -> Result<i64, ExpressionError> {
+      let mut left = self.unary()?;
+      while let Some(&(Token::Operator(operator), column)) = self.tokens.get(self.position) {
+         let precedence = match operator {
+            "||" => 1,
+            "&&" => 2,
+            "==" | "!=" => 3,
+            "<" | "<=" | ">" | ">=" => 4,
+            "+" | "-" => 5,
+            "*" | "/" | "%" => 6,
+            _ => break,
+         };
+         if precedence < min_precedence {
+            break;
          }
-         // Err(gpp::Error::ChildFailed { status }) => None,
-         // Err(gpp::Error::FileError { filename, line, error }) => None,
-         // Err(gpp::Error::FromUtf8Error(e)) => None,
-         // Err(gpp::Error::InvalidCommand { command_name }) => None,
-         // Err(gpp::Error::PipeFailed) => None,
-         // Err(gpp::Error::TooManyParameters { command }) => None,
-         // Err(gpp::Error::UnexpectedCommand { command }) => None,
-         // Err(gpp::Error::IoError(e)) => None,
+         self.position += 1;
+         let skip = (operator == "||" && left != 0) || (operator == "&&" && left == 0);
+         self.skipped += skip as usize;
+         let right = self.binary(precedence + 1)?;
+         self.skipped -= skip as usize;
+         left = match operator {
+            "||" => (left != 0 || right != 0) as i64,
+            "&&" => (left != 0 && right != 0) as i64,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            "<=" => (left <= right) as i64,
+            ">" => (left > right) as i64,
+            ">=" => (left >= right) as i64,
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            _ if right == 0 && self.skipped > 0 => 0,
+            _ if right == 0 => return Err((column, "division by zero".to_owned())),
+            "/" => left.wrapping_div(right),
+            _ => left.wrapping_rem(right),
+         };
+      }
+      Ok(left)
+   }
+
+   fn unary(&mut self) -> Result<i64, ExpressionError> {
+      match self.next()? {
+         (Token::Operator("!"), _) => Ok((self.unary()? == 0) as i64),
+         (Token::Operator("-"), _) => Ok(self.unary()?.wrapping_neg()),
+         (Token::Operator("("), _) => {
+            let value = self.binary(0)?;
+            self.expect(")")?;
+            Ok(value)
+         },
+         (Token::Number(value), _) => Ok(value),
+         (Token::Name("true"), _) => Ok(1),
+         (Token::Name("false"), _) => Ok(0),
+         (Token::Name("defined"), _) => {
+            let parenthesized = matches!(self.tokens.get(self.position), Some((Token::Operator("("), _)));
+            if parenthesized {
+               self.position += 1;
+            }
+            let value = match self.next()? {
+               (Token::Name(name), _) => self.defines.contains_key(name) as i64,
+               (_, column) => return Err((column, "defined expects a name".to_owned())),
+            };
+            if parenthesized {
+               self.expect(")")?;
+            }
+            Ok(value)
+         },
+         (Token::Name(name), column) => {
+            let Some(value) = self.defines.get(name) else {
+               return match self.skipped {
+                  0 => Err((column, format!("{name} isn't defined, use defined({name}) to test for it"))),
+                  _ => Ok(0),
+               };
+            };
+            if self.depth == MAX_EXPANSION_DEPTH {
+               return Err((column, format!("{name} expands to itself")));
+            }
+            // errors inside the value are reported at the name
+            evaluate(value, value, self.defines, self.depth + 1)
+               .map_err(|(_, message)| (column, format!("in {name} = {value}: {message}")))
+         },
+         (Token::Operator(operator), column) => Err((column, format!("unexpected '{operator}'"))),
       }
    }
 }
 
-impl Hash for Preprocessor {
-   fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-      // NOTE: doesn't consider order of values in macros
-      // which might be different for different instsantiations of Preprocessor (idk)
-      self.context.macros.iter()
-         .for_each(|(k, v)| {
-            k.hash(state);
-            v.hash(state);
-         });
+#[cfg(test)]
+mod tests {
+   use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
+
+   use super::*;
+
+   fn process(preprocessor: &Preprocessor, text: &str) -> Result<String, PreprocessError> {
+      let mut no_includes = |_: &str, path: &str| Err(format!("{path} isn't there"));
+      preprocessor.process("test.wgsl", text.to_owned(), &mut no_includes).map(|processed| processed.text)
+   }
+
+   #[test]
+   fn conditions_evaluate_expressions() {
+      let mut preprocessor = Preprocessor::new();
+      preprocessor.define("QUALITY", "2");
+      preprocessor.define("SAMPLES", "QUALITY * 4u");
+      let text = "#if SAMPLES > 4 && !defined(NO_SHADOWS)\n\
+         let samples = SAMPLES; // SAMPLES\n\
+         #elif QUALITY == 2\n\
+         wrong\n\
+         #else\n\
+         #include \"missing.wgsl\"\n\
+         #endif\n\
+         #define LIMIT (1 + 2) * 3\n\
+         #if LIMIT % 5 != 4 && 1 / 0\n\
+         wrong\n\
+         #elif defined QUALITY\n\
+         let limit = LIMIT;\n\
+         #endif\n";
+      assert_eq!(process(&preprocessor, text).unwrap(), "let samples = 2 * 4u; // SAMPLES\nlet limit = (1 + 2) * 3;\n");
+
+      let error = process(&preprocessor, "#ifdef QUALITY\n  #if LEVEL > 1\n#endif\n#endif").err().unwrap();
+      assert_eq!((error.line, error.column), (2, 7));
+      assert_eq!(error.message, "LEVEL isn't defined, use defined(LEVEL) to test for it");
+      let error = process(&preprocessor, "#if (QUALITY\n#endif").err().unwrap();
+      assert_eq!((error.column, error.message.as_str()), (13, "unexpected end of the expression"));
+      let error = process(&preprocessor, "#ifdef QUALITY\n#else\n#elif 1\n#endif").err().unwrap();
+      assert_eq!((error.line, error.message.as_str()), (3, "#elif after #else"));
+      let error = process(&preprocessor, "\n #ifndef QUALITY\n").err().unwrap();
+      assert_eq!((error.line, error.column, error.message.as_str()), (2, 2, "#if without #endif"));
    }
-}
\ No newline at end of file
+
+   #[test]
+   fn hash_is_independent_of_define_order() {
+      let (mut a, mut b) = (Preprocessor::new(), Preprocessor::new());
+      for i in 0..32 {
+         a.define(&format!("A{i}"), "1");
+         b.define(&format!("A{}", 31 - i), "1");
+      }
+      let hasher = BuildHasherDefault::<DefaultHasher>::default();
+      assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+   }
+}
@@ -1,6 +1,95 @@
-use std::{collections::HashMap, hash::{BuildHasher, Hash, Hasher}, path::Path, rc::Rc};
+use std::{collections::{HashMap, HashSet, VecDeque}, hash::{BuildHasher, Hash, Hasher}, path::Path, rc::{Rc, Weak}};
 
-use super::{preprocessor::Preprocessor, webgpu::utils::Utils};
+use futures::FutureExt;
+use futures::future::LocalBoxFuture;
+
+use crate::asset_source::{AssetError, AssetSources, EmbeddedSource, MemorySource};
+use crate::hash::content_hash;
+
+use super::{preprocessor::{Preprocessed, Preprocessor}, shader_reflection::ShaderReflection, webgpu::{uniform::{BindGroupInfo, WgslStruct}, utils::Utils}};
+
+// A preprocessor define a shader reads, with `#ifdef` or by substituting one of `values`.
+// Features left out of a `ShaderKey` aren't defined
+pub struct ShaderFeature {
+   pub name: &'static str,
+   pub values: &'static [&'static str],
+}
+
+impl ShaderFeature {
+   // checked with #ifdef only
+   pub const fn switch(name: &'static str) -> Self {
+      Self { name, values: &["1"] }
+   }
+}
+
+pub trait ShaderVariant: AsRef<Path> + Hash + Eq + Copy + 'static {
+   fn features(self) -> &'static [ShaderFeature];
+}
+
+// paths of shaders without features, e.g. in tests
+impl ShaderVariant for &'static str {
+   fn features(self) -> &'static [ShaderFeature] {
+      &[]
+   }
+}
+
+// A permutation of a shader, the shader loader caches modules by it
+#[derive(Clone, Hash, Eq, PartialEq)]
+pub struct ShaderKey<T> {
+   pub variant: T,
+   // sorted by name
+   defines: Vec<(&'static str, &'static str)>,
+}
+
+impl<T: ShaderVariant> From<T> for ShaderKey<T> {
+   fn from(variant: T) -> Self {
+      Self::new(variant)
+   }
+}
+
+impl<T: ShaderVariant> ShaderKey<T> {
+   pub fn new(variant: T) -> Self {
+      Self { variant, defines: vec![] }
+   }
+
+   // Panics if the shader doesn't declare the feature or the value, a typo would silently give another permutation
+   pub fn with(mut self, name: &str, value: &str) -> Self {
+      let path = self.variant.as_ref().display().to_string();
+      let feature = self.variant.features().iter()
+         .find(|feature| feature.name == name)
+         .unwrap_or_else(|| panic!("{path} has no feature {name}"));
+      let value = feature.values.iter()
+         .find(|allowed| **allowed == value)
+         .unwrap_or_else(|| panic!("{path}: {name} can't be {value}, only {:?}", feature.values));
+      match self.defines.binary_search_by_key(&feature.name, |(name, _)| name) {
+         Ok(i) => self.defines[i].1 = value,
+         Err(i) => self.defines.insert(i, (feature.name, value)),
+      }
+      self
+   }
+
+   pub fn defines(&self) -> &[(&'static str, &'static str)] {
+      &self.defines
+   }
+
+   // every combination of the declared features, each one undefined or set to one of its values
+   pub fn permutations(variant: T) -> Vec<Self> {
+      variant.features().iter().fold(vec![Self::new(variant)], |keys, feature| {
+         keys.into_iter()
+            .flat_map(|key| std::iter::once(key.clone())
+               .chain(feature.values.iter().map(move |value| key.clone().with(feature.name, value))))
+            .collect()
+      })
+   }
+
+   fn preprocessor(&self) -> Preprocessor {
+      let mut preprocessor = Preprocessor::new();
+      for (name, value) in &self.defines {
+         preprocessor.define(name, value);
+      }
+      preprocessor
+   }
+}
 
 #[allow(unused)]
 #[derive(Clone, Copy, Hash, Eq, PartialEq)]
@@ -8,17 +97,21 @@ pub enum VertexShaderVariant {
    TriangleFullscreen = 0,
    TriangleColored = 1,
    Passthrough = 2,
+   Mesh = 3,
+   ShadowDepth = 4,
 }
 
-// shader enum -> source code during compilation
-impl AsRef<str> for VertexShaderVariant {
-   fn as_ref(&self) -> &str {
+#[allow(unused)]
+impl VertexShaderVariant {
+   pub const ALL: [Self; 5] = {
       use VertexShaderVariant::*;
-      match self {
-         TriangleFullscreen => include_str!("shaders/triangle_fullscreen.vs.wgsl"),
-         TriangleColored => include_str!("shaders/triangle_colored.vs.wgsl"),
-         Passthrough => include_str!("shaders/passthrough.vs.wgsl"),
-      }
+      [TriangleFullscreen, TriangleColored, Passthrough, Mesh, ShadowDepth]
+   };
+}
+
+impl ShaderVariant for VertexShaderVariant {
+   fn features(self) -> &'static [ShaderFeature] {
+      &[]
    }
 }
 
@@ -30,6 +123,8 @@ impl AsRef<std::path::Path> for VertexShaderVariant {
          TriangleFullscreen => "shaders/triangle_fullscreen.vs.wgsl".as_ref(),
          TriangleColored => "shaders/triangle_colored.vs.wgsl".as_ref(),
          Passthrough => "shaders/passthrough.vs.wgsl".as_ref(),
+         Mesh => "shaders/mesh.vs.wgsl".as_ref(),
+         ShadowDepth => "shaders/shadow_depth.vs.wgsl".as_ref(),
       }
     }
 }
@@ -40,16 +135,30 @@ pub enum FragmentShaderVariant {
    VertexColor = 0,
    FractalMandelbrot = 1,
    Uv = 2,
+   MeshParallax = 3,
+   EquirectToCube = 4,
+   IrradianceConvolution = 5,
+   SpecularPrefilter = 6,
+   BrdfLut = 7,
+   Skybox = 8,
+   MipmapBlit = 9,
 }
 
-// shader enum -> source code during compilation
-impl AsRef<str> for FragmentShaderVariant {
-   fn as_ref(&self) -> &str {
+#[allow(unused)]
+impl FragmentShaderVariant {
+   pub const ALL: [Self; 10] = {
       use FragmentShaderVariant::*;
+      [VertexColor, FractalMandelbrot, Uv, MeshParallax, EquirectToCube,
+         IrradianceConvolution, SpecularPrefilter, BrdfLut, Skybox, MipmapBlit]
+   };
+}
+
+impl ShaderVariant for FragmentShaderVariant {
+   fn features(self) -> &'static [ShaderFeature] {
+      const MANDELBROT: &[ShaderFeature] = &[ShaderFeature::switch("USE_ANTIALIASING")];
       match self {
-         VertexColor => include_str!("shaders/vertex_color.fs.wgsl"),
-         FractalMandelbrot => include_str!("shaders/mandelbrot.fs.wgsl"),
-         Uv => include_str!("shaders/uv.fs.wgsl"),
+         FragmentShaderVariant::FractalMandelbrot => MANDELBROT,
+         _ => &[],
       }
    }
 }
@@ -62,77 +171,874 @@ impl AsRef<std::path::Path> for FragmentShaderVariant {
          VertexColor => "shaders/vertex_color.fs.wgsl".as_ref(),
          FractalMandelbrot => "shaders/mandelbrot.fs.wgsl".as_ref(),
          Uv => "shaders/uv.fs.wgsl".as_ref(),
+         MeshParallax => "shaders/mesh_parallax.fs.wgsl".as_ref(),
+         EquirectToCube => "shaders/equirect_to_cube.fs.wgsl".as_ref(),
+         IrradianceConvolution => "shaders/irradiance_convolution.fs.wgsl".as_ref(),
+         SpecularPrefilter => "shaders/specular_prefilter.fs.wgsl".as_ref(),
+         BrdfLut => "shaders/brdf_lut.fs.wgsl".as_ref(),
+         Skybox => "shaders/skybox.fs.wgsl".as_ref(),
+         MipmapBlit => "shaders/mipmap_blit.fs.wgsl".as_ref(),
       }
     }
 }
 
+// source code embedded during compilation, used when the files aren't found
+macro_rules! embedded_shaders {
+   ($($path:literal),* $(,)?) => {
+      const EMBEDDED_SHADER_PATHS: &[&str] = &[$($path),*];
+
+      fn embedded_shaders() -> EmbeddedSource {
+         EmbeddedSource::new()
+            $(.with_file($path, include_bytes!($path)))*
+      }
+   };
+}
+
+embedded_shaders!(
+   "shaders/triangle_fullscreen.vs.wgsl",
+   "shaders/triangle_colored.vs.wgsl",
+   "shaders/passthrough.vs.wgsl",
+   "shaders/mesh.vs.wgsl",
+   "shaders/shadow_depth.vs.wgsl",
+   "shaders/vertex_color.fs.wgsl",
+   "shaders/mandelbrot.fs.wgsl",
+   "shaders/uv.fs.wgsl",
+   "shaders/mesh_parallax.fs.wgsl",
+   "shaders/equirect_to_cube.fs.wgsl",
+   "shaders/irradiance_convolution.fs.wgsl",
+   "shaders/specular_prefilter.fs.wgsl",
+   "shaders/brdf_lut.fs.wgsl",
+   "shaders/skybox.fs.wgsl",
+   "shaders/mipmap_blit.fs.wgsl",
+   "shaders/common/global_uniform.wgsl",
+   "shaders/common/vertex_output_uv.wgsl",
+);
+
+// WGSL declarations of `#[derive(WgslStruct)]` types, shaders include them as "generated/<WGSL name>.wgsl".
+// Structs used as members have to be listed too
+macro_rules! generated_structs {
+   ($($type:ty),* $(,)?) => {
+      fn generated_structs() -> EmbeddedSource {
+         EmbeddedSource::new()
+            $(.with_text(&format!("shaders/generated/{}.wgsl", <$type as WgslStruct>::WGSL_NAME), <$type as WgslStruct>::wgsl_struct()))*
+      }
+   };
+}
+
+generated_structs!(
+   super::global_uniform::StableGlobalUniformData,
+   super::global_uniform::DynamicGlobalUniformData,
+   super::demo_fractal::FractalUniformData,
+   super::demo_mesh::MeshUniformData,
+   super::lighting::DirectionalLight,
+   super::lighting::PointLight,
+   super::lighting::SpotLight,
+   super::lighting::LightsUniformData,
+   super::ibl::BakeUniformData,
+   super::ibl::SkyboxUniformData,
+);
+
+// files on disk first on native, then the embedded and the generated ones
+fn shader_sources() -> AssetSources {
+   AssetSources::shaders(embedded_shaders()).with_source(generated_structs())
+}
+
+type Precompile = Box<dyn FnOnce(&mut ShaderLoader, &wgpu::Device)>;
+
+// Shader files read ahead through async sources, e.g. fetched from the served directory on the web
+struct RemoteShaders {
+   from: Rc<AssetSources>,
+   files: MemorySource,
+}
+
+// A compiled module, cached by the hash of its preprocessed text
+struct CachedModule {
+   shader: Rc<wgpu::ShaderModule>,
+   // files it was built from, for `invalidate`
+   files: Vec<String>,
+}
+
+// A shader requested since `forget_used_shaders`, e.g. by the current demo
+#[derive(Clone, PartialEq)]
+pub struct UsedShader {
+   pub path: String,
+   pub defines: Vec<(&'static str, &'static str)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShaderCacheStats {
+   // requests served by a cached module
+   pub hits: u64,
+   // requests which compiled their module, or failed to after preprocessing
+   pub misses: u64,
+   // modules dropped by `invalidate` and `clear_cache`
+   pub evicted: u64,
+   pub modules: usize,
+}
+
 pub struct ShaderLoader {
    // loaded_vertex_shaders: HashMap<u64, Rc<wgpu::ShaderModule>>,
    // loaded_fragment_shaders: HashMap<u64, Rc<wgpu::ShaderModule>>,
+   // the last module which compiled by request, also kept without `use_cache` to replace broken edits
    loaded_shaders: HashMap<u64, Rc<wgpu::ShaderModule>>,
+   // files each shader was built from, the shader itself first, then its includes
+   shader_files: HashMap<u64, Vec<String>>,
+   // Modules by the hash of their fully resolved source, which includes their entry points.
+   // Every request is preprocessed, so an edited file never hits a module of its old text,
+   // and permutations resolving to the same text share one
+   modules: HashMap<u64, CachedModule>,
+   stats: ShaderCacheStats,
+   // compilation errors by shader path, until the file compiles again
+   errors: HashMap<String, ShaderError>,
+   // bindings of every module handed out, until it's dropped
+   reflections: HashMap<wgpu::Id<wgpu::ShaderModule>, (Weak<wgpu::ShaderModule>, Rc<ShaderReflection>)>,
+   // permutations compiled ahead of their first request, one per `precompile_next`
+   precompile_queue: VecDeque<Precompile>,
+   remote: Option<RemoteShaders>,
+   // in memory versions of files, ahead of every other source
+   edits: MemorySource,
+   // in request order, precompiled permutations aren't listed
+   used_shaders: Vec<UsedShader>,
+   precompiling: bool,
+   error_shader: Option<Rc<wgpu::ShaderModule>>,
    use_cache: bool,
+   sources: AssetSources,
 }
 
 impl ShaderLoader {
    pub fn new(use_cache: bool) -> Self {
+      let edits = MemorySource::default();
       Self {
          use_cache,
+         sources: shader_sources().with_first_source(edits.clone()),
          loaded_shaders: Default::default(),
+         shader_files: Default::default(),
+         modules: Default::default(),
+         stats: Default::default(),
+         errors: Default::default(),
+         reflections: Default::default(),
+         precompile_queue: Default::default(),
+         remote: None,
+         edits,
+         used_shaders: vec![],
+         precompiling: false,
+         error_shader: None,
          // loaded_vertex_shaders: Default::default(),
          // loaded_fragment_shaders: Default::default(),
       }
    }
 
-   pub fn get_shader<T>(&mut self, device: &wgpu::Device, variant: T, preprocessor: Option<&mut Preprocessor>) -> Rc<wgpu::ShaderModule> where T: AsRef<str> + AsRef<Path> + Hash + 'static {
-      let mut hash = 0;
-      if self.use_cache {
-         let mut hasher = self.loaded_shaders.hasher().build_hasher();
-         variant.hash(&mut hasher);
-         std::any::TypeId::of::<T>().hash(&mut hasher); // hash of type, because vert/frag shader variants are stored in same cache
+   // where shader files are read from, edits stay ahead of them
+   pub fn set_sources(&mut self, sources: AssetSources) {
+      self.sources = sources.with_first_source(self.edits.clone());
+   }
 
-         preprocessor.as_ref().inspect(|p| p.hash(&mut hasher));
-         hash = hasher.finish();
-         if let Some(shader) = self.loaded_shaders.get(&hash) {
-            // #[cfg(feature = "web")]
-            // web_sys::console::log_1(&"Shader cache hit".into());
-            log::warn!("Shader cache hit {hash}");
-            return shader.clone()
+   pub fn sources(&self) -> &AssetSources {
+      &self.sources
+   }
+
+   // Shader files are read through `from` ahead of compiling, so they can be edited without a rebuild.
+   // Files `from` doesn't have are still the embedded ones. Compiling stays synchronous,
+   // so `fetch_remote_shaders` has to finish before the first shader request
+   pub fn use_remote_sources(&mut self, from: AssetSources) {
+      let files = MemorySource::default();
+      self.sources = AssetSources::new()
+         .with_source(self.edits.clone())
+         .with_source(files.clone())
+         .with_source(embedded_shaders())
+         .with_source(generated_structs());
+      self.remote = Some(RemoteShaders { from: Rc::new(from), files });
+   }
+
+   // Reads every shader file through the remote sources again, it resolves to the files which changed.
+   // Those have to be passed to `invalidate` afterwards. Without remote sources nothing is read
+   pub fn fetch_remote_shaders(&self) -> LocalBoxFuture<'static, Vec<String>> {
+      let Some(RemoteShaders { from, files }) = &self.remote else {
+         log::warn!("ShaderLoader: shaders are embedded, there are no remote sources to fetch from");
+         return Box::pin(futures::future::ready(vec![]));
+      };
+      // includes added since the build are known once a shader uses them
+      let mut paths: Vec<String> = EMBEDDED_SHADER_PATHS.iter().map(|path| path.to_string())
+         .chain(self.loaded_paths().filter(|path| !path.starts_with("shaders/generated/")).map(str::to_owned))
+         .collect();
+      paths.sort();
+      paths.dedup();
+      let (from, files) = (from.clone(), files.clone());
+      Box::pin(async move {
+         let reads = paths.iter().map(|path| {
+            let from = from.clone();
+            async move { from.read(path, &Default::default()).await }
+         });
+         let mut changed = vec![];
+         for (path, bytes) in paths.iter().zip(futures::future::join_all(reads).await) {
+            match bytes {
+               Ok(bytes) => if files.insert(path, bytes) {
+                  changed.push(path.clone());
+               },
+               Err(AssetError::NotFound { .. }) => {},
+               Err(e) => log::warn!("ShaderLoader: {e}, the previous version is kept"),
+            }
          }
-         // #[cfg(feature = "web")]
-         // web_sys::console::log_1(&"Shader cache MISS".into());
-         log::warn!("Shader cache MISS {hash}");
+         changed
+      })
+   }
+
+   // paths of the shaders built so far and of the files they include
+   pub fn loaded_paths(&self) -> impl Iterator<Item=&str> {
+      self.shader_files.values().flatten().map(String::as_str).collect::<HashSet<_>>().into_iter()
+   }
+
+   // Drops the cached modules built from the file of `path`, the next request compiles them again.
+   // Edits are picked up without it, it frees the modules of old versions
+   pub fn invalidate(&mut self, path: &str) {
+      let count = self.modules.len();
+      self.modules.retain(|_, module| !module.files.iter().any(|file| file == path));
+      self.stats.evicted += (count - self.modules.len()) as u64;
+   }
+
+   // every module is compiled again on its next request
+   pub fn clear_cache(&mut self) {
+      self.stats.evicted += self.modules.len() as u64;
+      self.modules.clear();
+   }
+
+   pub fn cache_stats(&self) -> ShaderCacheStats {
+      ShaderCacheStats { modules: self.modules.len(), ..self.stats }
+   }
+
+   pub fn errors(&self) -> &HashMap<String, ShaderError> {
+      &self.errors
+   }
+
+   // e.g. before loading another demo, so only its shaders are listed
+   pub fn forget_used_shaders(&mut self) {
+      self.used_shaders.clear();
+   }
+
+   pub fn used_shaders(&self) -> &[UsedShader] {
+      &self.used_shaders
+   }
+
+   // the text the module of `shader` is compiled from, with the current edits
+   pub fn preprocessed(&self, shader: &UsedShader) -> Result<Preprocessed, ShaderError> {
+      let mut preprocessor = Preprocessor::new();
+      for (name, value) in &shader.defines {
+         preprocessor.define(name, value);
+      }
+      preprocess(&self.sources, &shader.path, &preprocessor)
+   }
+
+   // Replaces the file of `path` in memory until `discard_edit`, false if it already had this text.
+   // Shaders using it compile from the edit on their next request
+   pub fn edit(&mut self, path: &str, text: String) -> bool {
+      self.invalidate(path);
+      self.edits.insert(path, text.into_bytes())
+   }
+
+   pub fn is_edited(&self, path: &str) -> bool {
+      self.edits.contains(path)
+   }
+
+   // false if the file wasn't edited
+   pub fn discard_edit(&mut self, path: &str) -> bool {
+      self.invalidate(path);
+      self.edits.remove(path)
+   }
+
+   // Writes the edit of `path` over the file it replaces, which has to be on the local file system
+   #[cfg(not(feature = "web"))]
+   pub fn save_edit(&mut self, path: &str) -> Result<std::path::PathBuf, String> {
+      if !self.edits.contains(path) {
+         return Err(format!("{path} isn't edited"));
+      }
+      let text = self.sources.read_now(path).map_err(|e| e.to_string())?;
+      let file = self.sources.local_path(path).ok_or_else(|| format!("{path} isn't a local file"))?;
+      std::fs::write(&file, text).map_err(|e| format!("{}: {e}", file.display()))?;
+      self.edits.remove(path);
+      Ok(file)
+   }
+
+   // Queues permutations to compile while nothing waits for them, e.g. during asset downloads.
+   // Without the cache they'd be compiled again on request, so they're skipped
+   pub fn precompile<T: ShaderVariant>(&mut self, keys: impl IntoIterator<Item=ShaderKey<T>>) {
+      if !self.use_cache {
+         return;
+      }
+      for key in keys {
+         self.precompile_queue.push_back(Box::new(move |shader_loader, device| {
+            // errors are kept in `errors`, and reported again on request
+            let _ = shader_loader.try_get_shader(device, key);
+         }));
       }
-      let shader = Rc::new(Self::build_shader(device, variant, preprocessor));
+   }
+
+   // compiles one queued permutation, false once the queue is empty
+   pub fn precompile_next(&mut self, device: &wgpu::Device) -> bool {
+      match self.precompile_queue.pop_front() {
+         Some(compile) => {
+            self.precompiling = true;
+            compile(self, device);
+            self.precompiling = false;
+            true
+         },
+         None => false,
+      }
+   }
+
+   // A shader which fails to compile is replaced by its last good module, so pipelines keep working.
+   // Without one, e.g. a file on disk which was broken before startup, its embedded version is compiled.
+   // The error is kept in `errors` until the file compiles, and returned if neither version does
+   pub fn get_shader<T: ShaderVariant>(&mut self, device: &wgpu::Device, key: impl Into<ShaderKey<T>>) -> Result<Rc<wgpu::ShaderModule>, ShaderError> {
+      let key = key.into();
+      let hash = self.shader_hash(&key);
+      let (filepath, preprocessor) = (key.variant.as_ref().to_str().unwrap().to_owned(), key.preprocessor());
+      self.try_get_shader(device, key)
+         .or_else(|e| self.last_good_shader(device, hash, &filepath, &preprocessor).ok_or(e))
+   }
+
+   // `vs_main` and `fs_main` of shaders/error.wgsl, for pipelines whose shaders don't compile at all
+   pub fn error_shader(&mut self, device: &wgpu::Device) -> Rc<wgpu::ShaderModule> {
+      self.error_shader
+         .get_or_insert_with(|| Rc::new(Utils::make_shader(device, include_str!("shaders/error.wgsl"), "shaders/error.wgsl")))
+         .clone()
+   }
+
+   fn last_good_shader(&mut self, device: &wgpu::Device, hash: u64, filepath: &str, preprocessor: &Preprocessor) -> Option<Rc<wgpu::ShaderModule>> {
+      if let Some(shader) = self.loaded_shaders.get(&hash) {
+         return Some(shader.clone());
+      }
+      let embedded = AssetSources::new().with_source(embedded_shaders()).with_source(generated_structs());
+      let preprocessed = preprocess(&embedded, filepath, preprocessor).ok()?;
+      let (shader, reflection) = ShaderLoader::build_shader_module(device, &preprocessed, filepath).ok()?;
+      log::warn!("ShaderLoader: {filepath} doesn't compile, its embedded version is used");
+      let shader = self.add_module(shader, reflection);
       self.loaded_shaders.insert(hash, shader.clone());
+      Some(shader)
+   }
+
+   // reflected for `check_bindings`, as long as the module is used
+   fn add_module(&mut self, shader: wgpu::ShaderModule, reflection: ShaderReflection) -> Rc<wgpu::ShaderModule> {
+      let shader = Rc::new(shader);
+      self.reflections.retain(|_, (shader, _)| shader.strong_count() > 0);
+      self.reflections.insert(shader.global_id(), (Rc::downgrade(&shader), Rc::new(reflection)));
       shader
    }
-   
-   fn build_shader<T>(device: &wgpu::Device, variant: T, preprocessor: Option<&mut Preprocessor>) -> wgpu::ShaderModule where T: AsRef<str> + AsRef<Path> {
-      let filepath: &std::path::Path = variant.as_ref();
-      let source_code;
-      cfg_if::cfg_if!(
-         if #[cfg(feature = "web")] {
-            // source code embedded during compilation
-            source_code = variant.as_ref()
-         } else {
-            // source code loaded from filesystem
-            let cwd = std::env::current_dir().expect("Failed to get current working dir");
-            let abs_filepath = cwd.join("src").join("renderer").join(filepath);
-            let source_code_owned = std::fs::read_to_string(&abs_filepath)
-               .expect(format!("File not found: {}", abs_filepath.to_str().unwrap()).as_ref());
-            source_code = source_code_owned.as_ref();
+
+   pub fn try_get_shader<T: ShaderVariant>(&mut self, device: &wgpu::Device, key: impl Into<ShaderKey<T>>) -> Result<Rc<wgpu::ShaderModule>, ShaderError> {
+      let key = key.into();
+      let hash = self.shader_hash(&key);
+      let filepath = key.variant.as_ref().to_str().unwrap().to_owned();
+      let used = UsedShader { path: filepath.clone(), defines: key.defines.clone() };
+      if !self.precompiling && !self.used_shaders.contains(&used) {
+         self.used_shaders.push(used);
+      }
+      let preprocessed = match preprocess(&self.sources, &filepath, &key.preprocessor()) {
+         Ok(preprocessed) => preprocessed,
+         Err(e) => {
+            // the includes of the last good version are kept watched
+            self.shader_files.entry(hash).or_insert_with(|| vec![filepath.clone()]);
+            return Err(self.compile_failed(filepath, e));
+         },
+      };
+      let files: Vec<String> = preprocessed.files.iter().map(|(file, _)| file.to_string()).collect();
+      self.shader_files.insert(hash, files.clone());
+      let source_hash = content_hash(preprocessed.text.as_bytes());
+      if let Some(module) = self.modules.get(&source_hash).filter(|_| self.use_cache) {
+         self.stats.hits += 1;
+         // e.g. an edit was reverted to a version which compiled
+         self.errors.remove(&filepath);
+         self.loaded_shaders.insert(hash, module.shader.clone());
+         return Ok(module.shader.clone());
+      }
+      self.stats.misses += 1;
+      match ShaderLoader::build_shader_module(device, &preprocessed, &filepath) {
+         Ok((shader, reflection)) => {
+            self.errors.remove(&filepath);
+            let shader = self.add_module(shader, reflection);
+            self.loaded_shaders.insert(hash, shader.clone());
+            if self.use_cache {
+               self.modules.insert(source_hash, CachedModule { shader: shader.clone(), files });
+            }
+            Ok(shader)
+         },
+         Err(e) => Err(self.compile_failed(filepath, e)),
+      }
+   }
+
+   fn compile_failed(&mut self, filepath: String, e: ShaderError) -> ShaderError {
+      log::error!("Shader failed to compile: {e}");
+      self.errors.insert(filepath, e.clone());
+      e
+   }
+
+   pub fn reflection(&self, shader: &wgpu::ShaderModule) -> Option<Rc<ShaderReflection>> {
+      self.reflections.get(&shader.global_id()).map(|(_, reflection)| reflection.clone())
+   }
+
+   // Checks the bindings of `shaders` against the bind groups of their pipeline, by group index.
+   // Mismatches are kept with the errors of the shader file, until it compiles again
+   pub fn check_bindings(&mut self, shaders: &[&wgpu::ShaderModule], groups: &[&BindGroupInfo]) -> bool {
+      let mut valid = true;
+      for shader in shaders {
+         let Some(reflection) = self.reflection(shader) else {
+            continue;
+         };
+         let mismatches = reflection.check(groups);
+         if mismatches.is_empty() {
+            continue;
+         }
+         valid = false;
+         for mismatch in &mismatches {
+            log::error!("Shader bindings don't match: {}: {mismatch}", reflection.file);
+         }
+         self.errors.insert(reflection.file.clone(), ShaderError::new(&reflection.file, mismatches.join("\n")));
+      }
+      valid
+   }
+
+   fn shader_hash<T: ShaderVariant>(&self, key: &ShaderKey<T>) -> u64 {
+      let mut hasher = self.loaded_shaders.hasher().build_hasher();
+      key.hash(&mut hasher);
+      std::any::TypeId::of::<T>().hash(&mut hasher); // hash of type, because vert/frag shader variants are stored in same cache
+      hasher.finish()
+   }
+
+   fn build_shader_module(device: &wgpu::Device, preprocessed: &Preprocessed, label: &str) -> Result<(wgpu::ShaderModule, ShaderReflection), ShaderError> {
+      let (module, info) = validate(preprocessed, label)?;
+      // device limits and features are only checked by wgpu
+      device.push_error_scope(wgpu::ErrorFilter::Validation);
+      let shader = Utils::make_shader(device, &preprocessed.text, label);
+      // native reports errors right away, on web the browser only logs them later
+      match device.pop_error_scope().now_or_never().flatten() {
+         Some(e) => Err(ShaderError::new(label, e.to_string())),
+         None => Ok((shader, ShaderReflection::new(label, &module, &info))),
+      }
+   }
+}
+
+// naga module of a preprocessed shader, needs no device.
+// naga reports errors with their location, wgpu would only log them or panic
+fn validate(preprocessed: &Preprocessed, label: &str) -> Result<(naga::Module, naga::valid::ModuleInfo), ShaderError> {
+   validate_wgsl(&preprocessed.text).map_err(|(message, location)| {
+      let location = location.map(|location| (location.line_number as usize, location.line_position as usize));
+      ShaderError::at(label, message, preprocessed, location)
+   })
+}
+
+// Preprocesses the file at `path` with the defines of `preprocessor`, includes are read from `sources`
+// and their paths are relative to the including file
+fn preprocess(sources: &AssetSources, path: &str, preprocessor: &Preprocessor) -> Result<Preprocessed, ShaderError> {
+   let read = |path: &str| sources.read_now(path)
+      .map_err(|e| e.to_string())
+      .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()));
+   let source_code = read(path).map_err(|e| ShaderError::new(path, e))?;
+   let mut include = |including_path: &str, include_path: &str| {
+      let include_path = resolve_include(including_path, include_path);
+      read(&include_path).map(|text| (include_path, text))
+   };
+   preprocessor.process(path, source_code, &mut include)
+      .map_err(|e| ShaderError::at_column(&e.file, e.message, &e.text, e.line, e.column))
+}
+
+// "shaders/uv.fs.wgsl" including "common/global_uniform.wgsl" -> "shaders/common/global_uniform.wgsl"
+fn resolve_include(including_path: &str, include_path: &str) -> String {
+   let mut parts: Vec<&str> = including_path.split('/').collect();
+   parts.pop();
+   for part in include_path.split('/') {
+      match part {
+         "." => {},
+         ".." => { parts.pop(); },
+         part => parts.push(part),
+      }
+   }
+   parts.join("/")
+}
+
+fn validate_wgsl(source: &str) -> Result<(naga::Module, naga::valid::ModuleInfo), (String, Option<naga::SourceLocation>)> {
+   let module = naga::front::wgsl::parse_str(source)
+      .map_err(|e| {
+         let labels: Vec<&str> = e.labels().map(|(_, label)| label).filter(|label| !label.is_empty()).collect();
+         let message = match labels.is_empty() {
+            true => e.message().to_owned(),
+            false => format!("{} ({})", e.message(), labels.join(", ")),
+         };
+         (message, e.location(source))
+      })?;
+   let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+      .validate(&module)
+      .map_err(|e| {
+         // "Function [0] 'fs_main' is invalid: Returning ... where ... is expected"
+         let mut message = e.as_inner().to_string();
+         let mut source_error = std::error::Error::source(e.as_inner());
+         while let Some(error) = source_error {
+            message += &format!(": {error}");
+            source_error = error.source();
          }
-      );
-      ShaderLoader::build_shader_module(
-         device, source_code, filepath.to_str().unwrap(), preprocessor)
+         (message, e.location(source))
+      })?;
+   Ok((module, info))
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+   pub file: String,
+   // 1-based line and column in the file, before preprocessing
+   pub location: Option<(usize, usize)>,
+   pub message: String,
+   // the line of the error with a caret under the column
+   pub snippet: String,
+}
+
+impl ShaderError {
+   fn new(file: &str, message: impl Into<String>) -> Self {
+      Self {
+         file: file.to_owned(),
+         location: None,
+         message: message.into(),
+         snippet: String::new(),
+      }
+   }
+
+   // `location` is in the preprocessed text, its origins map it back to the files
+   fn at(file: &str, message: String, preprocessed: &Preprocessed, location: Option<(usize, usize)>) -> Self {
+      match location.and_then(|(line, column)| Some((preprocessed.source_line(line)?, column))) {
+         Some(((origin, text), column)) => Self::at_column(&origin.file, message, text, origin.line, column),
+         None => Self::new(file, message),
+      }
+   }
+
+   fn at_column(file: &str, message: String, text: &str, line: usize, column: usize) -> Self {
+      let number = line.to_string();
+      let column = column.min(text.len() + 1);
+      Self {
+         file: file.to_owned(),
+         location: Some((line, column)),
+         message,
+         snippet: format!("{number} | {text}\n{} | {}^", " ".repeat(number.len()), " ".repeat(column - 1)),
+      }
+   }
+}
+
+impl std::fmt::Display for ShaderError {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self.location {
+         Some((line, column)) => write!(f, "{}:{line}:{column}: {}", self.file, self.message)?,
+         None => write!(f, "{}: {}", self.file, self.message)?,
+      }
+      if !self.snippet.is_empty() {
+         write!(f, "\n{}", self.snippet)?;
+      }
+      Ok(())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use std::collections::BTreeMap;
+
+   use crate::renderer::{demo_fractal, demo_mesh, demo_uv, Webgpu};
+   use crate::renderer::global_uniform::{DynamicGlobalUniformData, StableGlobalUniformData};
+   use crate::renderer::lighting::LightsUniformData;
+   use crate::renderer::shader_reflection::{check_binding, ReflectedResource};
+   use crate::renderer::webgpu::uniform::UniformStruct;
+
+   use super::*;
+
+   const VALID: &[u8] = b"@fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }";
+   const BROKEN: &[u8] = b"@fragment fn fs_main() -> @location(0) vec4<f32> { return 1.0 }";
+
+   #[test]
+   fn broken_shaders_keep_the_last_good_module() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", VALID)));
+      let good = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", BROKEN)));
+      shader_loader.invalidate("test.fs.wgsl");
+      let kept = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      assert!(Rc::ptr_eq(&good, &kept));
+      assert!(shader_loader.errors().contains_key("test.fs.wgsl"));
+
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", VALID)));
+      shader_loader.invalidate("test.fs.wgsl");
+      let fixed = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      assert!(!Rc::ptr_eq(&good, &fixed));
+      assert!(shader_loader.errors().is_empty());
+   }
+
+   #[test]
+   fn broken_files_fall_back_to_their_embedded_version() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      // a broken file on disk before startup
+      shader_loader.set_sources(shader_sources().with_first_source(EmbeddedSource::new().with_file("shaders/uv.fs.wgsl", BROKEN)));
+      shader_loader.get_shader(&webgpu.device, FragmentShaderVariant::Uv).unwrap();
+      assert!(shader_loader.errors().contains_key("shaders/uv.fs.wgsl"));
+      // without an embedded version there is nothing to fall back to
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", BROKEN)));
+      assert!(shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").is_err());
+   }
+
+   #[test]
+   fn modules_are_cached_by_their_resolved_source() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      let set_source = |shader_loader: &mut ShaderLoader, source: &[u8]| shader_loader.set_sources(AssetSources::new()
+         .with_source(EmbeddedSource::new().with_text("test.fs.wgsl", String::from_utf8_lossy(source).into_owned())));
+      set_source(&mut shader_loader, VALID);
+      let first = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      // no invalidation, the edit alone changes the key
+      set_source(&mut shader_loader, String::from_utf8_lossy(VALID).replace("1.0", "0.5").as_bytes());
+      let edited = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      assert!(!Rc::ptr_eq(&first, &edited));
+      set_source(&mut shader_loader, VALID);
+      assert!(Rc::ptr_eq(&first, &shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap()));
+      assert_eq!(shader_loader.cache_stats(), ShaderCacheStats { hits: 1, misses: 2, evicted: 0, modules: 2 });
+
+      shader_loader.invalidate("test.fs.wgsl");
+      assert_eq!((shader_loader.cache_stats().evicted, shader_loader.cache_stats().modules), (2, 0));
+      assert!(!Rc::ptr_eq(&first, &shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap()));
+
+      let mut uncached = ShaderLoader::new(false);
+      set_source(&mut uncached, VALID);
+      uncached.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      assert_eq!(uncached.cache_stats().modules, 0);
+   }
+
+   #[test]
+   fn edits_replace_files_until_discarded() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", VALID)));
+      let original = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      let used = UsedShader { path: "test.fs.wgsl".to_owned(), defines: vec![] };
+      assert!(shader_loader.used_shaders() == [used.clone()]);
+
+      assert!(shader_loader.edit("test.fs.wgsl", String::from_utf8_lossy(VALID).replace("1.0", "0.5")));
+      assert!(shader_loader.preprocessed(&used).unwrap().text.contains("0.5"));
+      let edited = shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap();
+      assert!(!Rc::ptr_eq(&original, &edited));
+      // edits stay ahead of replaced sources
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new().with_file("test.fs.wgsl", VALID)));
+      assert!(Rc::ptr_eq(&edited, &shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap()));
+
+      // modules of the replaced text are dropped, not only skipped
+      let modules = shader_loader.cache_stats().modules;
+      assert!(shader_loader.discard_edit("test.fs.wgsl"));
+      assert_eq!(shader_loader.cache_stats().modules, modules - 1);
+      assert!(!shader_loader.preprocessed(&used).unwrap().text.contains("0.5"));
+      assert!(!Rc::ptr_eq(&edited, &shader_loader.get_shader(&webgpu.device, "test.fs.wgsl").unwrap()));
+      shader_loader.forget_used_shaders();
+      assert!(shader_loader.used_shaders().is_empty());
    }
 
-   fn build_shader_module(device: &wgpu::Device, source_code: &str, label: &str, preprocessor: Option<&mut Preprocessor>) -> wgpu::ShaderModule {
-      let source_code = match preprocessor {
-         Some(preprocessor) => preprocessor.process(source_code)
-            .expect(format!("Failed to run preprocessor on shader: {}", label).as_ref()),
-         _ => source_code.to_owned(),
+   #[test]
+   fn remote_shaders_replace_the_embedded_ones() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      // stands in for the served directory
+      let remote = MemorySource::default();
+      remote.insert("shaders/uv.fs.wgsl", VALID.to_vec());
+      shader_loader.use_remote_sources(AssetSources::new().with_source(remote.clone()));
+      let fetch = |shader_loader: &mut ShaderLoader| {
+         let changed = futures::executor::block_on(shader_loader.fetch_remote_shaders());
+         changed.iter().for_each(|path| shader_loader.invalidate(path));
+         changed
       };
-      Utils::make_shader(device, &source_code, label)
+      assert_eq!(fetch(&mut shader_loader), ["shaders/uv.fs.wgsl"]);
+      assert_eq!(shader_loader.sources().read_now("shaders/uv.fs.wgsl").unwrap(), VALID);
+      let fetched = shader_loader.get_shader(&webgpu.device, "shaders/uv.fs.wgsl").unwrap();
+      // files the remote doesn't have are embedded
+      shader_loader.get_shader(&webgpu.device, FragmentShaderVariant::FractalMandelbrot).unwrap();
+
+      assert!(fetch(&mut shader_loader).is_empty());
+      assert!(Rc::ptr_eq(&fetched, &shader_loader.get_shader(&webgpu.device, "shaders/uv.fs.wgsl").unwrap()));
+      remote.insert("shaders/uv.fs.wgsl", String::from_utf8_lossy(VALID).replace("1.0", "0.5").into_bytes());
+      assert_eq!(fetch(&mut shader_loader), ["shaders/uv.fs.wgsl"]);
+      assert!(!Rc::ptr_eq(&fetched, &shader_loader.get_shader(&webgpu.device, "shaders/uv.fs.wgsl").unwrap()));
+   }
+
+   #[test]
+   fn precompiled_permutations_are_cached() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      shader_loader.precompile(ShaderKey::permutations(FragmentShaderVariant::FractalMandelbrot));
+      while shader_loader.precompile_next(&webgpu.device) {}
+      assert_eq!(shader_loader.loaded_shaders.len(), 2);
+      let key = ShaderKey::new(FragmentShaderVariant::FractalMandelbrot).with("USE_ANTIALIASING", "1");
+      let shader = shader_loader.get_shader(&webgpu.device, key.clone()).unwrap();
+      assert!(Rc::ptr_eq(&shader, &shader_loader.get_shader(&webgpu.device, key).unwrap()));
+      assert_eq!(shader_loader.loaded_shaders.len(), 2);
+   }
+
+   #[test]
+   fn errors_point_at_source_lines() {
+      let webgpu = futures::executor::block_on(Webgpu::new_offscreen());
+      let mut shader_loader = ShaderLoader::new(true);
+      shader_loader.set_sources(AssetSources::new().with_source(EmbeddedSource::new()
+         .with_file("shaders/test.fs.wgsl", b"#include \"common/unused.wgsl\"\n#ifdef UNUSED\nfn unused() {}\n#endif\n\
+            @fragment fn fs_main() -> @location(0) vec4<f32> {\n   return 1.0;\n}\n")
+         .with_file("shaders/common/unused.wgsl", b"fn unused_too() {}\n")
+         .with_file("shaders/test.wgsl", b"#include \"common/broken.wgsl\"\n")
+         .with_file("shaders/common/broken.wgsl", b"fn f() {\n   let x = ;\n}")));
+      let error = shader_loader.try_get_shader(&webgpu.device, "shaders/test.fs.wgsl")
+         .err().unwrap();
+      assert_eq!((error.file.as_str(), error.location), ("shaders/test.fs.wgsl", Some((6, 11))));
+      assert_eq!(error.snippet, "6 |    return 1.0;\n  |           ^");
+
+      let error = shader_loader.try_get_shader(&webgpu.device, "shaders/test.wgsl")
+         .err().unwrap();
+      assert_eq!((error.file.as_str(), error.location), ("shaders/common/broken.wgsl", Some((2, 12))));
+      assert!(shader_loader.loaded_paths().any(|path| path == "shaders/common/broken.wgsl"));
+   }
+
+   #[test]
+   fn includes_are_inlined_once_and_cycles_fail() {
+      let sources = AssetSources::new().with_source(EmbeddedSource::new()
+         .with_file("shaders/a.wgsl", b"#include \"common/b.wgsl\"\n#include \"common/c.wgsl\"\nfn a() {}")
+         .with_file("shaders/common/b.wgsl", b"#include \"c.wgsl\"\nfn b() {}")
+         .with_file("shaders/common/c.wgsl", b"fn c() {}")
+         .with_file("shaders/cycle.wgsl", b"#include \"common/d.wgsl\"")
+         .with_file("shaders/common/d.wgsl", b"\n  #include \"../cycle.wgsl\""));
+      let preprocessed = preprocess(&sources, "shaders/a.wgsl", &Preprocessor::new()).unwrap();
+      assert_eq!(preprocessed.text, "fn c() {}\nfn b() {}\nfn a() {}\n");
+      let origin = &preprocessed.origins[1];
+      assert_eq!((&*origin.file, origin.line), ("shaders/common/b.wgsl", 2));
+
+      let error = preprocess(&sources, "shaders/cycle.wgsl", &Preprocessor::new()).err().unwrap();
+      assert_eq!((error.file.as_str(), error.location), ("shaders/common/d.wgsl", Some((2, 3))));
+      assert!(error.message.ends_with("shaders/cycle.wgsl -> shaders/common/d.wgsl -> shaders/cycle.wgsl"));
+   }
+
+   fn parse<T: ShaderVariant>(sources: &AssetSources, key: &ShaderKey<T>) -> Result<naga::Module, ShaderError> {
+      let path = key.variant.as_ref().to_str().unwrap();
+      validate(&preprocess(sources, path, &key.preprocessor())?, path).map(|(module, _)| module)
+   }
+
+   fn permutation_errors<T: ShaderVariant>(sources: &AssetSources, variants: &[T]) -> Vec<String> {
+      variants.iter()
+         .flat_map(|variant| ShaderKey::permutations(*variant))
+         .filter_map(|key| parse(sources, &key).err().map(|e| format!("{:?} {e}", key.defines())))
+         .collect()
+   }
+
+   // @location inputs or outputs of an entry point, struct members flattened
+   fn locations(module: &naga::Module, ty: naga::Handle<naga::Type>, binding: &Option<naga::Binding>, out: &mut BTreeMap<u32, (naga::TypeInner, naga::Binding)>) {
+      match (binding, &module.types[ty].inner) {
+         (Some(binding @ naga::Binding::Location { location, .. }), inner) => {
+            out.insert(*location, (inner.clone(), binding.clone()));
+         },
+         (None, naga::TypeInner::Struct { members, .. }) => {
+            for member in members {
+               locations(module, member.ty, &member.binding, out);
+            }
+         },
+         _ => {},
+      }
+   }
+
+   // every location the fragment shader reads is written by the vertex shader, with the same type and interpolation
+   fn check_interface(vs: &naga::Module, fs: &naga::Module) -> Result<(), String> {
+      let entry_point = |module: &naga::Module, stage| module.entry_points.iter()
+         .find(|entry_point| entry_point.stage == stage)
+         .map(|entry_point| entry_point.function.clone())
+         .ok_or(format!("no {stage:?} entry point"));
+      let (vs_main, fs_main) = (entry_point(vs, naga::ShaderStage::Vertex)?, entry_point(fs, naga::ShaderStage::Fragment)?);
+      let mut outputs = BTreeMap::new();
+      if let Some(result) = &vs_main.result {
+         locations(vs, result.ty, &result.binding, &mut outputs);
+      }
+      let mut inputs = BTreeMap::new();
+      for argument in &fs_main.arguments {
+         locations(fs, argument.ty, &argument.binding, &mut inputs);
+      }
+      for (location, input) in inputs {
+         match outputs.get(&location) {
+            None => return Err(format!("location {location} isn't written by the vertex shader")),
+            Some(output) if *output != input => return Err(format!("location {location} is {output:?} in the vertex shader but {input:?} in the fragment shader")),
+            _ => {},
+         }
+      }
+      Ok(())
+   }
+
+   // uniform bindings of WGSL structs named in `rust_structs`, compared with the Rust struct
+   fn uniform_errors<T: ShaderVariant>(sources: &AssetSources, variants: &[T], rust_structs: &[(&str, UniformStruct)]) -> Vec<String> {
+      let mut errors = vec![];
+      for key in variants.iter().flat_map(|variant| ShaderKey::permutations(*variant)) {
+         let path = key.variant.as_ref().to_str().unwrap();
+         let (module, info) = validate(&preprocess(sources, path, &key.preprocessor()).unwrap(), path).unwrap();
+         for binding in ShaderReflection::new(path, &module, &info).bindings {
+            let ReflectedResource::Uniform { struct_name, .. } = &binding.resource else {
+               continue;
+            };
+            let Some((_, rust_struct)) = rust_structs.iter().find(|(name, _)| name == struct_name) else {
+               continue;
+            };
+            let entry = wgpu::BindGroupLayoutEntry {
+               binding: binding.binding,
+               visibility: wgpu::ShaderStages::all(),
+               ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+               count: None,
+            };
+            errors.extend(check_binding(&binding, &[entry], &[(binding.binding, rust_struct.clone())]).into_iter()
+               .map(|e| format!("{path} {:?}: {e}", key.defines())));
+         }
+      }
+      errors
+   }
+
+   #[test]
+   fn shared_uniform_structs_match_their_wgsl() {
+      let rust_structs = [
+         ("DemoSettingsStable", UniformStruct::of::<StableGlobalUniformData>()),
+         ("DemoSettingsDynamic", UniformStruct::of::<DynamicGlobalUniformData>()),
+         ("Lights", UniformStruct::of::<LightsUniformData>()),
+      ];
+      let sources = shader_sources();
+      let mut errors = uniform_errors(&sources, &VertexShaderVariant::ALL, &rust_structs);
+      errors.extend(uniform_errors(&sources, &FragmentShaderVariant::ALL, &rust_structs));
+      assert!(errors.is_empty(), "{}", errors.join("\n"));
+   }
+
+   #[test]
+   fn permutations_validate_without_a_device() {
+      let sources = shader_sources();
+      let mut errors = permutation_errors(&sources, &VertexShaderVariant::ALL);
+      errors.extend(permutation_errors(&sources, &FragmentShaderVariant::ALL));
+      assert!(errors.is_empty(), "{}", errors.join("\n"));
+      assert_eq!(ShaderKey::permutations(FragmentShaderVariant::FractalMandelbrot).len(), 2);
+   }
+
+   #[test]
+   fn linked_stages_have_matching_interfaces() {
+      use VertexShaderVariant::*;
+      use FragmentShaderVariant::*;
+      // the pipelines of the demos, IBL baking, the skybox and mipmap generation
+      let linked = [
+         (demo_uv::VERTEX_SHADER_VARIANT, demo_uv::FRAGMENT_SHADER_VARIANT),
+         (demo_fractal::VERTEX_SHADER_VARIANT, demo_fractal::FRAGMENT_SHADER_VARIANT),
+         (demo_mesh::VERTEX_SHADER_VARIANT, demo_mesh::FRAGMENT_SHADER_VARIANT),
+         (TriangleFullscreen, EquirectToCube),
+         (TriangleFullscreen, IrradianceConvolution),
+         (TriangleFullscreen, SpecularPrefilter),
+         (TriangleFullscreen, BrdfLut),
+         (TriangleFullscreen, Skybox),
+         (TriangleFullscreen, MipmapBlit),
+      ];
+      let sources = shader_sources();
+      for (vs, fs) in linked {
+         let vs_module = parse(&sources, &vs.into()).unwrap();
+         for key in ShaderKey::permutations(fs) {
+            let fs_module = parse(&sources, &key).unwrap();
+            if let Err(e) = check_interface(&vs_module, &fs_module) {
+               panic!("{} -> {} {:?}: {e}", vs.as_ref().display(), fs.as_ref().display(), key.defines());
+            }
+         }
+      }
+      // reads a vec3 color where the fullscreen triangle writes a vec2 uv
+      let error = check_interface(&parse(&sources, &TriangleFullscreen.into()).unwrap(), &parse(&sources, &VertexColor.into()).unwrap());
+      assert!(error.err().unwrap().starts_with("location 0 is"));
    }
 }
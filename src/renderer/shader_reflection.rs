@@ -0,0 +1,299 @@
+use wgpu::{BindingType, ShaderStages};
+
+use super::webgpu::uniform::{BindGroupInfo, ScalarType, UniformScalar, UniformStruct};
+
+// A resource bound to a shader, read from its naga module
+#[derive(Clone, Debug)]
+pub struct ReflectedBinding {
+   pub group: u32,
+   pub binding: u32,
+   pub name: String,
+   // entry points using it
+   pub stages: ShaderStages,
+   pub resource: ReflectedResource,
+}
+
+#[derive(Clone, Debug)]
+pub enum ReflectedResource {
+   Uniform {
+      struct_name: String,
+      size: usize,
+      // padding members, named "..__", are left out
+      scalars: Vec<UniformScalar>,
+   },
+   Storage { read_only: bool },
+   Texture { dimension: wgpu::TextureViewDimension, class: naga::ImageClass },
+   Sampler { comparison: bool },
+}
+
+impl ReflectedResource {
+   fn describe(&self) -> String {
+      match self {
+         ReflectedResource::Uniform { struct_name, .. } => format!("a uniform buffer of {struct_name}"),
+         ReflectedResource::Storage { read_only: true } => "a read only storage buffer".to_owned(),
+         ReflectedResource::Storage { read_only: false } => "a storage buffer".to_owned(),
+         ReflectedResource::Texture { dimension, class } => format!("a {dimension:?} texture of {class:?}"),
+         ReflectedResource::Sampler { comparison: true } => "a comparison sampler".to_owned(),
+         ReflectedResource::Sampler { comparison: false } => "a sampler".to_owned(),
+      }
+   }
+}
+
+pub struct ShaderReflection {
+   pub file: String,
+   pub bindings: Vec<ReflectedBinding>,
+}
+
+impl ShaderReflection {
+   // bindings no entry point uses are left out, like wgpu does
+   pub fn new(file: &str, module: &naga::Module, info: &naga::valid::ModuleInfo) -> Self {
+      let mut bindings = vec![];
+      for (handle, variable) in module.global_variables.iter() {
+         let Some(resource_binding) = &variable.binding else {
+            continue;
+         };
+         let stages = module.entry_points.iter()
+            .enumerate()
+            .filter(|(i, _)| !info.get_entry_point(*i)[handle].is_empty())
+            .fold(ShaderStages::NONE, |stages, (_, entry_point)| stages | match entry_point.stage {
+               naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+               naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+               naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+            });
+         if stages.is_empty() {
+            continue;
+         }
+         let ty = &module.types[variable.ty];
+         let resource = match (variable.space, &ty.inner) {
+            (naga::AddressSpace::Uniform, inner) => {
+               let mut scalars = vec![];
+               wgsl_scalars(module, variable.ty, "", 0, &mut scalars);
+               ReflectedResource::Uniform {
+                  struct_name: ty.name.clone().unwrap_or_default(),
+                  size: inner.size(module.to_ctx()) as usize,
+                  scalars,
+               }
+            },
+            (naga::AddressSpace::Storage { access }, _) => ReflectedResource::Storage {
+               read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            (naga::AddressSpace::Handle, naga::TypeInner::Image { dim, arrayed, class }) => ReflectedResource::Texture {
+               dimension: match (dim, arrayed) {
+                  (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                  (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                  (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                  (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                  (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                  (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+               },
+               class: *class,
+            },
+            (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => ReflectedResource::Sampler {
+               comparison: *comparison,
+            },
+            _ => continue,
+         };
+         bindings.push(ReflectedBinding {
+            group: resource_binding.group,
+            binding: resource_binding.binding,
+            name: variable.name.clone().unwrap_or_default(),
+            stages,
+            resource,
+         });
+      }
+      Self { file: file.to_owned(), bindings }
+   }
+
+   // Mismatches with the bind groups of a pipeline, `groups` are by group index
+   pub fn check(&self, groups: &[&BindGroupInfo]) -> Vec<String> {
+      self.bindings.iter()
+         .flat_map(|binding| match groups.get(binding.group as usize) {
+            Some(group) => check_binding(binding, &group.layout_entries, &group.uniform_structs),
+            None => vec![format!("{} (group {}) isn't bound, the pipeline has {} groups", binding.name, binding.group, groups.len())],
+         })
+         .collect()
+   }
+}
+
+fn scalar_type(scalar: naga::Scalar) -> Option<ScalarType> {
+   match (scalar.kind, scalar.width) {
+      (naga::ScalarKind::Float, 4) => Some(ScalarType::F32),
+      (naga::ScalarKind::Sint, 4) => Some(ScalarType::I32),
+      (naga::ScalarKind::Uint, 4) => Some(ScalarType::U32),
+      _ => None,
+   }
+}
+
+// scalars of a host-shareable type with their byte offsets, like `UniformLayout::scalars`
+fn wgsl_scalars(module: &naga::Module, ty: naga::Handle<naga::Type>, path: &str, offset: usize, out: &mut Vec<UniformScalar>) {
+   const COMPONENTS: [&str; 4] = ["x", "y", "z", "w"];
+   let mut push = |path: String, offset: usize, scalar: naga::Scalar| {
+      if let Some(ty) = scalar_type(scalar) {
+         out.push(UniformScalar { path, offset, ty });
+      }
+   };
+   match &module.types[ty].inner {
+      naga::TypeInner::Scalar(scalar) | naga::TypeInner::Atomic(scalar) => push(path.to_owned(), offset, *scalar),
+      naga::TypeInner::Vector { size, scalar } => {
+         for (i, component) in COMPONENTS.iter().enumerate().take(*size as usize) {
+            push(format!("{path}.{component}"), offset + i * scalar.width as usize, *scalar);
+         }
+      },
+      naga::TypeInner::Matrix { columns, rows, scalar } => {
+         // columns are aligned like vectors, vec3 like vec4
+         let column_stride = match rows {
+            naga::VectorSize::Bi => 2,
+            _ => 4,
+         } * scalar.width as usize;
+         for column in 0..*columns as usize {
+            for (row, component) in COMPONENTS.iter().enumerate().take(*rows as usize) {
+               push(format!("{path}[{column}].{component}"), offset + column * column_stride + row * scalar.width as usize, *scalar);
+            }
+         }
+      },
+      naga::TypeInner::Array { base, size: naga::ArraySize::Constant(length), stride } => {
+         for i in 0..length.get() as usize {
+            wgsl_scalars(module, *base, &format!("{path}[{i}]"), offset + i * *stride as usize, out);
+         }
+      },
+      naga::TypeInner::Struct { members, .. } => {
+         for member in members {
+            let name = member.name.as_deref().unwrap_or_default();
+            if name.ends_with("__") {
+               continue;
+            }
+            let path = match path.is_empty() {
+               true => name.to_owned(),
+               false => format!("{path}.{name}"),
+            };
+            wgsl_scalars(module, member.ty, &path, offset + member.offset as usize, out);
+         }
+      },
+      _ => {},
+   }
+}
+
+pub fn check_binding(binding: &ReflectedBinding, layout_entries: &[wgpu::BindGroupLayoutEntry], uniform_structs: &[(u32, UniformStruct)]) -> Vec<String> {
+   let name = format!("{} (group {} binding {})", binding.name, binding.group, binding.binding);
+   let Some(entry) = layout_entries.iter().find(|entry| entry.binding == binding.binding) else {
+      return vec![format!("{name} isn't in the bind group layout")];
+   };
+   let mut errors = vec![];
+   if !entry.visibility.contains(binding.stages) {
+      errors.push(format!("{name} is used by {:?}, but visible to {:?}", binding.stages, entry.visibility));
+   }
+   let matches = match (&binding.resource, entry.ty) {
+      (ReflectedResource::Uniform { struct_name, size, scalars }, BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, min_binding_size, .. }) => {
+         if let Some(min_size) = min_binding_size.filter(|min_size| min_size.get() < *size as u64) {
+            errors.push(format!("{name} is {size} bytes, but the layout binds at least {min_size}"));
+         }
+         let rust_struct = uniform_structs.iter().find(|(rust_binding, _)| *rust_binding == binding.binding);
+         if let Some((_, rust_struct)) = rust_struct {
+            errors.extend(compare_structs(struct_name, *size, scalars, rust_struct).into_iter()
+               .map(|error| format!("{name}: {error}")));
+         }
+         true
+      },
+      (ReflectedResource::Storage { read_only }, BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: layout_read_only }, .. }) =>
+         *read_only || !layout_read_only,
+      (ReflectedResource::Texture { dimension, class }, BindingType::Texture { view_dimension, sample_type, multisampled }) => {
+         let class_matches = match class {
+            naga::ImageClass::Sampled { kind, multi } => *multi == multisampled && match kind {
+               naga::ScalarKind::Float => matches!(sample_type, wgpu::TextureSampleType::Float { .. }),
+               naga::ScalarKind::Sint => sample_type == wgpu::TextureSampleType::Sint,
+               naga::ScalarKind::Uint => sample_type == wgpu::TextureSampleType::Uint,
+               _ => false,
+            },
+            naga::ImageClass::Depth { multi } => *multi == multisampled && sample_type == wgpu::TextureSampleType::Depth,
+            naga::ImageClass::Storage { .. } => false,
+         };
+         *dimension == view_dimension && class_matches
+      },
+      (ReflectedResource::Texture { dimension, class: naga::ImageClass::Storage { .. } }, BindingType::StorageTexture { view_dimension, .. }) =>
+         *dimension == view_dimension,
+      (ReflectedResource::Sampler { comparison }, BindingType::Sampler(sampler_type)) =>
+         *comparison == (sampler_type == wgpu::SamplerBindingType::Comparison),
+      _ => false,
+   };
+   if !matches {
+      errors.push(format!("{name} is {} in WGSL, but the layout has {:?}", binding.resource.describe(), entry.ty));
+   }
+   errors
+}
+
+// both sides list their scalars by byte offset
+fn compare_structs(wgsl_name: &str, wgsl_size: usize, wgsl_scalars: &[UniformScalar], rust_struct: &UniformStruct) -> Vec<String> {
+   let mut errors = vec![];
+   if wgsl_size != rust_struct.size {
+      errors.push(format!("{wgsl_name} is {wgsl_size} bytes, {} is {}", rust_struct.name, rust_struct.size));
+   }
+   for wgsl in wgsl_scalars {
+      match rust_struct.scalars.iter().find(|rust| rust.offset == wgsl.offset) {
+         None => errors.push(format!("{wgsl_name}.{} at byte {} has no field in {}", wgsl.path, wgsl.offset, rust_struct.name)),
+         Some(rust) if rust.ty != wgsl.ty => errors.push(format!("{wgsl_name}.{} is {}, but {}.{} is {}",
+            wgsl.path, wgsl.ty, rust_struct.name, rust.path, rust.ty)),
+         _ => {},
+      }
+   }
+   for rust in &rust_struct.scalars {
+      if !wgsl_scalars.iter().any(|wgsl| wgsl.offset == rust.offset) {
+         errors.push(format!("{}.{} at byte {} has no field in {wgsl_name}", rust_struct.name, rust.path, rust.offset));
+      }
+   }
+   errors
+}
+
+#[cfg(test)]
+mod tests {
+   use wgsl_derive::WgslStruct;
+
+   use super::*;
+
+   #[repr(C)]
+   #[derive(WgslStruct)]
+   struct Settings {
+      center: [f32; 2],
+      iterations: u32,
+      zoom: f32,
+   }
+
+   fn reflect(source: &str) -> ShaderReflection {
+      let module = naga::front::wgsl::parse_str(source).unwrap();
+      let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+         .validate(&module)
+         .unwrap();
+      ShaderReflection::new("test.fs.wgsl", &module, &info)
+   }
+
+   fn uniform_entry(binding: u32, visibility: ShaderStages) -> wgpu::BindGroupLayoutEntry {
+      wgpu::BindGroupLayoutEntry {
+         binding,
+         visibility,
+         ty: BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+         count: None,
+      }
+   }
+
+   #[test]
+   fn uniform_structs_are_compared_by_offset_and_type() {
+      let reflection = reflect("struct Settings { center: vec2<f32>, iterations: i32, zoom: f32, padding__: vec4<f32> }\n\
+         @group(0) @binding(0) var<uniform> settings: Settings;\n\
+         @group(0) @binding(1) var unused: sampler;\n\
+         @fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(settings.zoom); }");
+      // bindings no entry point reads are left out
+      assert_eq!(reflection.bindings.len(), 1);
+      let binding = &reflection.bindings[0];
+      assert_eq!(binding.stages, ShaderStages::FRAGMENT);
+
+      let rust_struct = UniformStruct::of::<Settings>();
+      assert_eq!(rust_struct.scalars.len(), 4);
+      let errors = check_binding(binding, &[uniform_entry(0, ShaderStages::VERTEX)], &[(0, rust_struct)]);
+      assert_eq!(errors, [
+         "settings (group 0 binding 0) is used by ShaderStages(FRAGMENT), but visible to ShaderStages(VERTEX)",
+         "settings (group 0 binding 0): Settings is 32 bytes, Settings is 16",
+         "settings (group 0 binding 0): Settings.iterations is i32, but Settings.iterations is u32",
+      ]);
+      assert_eq!(check_binding(binding, &[], &[]), ["settings (group 0 binding 0) isn't in the bind group layout"]);
+      assert!(check_binding(binding, &[uniform_entry(0, ShaderStages::FRAGMENT)], &[]).is_empty());
+   }
+}
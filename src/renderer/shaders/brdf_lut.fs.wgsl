@@ -0,0 +1,48 @@
+#include "common/vertex_output_uv.wgsl"
+
+const PI = 3.14159265359;
+const SAMPLE_COUNT = 512u;
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// scale and bias to F0 of the split-sum specular term, by (N.V, roughness)
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 1e-3);
+    // texture rows go top to bottom, fullscreen triangle UV goes bottom to top
+    let roughness = 1.0 - in.uv.y;
+    let view_dir = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let half_vector = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), roughness);
+        let light_dir = normalize(2.0 * dot(view_dir, half_vector) * half_vector - view_dir);
+        let n_dot_l = max(light_dir.z, 0.0);
+        let n_dot_h = max(half_vector.z, 0.0);
+        let v_dot_h = max(dot(view_dir, half_vector), 0.0);
+        if (n_dot_l > 0.0) {
+            let geometry = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let geometry_vis = geometry * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * geometry_vis;
+            bias += fresnel * geometry_vis;
+        }
+    }
+    return vec4(scale, bias, 0.0, 1.0) / f32(SAMPLE_COUNT);
+}
@@ -0,0 +1,6 @@
+// Bound by every demo, declared by StableGlobalUniformData and DynamicGlobalUniformData
+#include "../generated/DemoSettingsStable.wgsl"
+#include "../generated/DemoSettingsDynamic.wgsl"
+
+@group(0) @binding(0) var<uniform> demo: DemoSettingsStable;
+@group(0) @binding(1) var<uniform> demo_dyn: DemoSettingsDynamic;
@@ -0,0 +1,5 @@
+// Output of the fullscreen triangle and the passthrough vertex shaders
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
@@ -0,0 +1,33 @@
+#include "common/vertex_output_uv.wgsl"
+#include "generated/BakeSettings.wgsl"
+
+@group(0) @binding(0) var<uniform> bake: BakeSettings;
+@group(1) @binding(0) var source_texture: texture_2d<f32>;
+@group(1) @binding(1) var source_sampler: sampler;
+
+const PI = 3.14159265359;
+
+// direction through a texel of a cube face, faces are ordered +X, -X, +Y, -Y, +Z, -Z
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    // fullscreen triangle UV has Y pointing up, face coordinates have Y pointing down
+    let st = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    switch face {
+        case 0u: { return normalize(vec3(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3(-st.x, -st.y, -1.0)); }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = cube_direction(bake.face, in.uv);
+    let equirect_uv = vec2(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    // explicit LOD, derivatives are discontinuous at the longitude seam
+    let color = textureSampleLevel(source_texture, source_sampler, equirect_uv, 0.0).rgb;
+    return vec4(color, 1.0);
+}
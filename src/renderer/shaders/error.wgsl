@@ -0,0 +1,14 @@
+// Stands in for shaders which don't compile, a magenta triangle covering the screen.
+// Has no vertex inputs, varyings or bindings, so it fits any pipeline
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let index = vertex_index % 3u;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+}
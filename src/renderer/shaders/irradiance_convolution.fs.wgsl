@@ -0,0 +1,43 @@
+#include "common/vertex_output_uv.wgsl"
+#include "generated/BakeSettings.wgsl"
+
+@group(0) @binding(0) var<uniform> bake: BakeSettings;
+@group(1) @binding(0) var environment_texture: texture_cube<f32>;
+@group(1) @binding(1) var environment_sampler: sampler;
+
+const PI = 3.14159265359;
+const SAMPLE_DELTA = 0.05;
+
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    switch face {
+        case 0u: { return normalize(vec3(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3(-st.x, -st.y, -1.0)); }
+    }
+}
+
+// cosine weighted integral of radiance over the hemisphere around the normal
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = cube_direction(bake.face, in.uv);
+    let up = select(vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0), abs(normal.y) > 0.999);
+    let right = normalize(cross(up, normal));
+    let tangent_up = cross(normal, right);
+
+    var irradiance = vec3(0.0);
+    var num_samples = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += SAMPLE_DELTA) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+            let sample_ts = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let sample_dir = sample_ts.x * right + sample_ts.y * tangent_up + sample_ts.z * normal;
+            irradiance += textureSampleLevel(environment_texture, environment_sampler, sample_dir, 0.0).rgb
+                * cos(theta) * sin(theta);
+            num_samples += 1.0;
+        }
+    }
+    return vec4(PI * irradiance / num_samples, 1.0);
+}
@@ -1,29 +1,6 @@
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-struct DemoSettingsStable {
-    color_attachment_size: vec2<i32>,
-    aspect_ratio: f32,
-    is_debug: f32,
-}
-
-struct DemoSettingsDynamic {
-    mouse_position: vec2<f32>,
-    padding__: vec2<i32>,
-}
-
-@group(0) @binding(0) var<uniform> demo: DemoSettingsStable;
-@group(0) @binding(1) var<uniform> demo_dyn: DemoSettingsDynamic;
-
-struct FractalSettings {
-    center: vec2<f32>,
-    zoom: f32,
-    num_iterations: i32,
-    color_bias: vec3<f32>,
-    color_power: f32,
-}
+#include "common/vertex_output_uv.wgsl"
+#include "common/global_uniform.wgsl"
+#include "generated/FractalSettings.wgsl"
 @group(1) @binding(0) var<uniform> fractal: FractalSettings;
 
 const AA : i32 = 2;
@@ -0,0 +1,195 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) position: vec3<f32>,
+};
+
+#include "common/global_uniform.wgsl"
+
+@group(1) @binding(0) var albedo_texture: texture_2d<f32>;
+@group(1) @binding(1) var normal_texture: texture_2d<f32>;
+@group(1) @binding(2) var height_texture: texture_2d<f32>;
+@group(1) @binding(3) var material_sampler: sampler;
+
+#include "generated/MeshSettings.wgsl"
+@group(1) @binding(4) var<uniform> mesh: MeshSettings;
+
+#include "generated/Lights.wgsl"
+
+// lengths of the light arrays in Lights
+const MAX_POINT_LIGHTS = 4u;
+const MAX_SPOT_LIGHTS = 4u;
+@group(2) @binding(0) var<uniform> lights: Lights;
+@group(2) @binding(1) var shadow_map: texture_depth_2d;
+@group(2) @binding(2) var shadow_sampler: sampler_comparison;
+
+@group(3) @binding(0) var irradiance_map: texture_cube<f32>;
+@group(3) @binding(1) var prefiltered_map: texture_cube<f32>;
+@group(3) @binding(2) var brdf_lut: texture_2d<f32>;
+@group(3) @binding(3) var ibl_sampler: sampler;
+// mip count of the prefiltered map - 1
+const MAX_REFLECTION_LOD = 4.0;
+
+// the mesh lies in XY plane, with UV aligned to XY axes,
+// so tangent space matches object space: T = +X, B = +Y, N = +Z,
+// and normal map values are used as they are
+
+// dielectric reflectance at normal incidence
+const F0 = vec3(0.04);
+
+struct ParallaxResult {
+    uv: vec2<f32>,
+    depth: f32,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // gradients are taken before any non-uniform control flow,
+    // all samples inside loops use textureSampleGrad
+    let duv_dx = dpdx(in.uv);
+    let duv_dy = dpdy(in.uv);
+    let view_ts = normalize(mesh.eye_position - in.position);
+    let light_ts = -normalize(lights.directional.direction);
+
+    var parallax = ParallaxResult(in.uv, 0.0);
+    var shadow = 1.0;
+    if (mesh.parallax_steps > 0u) {
+        parallax = parallax_occlusion(in.uv, view_ts, mesh.parallax_steps, duv_dx, duv_dy);
+        if (mesh.parallax_shadow_steps > 0u) {
+            shadow = parallax_self_shadow(parallax.uv, parallax.depth, light_ts, mesh.parallax_shadow_steps, duv_dx, duv_dy);
+        }
+    }
+
+    if (mesh.debug_view != 0u) {
+        // displaced UVs in RG, sampled depth in B
+        return vec4(fract(parallax.uv), parallax.depth, 1.0);
+    }
+
+    let albedo = textureSampleGrad(albedo_texture, material_sampler, parallax.uv, duv_dx, duv_dy).rgb;
+    // without parallax steps this is the undisplaced UV
+    let normal = normalize(textureSampleGrad(normal_texture, material_sampler, parallax.uv, duv_dx, duv_dy).xyz * 2.0 - 1.0);
+    shadow *= directional_shadow(in.position);
+    var radiance = lights.directional.color * lights.directional.intensity
+        * max(dot(normal, light_ts), 0.0) * shadow;
+    for (var i = 0u; i < min(lights.num_point_lights, MAX_POINT_LIGHTS); i++) {
+        radiance += point_light_radiance(lights.point_lights[i], in.position, normal);
+    }
+    for (var i = 0u; i < min(lights.num_spot_lights, MAX_SPOT_LIGHTS); i++) {
+        radiance += spot_light_radiance(lights.spot_lights[i], in.position, normal);
+    }
+    let shade = albedo * radiance + image_based_lighting(albedo, normal, view_ts);
+    return vec4<f32>(shade, 1.0);
+}
+
+// split-sum approximation with prefiltered environment maps
+fn image_based_lighting(albedo: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view_dir), 1e-3);
+    let fresnel = F0 + (max(vec3(1.0 - mesh.roughness), F0) - F0) * pow(1.0 - n_dot_v, 5.0);
+    let irradiance = textureSampleLevel(irradiance_map, ibl_sampler, normal, 0.0).rgb;
+    let reflected = reflect(-view_dir, normal);
+    let prefiltered = textureSampleLevel(prefiltered_map, ibl_sampler, reflected, mesh.roughness * MAX_REFLECTION_LOD).rgb;
+    let brdf = textureSampleLevel(brdf_lut, ibl_sampler, vec2(n_dot_v, mesh.roughness), 0.0).rg;
+    let diffuse = (1.0 - fresnel) * irradiance * albedo;
+    let specular = prefiltered * (fresnel * brdf.x + brdf.y);
+    return (diffuse + specular) * mesh.ibl_intensity;
+}
+
+// percentage closer filtering over (2*pcf_radius + 1)^2 texels of the shadow map
+fn directional_shadow(position: vec3<f32>) -> f32 {
+    if (lights.pcf_radius < 0) {
+        return 1.0;
+    }
+    let light_clip = lights.shadow_view_proj * vec4(position, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    let shadow_uv = light_ndc.xy * vec2(0.5, -0.5) + 0.5;
+    if (any(shadow_uv < vec2(0.0)) || any(shadow_uv > vec2(1.0)) || light_ndc.z > 1.0) {
+        return 1.0;
+    }
+    let texel = 1.0 / lights.shadow_map_size;
+    let reference_depth = light_ndc.z - lights.directional.shadow_bias;
+    var lit = 0.0;
+    for (var y = -lights.pcf_radius; y <= lights.pcf_radius; y++) {
+        for (var x = -lights.pcf_radius; x <= lights.pcf_radius; x++) {
+            let offset = vec2(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, shadow_uv + offset, reference_depth);
+        }
+    }
+    let kernel_size = f32(2 * lights.pcf_radius + 1);
+    return lit / (kernel_size * kernel_size);
+}
+
+fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let falloff = clamp(1.0 - distance / range, 0.0, 1.0);
+    return falloff * falloff;
+}
+
+fn point_light_radiance(light: PointLight, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let n_dot_l = max(dot(normal, to_light / max(distance, 1e-5)), 0.0);
+    return light.color * light.intensity * n_dot_l * distance_attenuation(distance, light.range);
+}
+
+fn spot_light_radiance(light: SpotLight, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let light_dir = to_light / max(distance, 1e-5);
+    let n_dot_l = max(dot(normal, light_dir), 0.0);
+    let cone = smoothstep(light.outer_cos, light.inner_cos, dot(-light_dir, normalize(light.direction)));
+    return light.color * light.intensity * n_dot_l * cone * distance_attenuation(distance, light.range);
+}
+
+fn sample_depth(uv: vec2<f32>, duv_dx: vec2<f32>, duv_dy: vec2<f32>) -> f32 {
+    return 1.0 - textureSampleGrad(height_texture, material_sampler, uv, duv_dx, duv_dy).r;
+}
+
+// steep parallax ray march, refined with linear interpolation between the last two layers
+fn parallax_occlusion(uv: vec2<f32>, view_ts: vec3<f32>, num_steps: u32, duv_dx: vec2<f32>, duv_dy: vec2<f32>) -> ParallaxResult {
+    let layer_depth = 1.0 / f32(num_steps);
+    let uv_shift = view_ts.xy / max(view_ts.z, 0.05) * mesh.parallax_scale;
+    let delta_uv = uv_shift * layer_depth;
+
+    var current_uv = uv;
+    var current_layer_depth = 0.0;
+    var current_depth = sample_depth(current_uv, duv_dx, duv_dy);
+    for (var i = 0u; i < num_steps; i++) {
+        if (current_layer_depth >= current_depth) {
+            break;
+        }
+        current_uv -= delta_uv;
+        current_depth = sample_depth(current_uv, duv_dx, duv_dy);
+        current_layer_depth += layer_depth;
+    }
+
+    let previous_uv = current_uv + delta_uv;
+    let depth_after = current_depth - current_layer_depth;
+    let depth_before = sample_depth(previous_uv, duv_dx, duv_dy) - current_layer_depth + layer_depth;
+    let weight = depth_after / min(depth_after - depth_before, -1e-5);
+    return ParallaxResult(
+        mix(current_uv, previous_uv, weight),
+        mix(current_layer_depth, current_layer_depth - layer_depth, weight));
+}
+
+// march from the displaced point towards the light, soft shadow from the deepest occluder
+fn parallax_self_shadow(uv: vec2<f32>, depth: f32, light_ts: vec3<f32>, num_steps: u32, duv_dx: vec2<f32>, duv_dy: vec2<f32>) -> f32 {
+    if (light_ts.z <= 0.0 || depth <= 0.0) {
+        return 1.0;
+    }
+    let layer_depth = depth / f32(num_steps);
+    let delta_uv = light_ts.xy / light_ts.z * mesh.parallax_scale / f32(num_steps) * depth;
+
+    var current_uv = uv + delta_uv;
+    var current_layer_depth = depth - layer_depth;
+    var occlusion = 0.0;
+    for (var i = 1u; i <= num_steps; i++) {
+        if (current_layer_depth <= 0.0) {
+            break;
+        }
+        let surface_depth = sample_depth(current_uv, duv_dx, duv_dy);
+        let step_weight = 1.0 - f32(i) / f32(num_steps);
+        occlusion = max(occlusion, (current_layer_depth - surface_depth) * mesh.shadow_softness * step_weight);
+        current_uv += delta_uv;
+        current_layer_depth -= layer_depth;
+    }
+    return 1.0 - clamp(occlusion, 0.0, 1.0);
+}
@@ -0,0 +1,12 @@
+#include "common/vertex_output_uv.wgsl"
+
+// previous mip level, sRGB views decode to linear on sampling and encode on writing
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+// the target is half the size of the source, so a single bilinear tap averages 2x2 texels
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = vec2(in.uv.x, 1.0 - in.uv.y);
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0);
+}
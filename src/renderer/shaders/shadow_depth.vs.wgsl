@@ -0,0 +1,18 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+#include "generated/DirectionalLight.wgsl"
+
+struct ShadowCaster {
+    light: DirectionalLight,
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> shadow: ShadowCaster;
+
+@vertex
+fn vs_main(in_vertex: VertexInput,
+) -> @builtin(position) vec4<f32> {
+    return shadow.view_proj * vec4(in_vertex.position, 1.0);
+}
@@ -0,0 +1,16 @@
+#include "common/vertex_output_uv.wgsl"
+// inv_view_proj is the inverse of projection * view, with translation removed from the view
+#include "generated/SkyboxSettings.wgsl"
+
+@group(0) @binding(0) var<uniform> skybox: SkyboxSettings;
+@group(0) @binding(1) var environment_texture: texture_cube<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ndc = in.uv * 2.0 - 1.0;
+    let world = skybox.inv_view_proj * vec4(ndc, 1.0, 1.0);
+    let direction = normalize(world.xyz / world.w);
+    let color = textureSampleLevel(environment_texture, environment_sampler, direction, 0.0).rgb;
+    return vec4(color * skybox.exposure, 1.0);
+}
@@ -0,0 +1,68 @@
+#include "common/vertex_output_uv.wgsl"
+#include "generated/BakeSettings.wgsl"
+
+@group(0) @binding(0) var<uniform> bake: BakeSettings;
+@group(1) @binding(0) var environment_texture: texture_cube<f32>;
+@group(1) @binding(1) var environment_sampler: sampler;
+
+const PI = 3.14159265359;
+const SAMPLE_COUNT = 256u;
+
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    switch face {
+        case 0u: { return normalize(vec3(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3(-st.x, -st.y, -1.0)); }
+    }
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let half_ts = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    let up = select(vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), abs(normal.z) > 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * half_ts.x + bitangent * half_ts.y + normal * half_ts.z);
+}
+
+// split-sum approximation, assumes the view direction equals the normal
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = cube_direction(bake.face, in.uv);
+    let size = f32(textureDimensions(environment_texture).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * size * size);
+    var color = vec3(0.0);
+    var total_weight = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let half_vector = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, bake.roughness);
+        let light_dir = normalize(2.0 * dot(normal, half_vector) * half_vector - normal);
+        let n_dot_l = dot(normal, light_dir);
+        if (n_dot_l > 0.0) {
+            // each sample covers the solid angle of its pdf, read from the mip of matching texel size;
+            // with V = N the pdf D * n_dot_h / (4 * v_dot_h) is D / 4
+            let pdf = distribution_ggx(max(dot(normal, half_vector), 0.0), bake.roughness) * 0.25;
+            let sample_solid_angle = 1.0 / (f32(SAMPLE_COUNT) * pdf + 1e-4);
+            let lod = select(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0, bake.roughness == 0.0);
+            color += textureSampleLevel(environment_texture, environment_sampler, light_dir, max(lod, 0.0)).rgb * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+    return vec4(color / max(total_weight, 1e-4), 1.0);
+}
@@ -1,7 +1,4 @@
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
+#include "common/vertex_output_uv.wgsl"
 
 @vertex
 fn vs_main(
@@ -1,21 +1,5 @@
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-};
-
-struct DemoSettingsStable {
-    color_attachment_size: vec2<i32>,
-    aspect_ratio: f32,
-    is_debug: f32,
-}
-
-struct DemoSettingsDynamic {
-    mouse_position: vec2<f32>,
-    padding__: vec2<i32>,
-}
-
-@group(0) @binding(0) var<uniform> demo: DemoSettingsStable;
-@group(0) @binding(1) var<uniform> demo_dyn: DemoSettingsDynamic;
+#include "common/vertex_output_uv.wgsl"
+#include "common/global_uniform.wgsl"
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
@@ -79,7 +79,7 @@ impl Webgpu {
       let (width, height) = (canvas.width(), canvas.height()); // TODO: newly created, maybe pass size, or resize in init_fn
 
       let mut device_result = adapter.request_device(
-         &Utils::default_device_descriptor(),
+         &Utils::default_device_descriptor(adapter.features()),
          None, // Trace path
       ).await;
 
@@ -143,7 +143,7 @@ impl Webgpu {
       ).await.unwrap();
 
       let (device, queue) = adapter
-         .request_device(&Utils::default_device_descriptor(), None)
+         .request_device(&Utils::default_device_descriptor(adapter.features()), None)
          .await.unwrap();
 
       let surface_caps = surface.get_capabilities(&adapter);
@@ -185,7 +185,7 @@ impl Webgpu {
          .await
          .unwrap();
       let (device, queue) = adapter
-         .request_device(&Utils::default_device_descriptor(), None)
+         .request_device(&Utils::default_device_descriptor(adapter.features()), None)
          .await
          .unwrap();
 
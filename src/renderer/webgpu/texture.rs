@@ -1,3 +1,5 @@
+use wgpu::util::DeviceExt;
+
 pub struct TextureBuilder<'a> {
    size: wgpu::Extent3d,
    mip_level_count: u32,
@@ -23,6 +25,13 @@ impl<'a> TextureBuilder<'a> {
       }
    }
 
+   // 6 array layers, to be viewed with `wgpu::TextureViewDimension::Cube`
+   pub fn new_cube(size: u32, format: wgpu::TextureFormat)-> TextureBuilder<'a> {
+      Self::new_2d(wgpu::Extent3d {
+         width: size, height: size, depth_or_array_layers: 6,
+      }, format)
+   }
+
    pub fn with_mip_level_count(mut self, mip_level_count: u32) -> Self {
       self.mip_level_count = mip_level_count;
       self
@@ -80,4 +89,17 @@ impl<'a> TextureBuilder<'a> {
          view_formats: &self.view_formats,
       })
    }
+
+   pub fn build_with_data(self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8]) -> wgpu::Texture {
+      device.create_texture_with_data(queue, &wgpu::TextureDescriptor {
+         size: self.size,
+         mip_level_count: self.mip_level_count,
+         sample_count: self.sample_count,
+         dimension: self.dimension,
+         format: self.format,
+         usage: self.usage | wgpu::TextureUsages::COPY_DST,
+         label: self.label,
+         view_formats: &self.view_formats,
+      }, wgpu::util::TextureDataOrder::LayerMajor, data)
+   }
 }
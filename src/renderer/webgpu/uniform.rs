@@ -1,14 +1,226 @@
 use crate::timer::ScopedTimer;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarType {
+   F32,
+   I32,
+   U32,
+}
+
+impl std::fmt::Display for ScalarType {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.write_str(match self {
+         ScalarType::F32 => "f32",
+         ScalarType::I32 => "i32",
+         ScalarType::U32 => "u32",
+      })
+   }
+}
+
+// a scalar of a uniform struct, e.g. "eye_position[1]" at byte 20
+#[derive(Clone, Debug)]
+pub struct UniformScalar {
+   pub path: String,
+   pub offset: usize,
+   pub ty: ScalarType,
+}
+
+// Data copied into uniform buffers, described down to its scalars
+// so it can be compared with the WGSL struct bound at its slot. Structs get it from `#[derive(WgslStruct)]`
+pub trait UniformLayout {
+   fn scalars(path: &str, offset: usize, out: &mut Vec<UniformScalar>);
+}
+
+macro_rules! scalar_layout {
+   ($($type:ty => $scalar:ident),*) => {
+      $(impl UniformLayout for $type {
+         fn scalars(path: &str, offset: usize, out: &mut Vec<UniformScalar>) {
+            out.push(UniformScalar { path: path.to_owned(), offset, ty: ScalarType::$scalar });
+         }
+      })*
+   };
+}
+
+scalar_layout!(f32 => F32, i32 => I32, u32 => U32);
+
+impl<T: UniformLayout, const N: usize> UniformLayout for [T; N] {
+   fn scalars(path: &str, offset: usize, out: &mut Vec<UniformScalar>) {
+      for i in 0..N {
+         T::scalars(&format!("{path}[{i}]"), offset + i * std::mem::size_of::<T>(), out);
+      }
+   }
+}
+
+// "path.name", or just "name" at the top
+pub fn member_path(path: &str, name: &str) -> String {
+   match path.is_empty() {
+      true => name.to_owned(),
+      false => format!("{path}.{name}"),
+   }
+}
+
+// A Rust type with a WGSL counterpart of the same layout.
+// ALIGN and SIZE are AlignOf and SizeOf of the WGSL type, https://www.w3.org/TR/WGSL/#alignment-and-size
+pub trait WgslType {
+   const ALIGN: usize;
+   const SIZE: usize;
+   // alignment of a member of this type in the uniform address space
+   const UNIFORM_ALIGN: usize = Self::ALIGN;
+
+   fn wgsl_type() -> String;
+
+   // generated files the WGSL type is declared in
+   fn wgsl_includes(_out: &mut Vec<String>) {}
+}
+
+// Implemented by `#[derive(WgslStruct)]`, which checks the alignment of the fields at compile time
+pub trait WgslStruct: WgslType {
+   const WGSL_NAME: &'static str;
+
+   // declaration including the structs of its members, padding members are added where needed
+   fn wgsl_struct() -> String;
+}
+
+macro_rules! wgsl_type {
+   ($($type:ty => $name:literal, $align:literal, $size:literal);* $(;)?) => {
+      $(impl WgslType for $type {
+         const ALIGN: usize = $align;
+         const SIZE: usize = $size;
+
+         fn wgsl_type() -> String {
+            $name.to_owned()
+         }
+      })*
+   };
+}
+
+wgsl_type!(
+   f32 => "f32", 4, 4;
+   i32 => "i32", 4, 4;
+   u32 => "u32", 4, 4;
+   [f32; 2] => "vec2<f32>", 8, 8;
+   [f32; 3] => "vec3<f32>", 16, 12;
+   [f32; 4] => "vec4<f32>", 16, 16;
+   [i32; 2] => "vec2<i32>", 8, 8;
+   [i32; 3] => "vec3<i32>", 16, 12;
+   [i32; 4] => "vec4<i32>", 16, 16;
+   [u32; 2] => "vec2<u32>", 8, 8;
+   [u32; 3] => "vec3<u32>", 16, 12;
+   [u32; 4] => "vec4<u32>", 16, 16;
+   // column major, columns of 4 rows have no padding
+   [[f32; 4]; 2] => "mat2x4<f32>", 16, 32;
+   [[f32; 4]; 3] => "mat3x4<f32>", 16, 48;
+   [[f32; 4]; 4] => "mat4x4<f32>", 16, 64;
+);
+
+impl<T: WgslStruct, const N: usize> WgslType for [T; N] {
+   const ALIGN: usize = T::ALIGN;
+   // the derive makes the struct size a multiple of its alignment, so the stride is the Rust one
+   const SIZE: usize = N * T::SIZE;
+   const UNIFORM_ALIGN: usize = {
+      assert!(T::SIZE % 16 == 0, "elements of arrays in a uniform buffer have to be a multiple of 16 bytes");
+      uniform_struct_align(T::ALIGN)
+   };
+
+   fn wgsl_type() -> String {
+      format!("array<{}, {N}>", T::WGSL_NAME)
+   }
+
+   fn wgsl_includes(out: &mut Vec<String>) {
+      T::wgsl_includes(out);
+   }
+}
+
+pub const fn max_align(aligns: &[usize]) -> usize {
+   let (mut max, mut i) = (1, 0);
+   while i < aligns.len() {
+      if aligns[i] > max {
+         max = aligns[i];
+      }
+      i += 1;
+   }
+   max
+}
+
+// structs and arrays in uniform buffers are aligned to 16 bytes at least
+pub const fn uniform_struct_align(align: usize) -> usize {
+   if align > 16 { align } else { 16 }
+}
+
+pub fn push_include<T: WgslStruct>(out: &mut Vec<String>) {
+   let include = format!("{}.wgsl", T::WGSL_NAME);
+   if !out.contains(&include) {
+      out.push(include);
+   }
+}
+
+// a non-padding field of a `WgslStruct`
+pub struct WgslMember {
+   pub name: &'static str,
+   pub offset: usize,
+   pub align: usize,
+   pub size: usize,
+   pub ty: String,
+}
+
+pub fn wgsl_struct_declaration<T: WgslStruct>(includes: &[String], members: &[WgslMember]) -> String {
+   let round_up = |offset: usize, align: usize| offset.div_ceil(align) * align;
+   let mut text = String::new();
+   for include in includes {
+      text += &format!("#include \"{include}\"\n");
+   }
+   text += &format!("// generated from {}\nstruct {} {{\n", std::any::type_name::<T>(), T::WGSL_NAME);
+   let (mut end, mut paddings) = (0, 0);
+   let mut pad_while = |text: &mut String, end: &mut usize, gap: &dyn Fn(usize) -> bool| {
+      // the derive checks offsets are aligned, f32 padding fills any gap
+      while gap(*end) {
+         *text += &format!("    padding{paddings}__: f32,\n");
+         paddings += 1;
+         *end += 4;
+      }
+   };
+   for member in members {
+      pad_while(&mut text, &mut end, &|end| round_up(end, member.align) < member.offset);
+      text += &format!("    {}: {},\n", member.name, member.ty);
+      end = member.offset + member.size;
+   }
+   pad_while(&mut text, &mut end, &|end| round_up(end, T::ALIGN) < T::SIZE);
+   text += "}\n";
+   text
+}
+
+// the Rust struct written to a uniform binding
+#[derive(Clone, Debug)]
+pub struct UniformStruct {
+   pub name: &'static str,
+   pub size: usize,
+   pub scalars: Vec<UniformScalar>,
+}
+
+impl UniformStruct {
+   pub fn of<T: UniformLayout>() -> Self {
+      let mut scalars = vec![];
+      T::scalars("", 0, &mut scalars);
+      Self {
+         name: std::any::type_name::<T>().rsplit("::").next().unwrap(),
+         size: std::mem::size_of::<T>(),
+         scalars,
+      }
+   }
+}
+
 pub struct BindGroupInfo {
    pub bind_group: wgpu::BindGroup,
    pub layout: wgpu::BindGroupLayout,
    pub layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+   // Rust structs of uniform bindings, by binding
+   pub uniform_structs: Vec<(u32, UniformStruct)>,
 }
 
 pub struct BindGroupBuilfer<'a> {
    layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
    group_entries: Vec<wgpu::BindGroupEntry<'a>>,
+   uniform_structs: Vec<(u32, UniformStruct)>,
 }
 
 impl BindGroupInfo {
@@ -16,6 +228,7 @@ impl BindGroupInfo {
       BindGroupBuilfer {
          layout_entries: vec![],
          group_entries: vec![],
+         uniform_structs: vec![],
       }
    }
 
@@ -73,6 +286,31 @@ impl<'a> BindGroupBuilfer<'a> {
       self
    }
 
+   // `size` bytes are bound, the offset is passed to `set_bind_group` for each draw
+   pub fn with_dynamic_uniform_buffer(mut self, binding: u32, visibility: wgpu::ShaderStages, buffer: &'a wgpu::Buffer, size: u64) -> Self {
+      let layout_entry = wgpu::BindGroupLayoutEntry {
+         binding,
+         visibility,
+         ty: wgpu::BindingType::Buffer {
+             ty: wgpu::BufferBindingType::Uniform,
+             has_dynamic_offset: true,
+             min_binding_size: std::num::NonZeroU64::new(size),
+         },
+         count: None,
+      };
+      let group_entry = wgpu::BindGroupEntry {
+         binding,
+         resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding{
+            buffer,
+            offset: 0,
+            size: std::num::NonZeroU64::new(size),
+         }),
+      };
+      self.layout_entries.push(layout_entry);
+      self.group_entries.push(group_entry);
+      self
+   }
+
    pub fn with_texture_2d(self, binding: u32, visibility: wgpu::ShaderStages, sample_type: wgpu::TextureSampleType, view: &'a wgpu::TextureView) -> Self {
       self.with_texture(binding, visibility,
          wgpu::TextureViewDimension::D2, sample_type,
@@ -121,6 +359,28 @@ impl<'a> BindGroupBuilfer<'a> {
       self
    }
 
+   pub fn with_comparison_sampler(mut self, binding: u32, visibility: wgpu::ShaderStages, sampler: &'a wgpu::Sampler) -> Self {
+      let layout_entry = wgpu::BindGroupLayoutEntry {
+         binding,
+         visibility,
+         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+         count: None,
+      };
+      let group_entry = wgpu::BindGroupEntry {
+         binding,
+         resource: wgpu::BindingResource::Sampler(&sampler),
+      };
+      self.layout_entries.push(layout_entry);
+      self.group_entries.push(group_entry);
+      self
+   }
+
+   // the Rust struct written to the uniform buffer at `binding`, shaders are checked against it
+   pub fn with_uniform_layout<T: UniformLayout>(mut self, binding: u32) -> Self {
+      self.uniform_structs.push((binding, UniformStruct::of::<T>()));
+      self
+   }
+
    pub fn with_entries(mut self, layout_entry: wgpu::BindGroupLayoutEntry, entry: wgpu::BindGroupEntry<'a>) -> Self {
       self.layout_entries.push(layout_entry);
       self.group_entries.push(entry);
@@ -141,8 +401,46 @@ impl<'a> BindGroupBuilfer<'a> {
       });
       BindGroupInfo {
          layout_entries: self.layout_entries,
+         uniform_structs: self.uniform_structs,
          layout,
          bind_group,
       }
    }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+   use wgsl_derive::WgslStruct;
+
+   use crate::renderer::lighting::{LightsUniformData, SpotLight};
+
+   use super::*;
+
+   #[repr(C)]
+   #[derive(WgslStruct)]
+   #[wgsl(name = "Padded")]
+   struct PaddedData {
+      #[wgsl(name = "scale")]
+      scale_factor: f32,
+      __padding0: f32,
+      offset: f32,
+      __padding1: f32,
+      color: [f32; 3],
+      __padding2: [f32; 5],
+   }
+
+   #[test]
+   fn wgsl_structs_fill_gaps_with_padding() {
+      // WGSL would put `offset` right after `scale` and end the struct at 32 bytes
+      assert_eq!(PaddedData::wgsl_struct(), format!("// generated from {}\n\
+         struct Padded {{\n    scale: f32,\n    padding0__: f32,\n    offset: f32,\n    color: vec3<f32>,\n\
+         \x20   padding1__: f32,\n    padding2__: f32,\n}}\n",
+         std::any::type_name::<PaddedData>()));
+      // trailing padding WGSL adds by itself isn't declared
+      assert!(!SpotLight::wgsl_struct().contains("padding"));
+      let lights = LightsUniformData::wgsl_struct();
+      assert!(lights.starts_with("#include \"DirectionalLight.wgsl\"\n#include \"PointLight.wgsl\"\n#include \"SpotLight.wgsl\"\n"));
+      assert!(lights.contains("    point_lights: array<PointLight, 4>,\n"));
+      assert_eq!(UniformStruct::of::<PaddedData>().scalars.len(), 5);
+   }
+}
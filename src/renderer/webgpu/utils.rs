@@ -50,6 +50,33 @@ impl Utils {
     })
    }
 
+   pub fn cube_texture_view(texture: &wgpu::Texture, label: Option<&str>) -> wgpu::TextureView {
+      texture.create_view(&wgpu::TextureViewDescriptor {
+        label,
+        format: Default::default(),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        aspect: wgpu::TextureAspect::All,
+        base_mip_level: 0,
+        mip_level_count: None,
+        base_array_layer: 0,
+        array_layer_count: Some(6),
+    })
+   }
+
+   // single mip of a single layer, e.g. to render into a cube face
+   pub fn texture_view_layer(texture: &wgpu::Texture, mip_level: u32, layer: u32, label: Option<&str>) -> wgpu::TextureView {
+      texture.create_view(&wgpu::TextureViewDescriptor {
+        label,
+        format: Default::default(),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        aspect: wgpu::TextureAspect::All,
+        base_mip_level: mip_level,
+        mip_level_count: Some(1),
+        base_array_layer: layer,
+        array_layer_count: Some(1),
+    })
+   }
+
    pub fn make_shader(device: &Device, shader_code: &str, label: &str) -> wgpu::ShaderModule {
       device.create_shader_module(wgpu::ShaderModuleDescriptor {
          label: Some(label),
@@ -89,7 +116,7 @@ impl Utils {
          address_mode_v: wgpu::AddressMode::ClampToEdge,
          address_mode_w: wgpu::AddressMode::ClampToEdge,
          mag_filter: wgpu::FilterMode::Linear,
-         min_filter: wgpu::FilterMode::Nearest,
+         min_filter: wgpu::FilterMode::Linear,
          mipmap_filter: wgpu::FilterMode::Nearest,
          lod_min_clamp: 0.0,
          lod_max_clamp: 32.0,
@@ -116,8 +143,68 @@ impl Utils {
       }
    }
 
-   pub fn default_device_descriptor() -> wgpu::DeviceDescriptor<'static> {
-      Utils::make_device_descriptor(wgpu::Features::PUSH_CONSTANTS)
+   pub fn trilinear_sampler() -> wgpu::SamplerDescriptor<'static> {
+      wgpu::SamplerDescriptor {
+         label: Some("Sampler Trilinear"),
+         address_mode_u: wgpu::AddressMode::ClampToEdge,
+         address_mode_v: wgpu::AddressMode::ClampToEdge,
+         address_mode_w: wgpu::AddressMode::ClampToEdge,
+         mag_filter: wgpu::FilterMode::Linear,
+         min_filter: wgpu::FilterMode::Linear,
+         mipmap_filter: wgpu::FilterMode::Linear,
+         lod_min_clamp: 0.0,
+         lod_max_clamp: 32.0,
+         compare: None,
+         anisotropy_clamp: 1,
+         border_color: None,
+      }
+   }
+
+   // all filters must be linear when anisotropy_clamp > 1
+   pub fn anisotropic_sampler(anisotropy_clamp: u16) -> wgpu::SamplerDescriptor<'static> {
+      wgpu::SamplerDescriptor {
+         label: Some("Sampler Anisotropic"),
+         address_mode_u: wgpu::AddressMode::Repeat,
+         address_mode_v: wgpu::AddressMode::Repeat,
+         address_mode_w: wgpu::AddressMode::Repeat,
+         mag_filter: wgpu::FilterMode::Linear,
+         min_filter: wgpu::FilterMode::Linear,
+         mipmap_filter: wgpu::FilterMode::Linear,
+         lod_min_clamp: 0.0,
+         lod_max_clamp: 32.0,
+         compare: None,
+         anisotropy_clamp,
+         border_color: None,
+      }
+   }
+
+   pub fn shadow_comparison_sampler() -> wgpu::SamplerDescriptor<'static> {
+      wgpu::SamplerDescriptor {
+         label: Some("Sampler Shadow Comparison"),
+         address_mode_u: wgpu::AddressMode::ClampToEdge,
+         address_mode_v: wgpu::AddressMode::ClampToEdge,
+         address_mode_w: wgpu::AddressMode::ClampToEdge,
+         mag_filter: wgpu::FilterMode::Linear,
+         min_filter: wgpu::FilterMode::Linear,
+         mipmap_filter: wgpu::FilterMode::Nearest,
+         lod_min_clamp: 0.0,
+         lod_max_clamp: 32.0,
+         compare: Some(wgpu::CompareFunction::LessEqual),
+         anisotropy_clamp: 1,
+         border_color: None,
+      }
+   }
+
+   pub fn texture_compression_features() -> wgpu::Features {
+      wgpu::Features::TEXTURE_COMPRESSION_BC
+         | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+         | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+   }
+
+   // compressed texture formats are requested whenever the adapter has them
+   pub fn default_device_descriptor(adapter_features: wgpu::Features) -> wgpu::DeviceDescriptor<'static> {
+      Utils::make_device_descriptor(wgpu::Features::PUSH_CONSTANTS
+         | (adapter_features & Utils::texture_compression_features()))
    }
 
    #[allow(unused)]
@@ -28,6 +28,11 @@ impl<'a> Drop for ScopedTimer<'a> {
    }
 }
 
+// milliseconds since the page was opened
+pub fn now_ms() -> f64 {
+   performance().now()
+}
+
 } // mod details
 
 #[cfg(not(feature = "web"))] mod details {
@@ -54,6 +59,12 @@ impl<'a> Drop for ScopedTimer<'a> {
    }
 }
 
+// milliseconds since the first call
+pub fn now_ms() -> f64 {
+   static START: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+   START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
 } // mod details
 
 
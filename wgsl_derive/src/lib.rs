@@ -0,0 +1,165 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitStr};
+
+// Makes a `#[repr(C)]` struct the source of its WGSL declaration:
+//
+//    #[derive(WgslStruct)]
+//    #[wgsl(name = "FractalSettings")]
+//    struct FractalUniformData {
+//       #[wgsl(name = "center")]
+//       fractal_center: [f32; 2],
+//       ...
+//       __padding: [f32; 2],
+//    }
+//
+// Implements `WgslType`, `WgslStruct` and `UniformLayout` of `renderer::webgpu::uniform`.
+// Fields named "__.." are padding, they become `paddingN__` members where WGSL wouldn't leave the gap itself.
+// Field offsets are checked against WGSL alignment at compile time, with the stricter uniform buffer
+// rules unless the struct is marked `#[wgsl(storage)]`
+#[proc_macro_derive(WgslStruct, attributes(wgsl))]
+pub fn derive_wgsl_struct(input: TokenStream) -> TokenStream {
+   let input = parse_macro_input!(input as DeriveInput);
+   expand(&input)
+      .unwrap_or_else(syn::Error::into_compile_error)
+      .into()
+}
+
+#[derive(Default)]
+struct Options {
+   name: Option<LitStr>,
+   storage: bool,
+}
+
+fn parse_options(attrs: &[Attribute], allow_storage: bool) -> syn::Result<Options> {
+   let mut options = Options::default();
+   for attr in attrs.iter().filter(|attr| attr.path().is_ident("wgsl")) {
+      attr.parse_nested_meta(|meta| {
+         if meta.path.is_ident("name") {
+            options.name = Some(meta.value()?.parse()?);
+            Ok(())
+         } else if allow_storage && meta.path.is_ident("storage") {
+            options.storage = true;
+            Ok(())
+         } else {
+            Err(meta.error("unknown wgsl option"))
+         }
+      })?;
+   }
+   Ok(options)
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> bool {
+   attrs.iter()
+      .filter(|attr| attr.path().is_ident("repr"))
+      .any(|attr| {
+         let mut repr_c = false;
+         // e.g. #[repr(C, align(16))], other hints don't matter here
+         let _ = attr.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C");
+            if meta.input.peek(syn::token::Paren) {
+               let _arguments;
+               syn::parenthesized!(_arguments in meta.input);
+            }
+            Ok(())
+         });
+         repr_c
+      })
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+   let ty = &input.ident;
+   if !input.generics.params.is_empty() {
+      return Err(syn::Error::new_spanned(&input.generics, "WgslStruct can't be generic"));
+   }
+   if !is_repr_c(&input.attrs) {
+      return Err(syn::Error::new_spanned(ty, "WgslStruct needs #[repr(C)], the layout has to be fixed"));
+   }
+   let Data::Struct(data) = &input.data else {
+      return Err(syn::Error::new_spanned(ty, "WgslStruct can only be derived for structs"));
+   };
+   let Fields::Named(fields) = &data.fields else {
+      return Err(syn::Error::new_spanned(ty, "WgslStruct needs named fields"));
+   };
+   let options = parse_options(&input.attrs, true)?;
+   let wgsl_name = options.name.map_or_else(|| ty.to_string(), |name| name.value());
+   let uniform = quote!(crate::renderer::webgpu::uniform);
+
+   let (mut checks, mut members, mut scalars, mut aligns, mut includes) = (vec![], vec![], vec![], vec![], vec![]);
+   for field in &fields.named {
+      let ident = field.ident.as_ref().unwrap();
+      let field_ty = &field.ty;
+      let field_options = parse_options(&field.attrs, false)?;
+      if ident.to_string().starts_with("__") {
+         continue;
+      }
+      let member_name = field_options.name.map_or_else(|| ident.to_string(), |name| name.value());
+      let offset = quote!(std::mem::offset_of!(#ty, #ident));
+      checks.push(quote! {
+         assert!(#offset % <#field_ty as #uniform::WgslType>::ALIGN == 0,
+            concat!(stringify!(#ty), ".", stringify!(#ident), " isn't aligned for its WGSL type, add padding before it"));
+      });
+      if !options.storage {
+         checks.push(quote! {
+            assert!(#offset % <#field_ty as #uniform::WgslType>::UNIFORM_ALIGN == 0,
+               concat!(stringify!(#ty), ".", stringify!(#ident), " has to be aligned to 16 bytes in a uniform buffer, add padding before it"));
+         });
+      }
+      members.push(quote! {
+         #uniform::WgslMember {
+            name: #member_name,
+            offset: #offset,
+            align: <#field_ty as #uniform::WgslType>::ALIGN,
+            size: <#field_ty as #uniform::WgslType>::SIZE,
+            ty: <#field_ty as #uniform::WgslType>::wgsl_type(),
+         }
+      });
+      scalars.push(quote! {
+         <#field_ty as #uniform::UniformLayout>::scalars(&#uniform::member_path(path, stringify!(#ident)), offset + #offset, out);
+      });
+      aligns.push(quote!(<#field_ty as #uniform::WgslType>::ALIGN));
+      includes.push(quote!(<#field_ty as #uniform::WgslType>::wgsl_includes(&mut includes);));
+   }
+   if members.is_empty() {
+      return Err(syn::Error::new_spanned(ty, "WGSL structs need at least one member besides padding"));
+   }
+
+   Ok(quote! {
+      const _: () = {
+         #(#checks)*
+         assert!(std::mem::size_of::<#ty>() % <#ty as #uniform::WgslType>::ALIGN == 0,
+            concat!(stringify!(#ty), " isn't a multiple of its WGSL alignment, add padding at the end"));
+      };
+
+      impl #uniform::WgslType for #ty {
+         const ALIGN: usize = #uniform::max_align(&[#(#aligns),*]);
+         const SIZE: usize = std::mem::size_of::<#ty>();
+         const UNIFORM_ALIGN: usize = #uniform::uniform_struct_align(Self::ALIGN);
+
+         fn wgsl_type() -> String {
+            #wgsl_name.to_owned()
+         }
+
+         fn wgsl_includes(out: &mut Vec<String>) {
+            #uniform::push_include::<Self>(out);
+         }
+      }
+
+      impl #uniform::WgslStruct for #ty {
+         const WGSL_NAME: &'static str = #wgsl_name;
+
+         fn wgsl_struct() -> String {
+            let mut includes = vec![];
+            #(#includes)*
+            #uniform::wgsl_struct_declaration::<Self>(&includes, &[#(#members),*])
+         }
+      }
+
+      impl #uniform::UniformLayout for #ty {
+         fn scalars(path: &str, offset: usize, out: &mut Vec<#uniform::UniformScalar>) {
+            #(#scalars)*
+         }
+      }
+   })
+}
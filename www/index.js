@@ -925,9 +925,16 @@ window.onload = function() {
       // Canvas is created and owned by WasmInterface
       demo_loading_apply_progress(0.1);
       const canvas_id = "main-canvas";
+      // with "?live_shaders" shaders are fetched from ./shaders, see `make www_shaders`,
+      // and `reloadShaders()` in the console applies edits
+      const liveShaders = new URLSearchParams(window.location.search).has("live_shaders");
       WASM_INSTANCE = await new WasmInterface(canvas_id,
          document.getElementById("canvas-wrapper"),
-         CURRENT_GRAPHICS_LEVEL.val);
+         CURRENT_GRAPHICS_LEVEL.val,
+         liveShaders ? "./" : undefined);
+      if (liveShaders) {
+         window.reloadShaders = () => WASM_INSTANCE.reloadShaders();
+      }
       demo_loading_apply_progress(0.5);
       const configureCanvas = getCanvasConvigurationFunc(canvas_id);
       configureResizingBorder(configureCanvas);